@@ -7,11 +7,11 @@ welcome =
     
     If you use me as an inline bot, I'll mirror content from many furry sites. When I post the image I'll include a direct link and a source link, if available. You can add your Twitter account with /twitter to get content from locked accounts you follow.
     
-    If you send me an image, I'll try to find the source on FA.
+    If you send me an image, GIF, video, or sticker, I'll try to find the source on FA.
     
     Add me to your group for features like /mirror (where I mirror all the links in a message, including messages you reply to) or /source (where I attempt to find the source of an image you're replying to).
     
-    If I'm given edit permissions in your channel, I'll automatically edit posts to include a source link.
+    If I'm given edit permissions in your channel, I'll automatically edit posts to include a source link. Send /channel to change how this works.
     
     Contact my creator { -creatorName } if you have any issues or feature suggestions.
 
@@ -22,7 +22,7 @@ welcome-group =
     
     I've also got a few commands to help in groups:
     · /mirror - I'll look at all the links in your message or the message you're replying to and mirror them
-    · /source - I'll attempt to find if the photo you're replying to has been posted on FurAffinity
+    · /source - I'll attempt to find if the photo you're replying to has been posted on FurAffinity, or send /source 10 to source the last 10 photos in groups with automatic sourcing enabled
     
     You can also enable automatically finding sources for images posted in here with the /groupsource command. However, I must be an administrator in the group for this to work and it can only be enabled by an administrator.
 
@@ -31,11 +31,58 @@ welcome-try-me = Try Me!
 # Inline Keyboard
 inline-direct = Direct Link
 inline-source = Source
+inline-artist = By { $artist }
 
 # No Inline Results
 inline-no-results-title = No results found
 inline-no-results-body = I could not find any results for the provided query.
 
+# Failed Links
+inline-failed-title = Some links could not be loaded
+link-failure = · { $link } ({ $reason })
+link-failure-site = · { $link } ({ $site }: { $reason })
+link-reason-no-media = no images found
+link-reason-not-found = post was not found
+link-reason-auth-failed = not allowed to view this post
+link-reason-account-expired = your linked account expired, link it again from /settings
+link-reason-rate-limited = too many requests, try again later
+link-reason-blocked = the site blocked the request
+link-reason-timeout = took too long to respond
+link-reason-unavailable = site is having issues, try again later
+link-reason-error = something went wrong
+
+# Content Filters
+inline-hidden-title = Some results were hidden by your filters
+filter-hidden = · { $link } ({ $reason })
+filter-reason-rating = hidden due to rating
+filter-reason-blocklist = has a blocked tag
+filter-reason-unknown-rating = rating is unknown
+rating-safe = Safe
+rating-questionable = Questionable
+rating-explicit = Explicit
+group-rating-usage = Choose the most explicit rating to allow in this group with /grouprating safe, /grouprating questionable, or /grouprating explicit.
+group-rating-updated = Results in this group are now limited to { $rating }.
+
+# Match Strictness
+strictness-strict = Strict
+strictness-normal = Normal
+strictness-loose = Loose
+group-strictness-usage = Choose how closely images must match before I share a source in this group with /groupstrictness strict, /groupstrictness normal, or /groupstrictness loose.
+group-strictness-updated = Image matching in this group is now { $strictness }.
+
+# Blocklists
+blocklist-usage =
+    Use /blocklist add followed by tags to hide posts with all of those tags, /blocklist remove to remove a rule, or /blocklist clear to remove every rule.
+    
+    Rules use the same syntax as e621's blacklist. Tags starting with - must not be present, and at least one tag starting with ~ must be present.
+blocklist-empty = There are no blocked tags.
+blocklist-current = Currently blocked tags:
+blocklist-item = · { $rule }
+blocklist-added = Added rule: { $rule }
+blocklist-removed = Removed rule: { $rule }
+blocklist-not-found = There is no rule for { $rule }.
+blocklist-cleared = Removed every rule.
+
 # Inline Videos
 video-starting = Starting to process video...
 video-too-large = Video was too large, aborting.
@@ -49,8 +96,23 @@ reverse-bad-result = I found this but it may not be the same image, be warned (d
 
 # Twitter Onboarding
 twitter-oob = Please follow the link and enter the 6 digit code returned: { $link }
+twitter-callback = Please follow the link to sign in with Twitter, I'll let you know when it's done: { $link }
+twitter-callback-expired = This sign in link has expired, please send /twitter to try again.
+twitter-callback-denied = You cancelled signing in to Twitter, send /twitter if you'd like to try again.
+twitter-callback-error = Something went wrong signing in to Twitter, please send /twitter to try again.
 twitter-welcome = Welcome aboard, { $userName }!
 twitter-private = Let's do this in a private chat.
+twitter-unavailable = Sorry, Twitter isn't available right now.
+
+# Linked Accounts
+link-prompt-furaffinity =
+    Reply to this message with the values of your FurAffinity a and b cookies, separated by a space.
+link-prompt-inkbunny =
+    Reply to this message with your Inkbunny session ID, the value of your PHPSESSID cookie. I never need your password.
+link-private = Let's do this in a private chat.
+link-unavailable = Sorry, linking accounts isn't available right now.
+link-success = Linked your { $site } account! I deleted your message so your details stay private.
+link-invalid = Sorry, that doesn't look right for { $site }. I deleted your message so your details stay private. Try again from /settings.
 
 # In-group mirroring
 mirror-no-links = Sorry, I could not find any links to mirror images from.
@@ -61,28 +123,64 @@ mirror-missing =
     { $links }
     
     Sorry about that.
+mirror-failed =
+    Sorry, I could not fetch images from the following links:
+    
+    { $links }
+mirror-caption = { $link } by { $artist }
+mirror-process-video = Some videos need to be converted before I can send them.
+mirror-process-video-button = Convert video
 
 # In-group sourcing
 source-no-photo = Sorry, I can't seem to find the photo here.
+source-hidden = I found a source, but it was hidden by your filters.
+source-recent-title = Here's what I found for the last { $count } photos, newest first:
+source-recent-result = { $index }. { $link } (distance of { $distance })
+source-recent-unsure = { $index }. { $link } (distance of { $distance }, may not be the same image)
+source-recent-hidden = { $index }. hidden by filters (distance of { $distance })
+source-recent-none = { $index }. no sources found
+source-recent-empty = I haven't seen any photos here recently. I only remember photos in groups with automatic sourcing enabled.
 
 # In-group alternates
 alternate-title = Here are some possible alternate versions:
 alternate-posted-by = Posted by { $name }
 alternate-distance = · { $link } (distance of { $distance })
 alternate-multiple-photo = I can only find alternates for a single photo, sorry.
+alternate-hidden = I found some alternate versions, but they were hidden by your filters.
 
 # Automatic group sourcing
 automatic-single = It looks like this image may have come from here: { $link }
 automatic-multiple = I found a few places this image may have come from:
 automatic-multiple-result = · { $link } (distance of { $distance })
+automatic-single-hidden = It looks like this image may have come from a post that was hidden by this group's filters.
+automatic-multiple-hidden = · hidden by filters (distance of { $distance })
+automatic-album = I found a few places these images may have come from:
+automatic-album-image = Image { $index }:
+automatic-album-caption = { $index }: { $link }
 automatic-enable-not-admin = Sorry, you must be a group admin to enable this.
 automatic-enable-bot-not-admin = Sorry, you must give me admin permissions due to a Telegram limitation.
-automatic-enable-success = Automatic group sourcing is now enabled!
+automatic-enable-success = Automatic group sourcing is now enabled! I'll also remember the last 25 photos here so they can be sourced with /source 10.
 automatic-disable = This feature is now turned off.
 automatic-enable-not-group = This feature is only supported in groups.
 automatic-preview-disable = Sourced image previews disabled.
 automatic-preview-enable = Sourced image previews enabled.
 
+# Channel Settings
+channel-usage = To change how I add sources in your channel, forward me a post from it and reply to that post with /channel. You can also send /channel followed by the channel's ID.
+channel-private = Let's do this in a private chat.
+channel-not-admin = Sorry, I couldn't check that channel. Make sure you and I are both admins there.
+channel-settings =
+    Here's how I add sources in { $channel }. Tap a setting to change it.
+    
+    Sources are preferred from the sites you choose, in the order you chose them.
+channel-enabled = Add sources
+channel-mode-button = Mode: button
+channel-mode-caption = Mode: caption
+channel-caption-replace = Caption: replace
+channel-caption-append = Caption: append
+channel-artist = Include artist
+channel-updated = Updated channel settings
+
 # Error Messages
 error-generic = Oh no, something went wrong! Please send a message to my creator, { -creatorName }, saying what happened.
 error-uuid = Oh no, something went wrong! Please reply to this message saying what happened. You may also send a message to my creator, { -creatorName }, with this ID if you continue having issues: { $uuid }
@@ -103,3 +201,22 @@ settings-move-unable = Unable to move { $name } to that position
 settings-move-updated = Updated position for { $name }
 settings-site-preference = Site Preference
 settings-source-name = Source Name
+settings-content-rating = Content Rating
+settings-rating = Choose the most explicit results I should show you. Anything above this will be hidden.
+settings-rating-updated = Updated allowed rating
+settings-match-strictness = Match Strictness
+settings-strictness = Choose how closely images must match before I show a source. Stricter settings show fewer results, but they're more likely to be right.
+settings-strictness-updated = Updated match strictness
+settings-blocked-tags = Blocked Tags
+settings-blocklist =
+    Posts with these tags are hidden. Tap a rule to remove it.
+    
+    Add rules with /blocklist add, using the same syntax as e621's blacklist.
+settings-blocklist-removed = Removed rule
+settings-linked-accounts = Linked Accounts
+settings-accounts =
+    These are the accounts you've linked. Tap an account to unlink it, or tap a site below to link it.
+    
+    Send /twitter to sign in with Twitter.
+settings-accounts-removed = Unlinked { $site }
+settings-accounts-reauth = Sign in again
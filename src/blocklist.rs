@@ -0,0 +1,184 @@
+use std::collections::HashSet;
+
+use crate::sites::Rating;
+
+/// A list of tag rules, using the same syntax as e621's blacklist.
+///
+/// Each line is a rule. A post is blocked by a rule if it has every plain
+/// tag, none of the tags starting with `-`, and at least one of the tags
+/// starting with `~`. Ratings can be matched with `rating:s`, `rating:q`, or
+/// `rating:e`.
+#[derive(Clone, Debug, Default)]
+pub struct Blocklist {
+    rules: Vec<Rule>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Rule {
+    required: Vec<Tag>,
+    excluded: Vec<Tag>,
+    any: Vec<Tag>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tag {
+    Name(String),
+    Rating(Rating),
+}
+
+/// Tags are compared without case, and spaces are the same as underscores.
+fn normalize(tag: &str) -> String {
+    tag.trim().to_lowercase().replace(' ', "_")
+}
+
+impl Tag {
+    fn parse(tag: &str) -> Self {
+        let tag = normalize(tag);
+
+        if tag.starts_with("rating:") {
+            let rating = match &tag["rating:".len()..] {
+                "s" | "safe" => Some(Rating::Safe),
+                "q" | "questionable" => Some(Rating::Questionable),
+                "e" | "explicit" => Some(Rating::Explicit),
+                _ => None,
+            };
+
+            if let Some(rating) = rating {
+                return Tag::Rating(rating);
+            }
+        }
+
+        Tag::Name(tag)
+    }
+
+    fn matches(&self, tags: &HashSet<String>, rating: Option<Rating>) -> bool {
+        match self {
+            Tag::Name(name) => tags.contains(name),
+            Tag::Rating(wanted) => rating == Some(*wanted),
+        }
+    }
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Self> {
+        let mut rule = Rule::default();
+
+        for tag in line.split_whitespace() {
+            if tag.starts_with('-') {
+                rule.excluded.push(Tag::parse(&tag[1..]));
+            } else if tag.starts_with('~') {
+                rule.any.push(Tag::parse(&tag[1..]));
+            } else {
+                rule.required.push(Tag::parse(tag));
+            }
+        }
+
+        // A rule with only excluded tags would block nearly everything,
+        // which is never what someone wants.
+        if rule.required.is_empty() && rule.any.is_empty() {
+            return None;
+        }
+
+        Some(rule)
+    }
+
+    fn matches(&self, tags: &HashSet<String>, rating: Option<Rating>) -> bool {
+        self.required.iter().all(|tag| tag.matches(tags, rating))
+            && !self.excluded.iter().any(|tag| tag.matches(tags, rating))
+            && (self.any.is_empty() || self.any.iter().any(|tag| tag.matches(tags, rating)))
+    }
+}
+
+impl Blocklist {
+    /// Parse a blocklist from lines of rules. Invalid lines are ignored.
+    pub fn parse<'a, I>(lines: I) -> Self
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        Self {
+            rules: lines.into_iter().filter_map(Rule::parse).collect(),
+        }
+    }
+
+    /// Check if a line would be a usable rule.
+    pub fn is_valid_rule(line: &str) -> bool {
+        Rule::parse(line).is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Add all of the rules from another blocklist.
+    pub fn extend(&mut self, other: Blocklist) {
+        self.rules.extend(other.rules);
+    }
+
+    /// Check if a post with the given tags and rating is blocked.
+    pub fn blocks<S: AsRef<str>>(&self, tags: &[S], rating: Option<Rating>) -> bool {
+        if self.rules.is_empty() {
+            return false;
+        }
+
+        let tags: HashSet<String> = tags.iter().map(|tag| normalize(tag.as_ref())).collect();
+
+        self.rules.iter().any(|rule| rule.matches(&tags, rating))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Blocklist;
+    use crate::sites::Rating;
+
+    #[test]
+    fn test_blocks() {
+        let blocklist = Blocklist::parse(vec![
+            "gore",
+            "feral rating:e",
+            "young -rating:s",
+            "~scat ~watersports",
+            "-solo",
+            "",
+        ]);
+
+        let tests: Vec<(Vec<&str>, Option<Rating>, bool)> = vec![
+            (vec![], None, false),
+            (vec!["fox", "solo"], Some(Rating::Explicit), false),
+            (vec!["fox", "Gore"], None, true),
+            (vec!["feral"], Some(Rating::Safe), false),
+            (vec!["feral"], Some(Rating::Explicit), true),
+            (vec!["feral"], None, false),
+            (vec!["young"], Some(Rating::Safe), false),
+            (vec!["young"], Some(Rating::Questionable), true),
+            (vec!["watersports"], None, true),
+            (vec!["fox", "duo"], None, false),
+        ];
+
+        for (tags, rating, blocked) in tests {
+            assert_eq!(
+                blocklist.blocks(&tags, rating),
+                blocked,
+                "wrong result for {:?} {:?}",
+                tags,
+                rating
+            );
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        let blocklist = Blocklist::parse(vec!["red_fox"]);
+
+        assert!(blocklist.blocks(&["Red Fox"], None));
+        assert!(blocklist.blocks(&["red_fox"], None));
+    }
+
+    #[test]
+    fn test_is_valid_rule() {
+        assert!(Blocklist::is_valid_rule("gore"));
+        assert!(Blocklist::is_valid_rule("~scat -solo"));
+        assert!(!Blocklist::is_valid_rule("-solo"));
+        assert!(!Blocklist::is_valid_rule("   "));
+    }
+}
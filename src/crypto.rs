@@ -0,0 +1,220 @@
+use aes_gcm::aead::{generic_array::GenericArray, Aead, NewAead};
+use aes_gcm::Aes256Gcm;
+
+/// Length of the random nonce stored before each ciphertext.
+const NONCE_LEN: usize = 12;
+
+/// Columns holding secrets, by table. Each of these tables has an
+/// `encrypted` column marking rows that have been encrypted.
+const SECRET_COLUMNS: &[(&str, &[&str])] = &[
+    ("twitter_account", &["consumer_key", "consumer_secret"]),
+    ("site_account", &["secret"]),
+];
+
+/// Key used to encrypt secrets before they are stored in the database.
+///
+/// Secrets are encrypted with AES-256-GCM and stored as base64, with a new
+/// random nonce prepended to each value.
+pub struct SecretKey {
+    cipher: Aes256Gcm,
+}
+
+impl SecretKey {
+    /// Load a key from 32 bytes encoded as base64.
+    pub fn from_base64(key: &str) -> failure::Fallible<Self> {
+        let key = base64::decode(key.trim())?;
+        if key.len() != 32 {
+            failure::bail!("secret key must be 32 bytes, got {}", key.len());
+        }
+
+        Ok(Self {
+            cipher: Aes256Gcm::new(GenericArray::from_slice(&key)),
+        })
+    }
+
+    pub fn encrypt(&self, secret: &str) -> failure::Fallible<String> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+
+        let ciphertext = self
+            .cipher
+            .encrypt(GenericArray::from_slice(&nonce), secret.as_bytes())
+            .map_err(|_err| failure::format_err!("unable to encrypt secret"))?;
+
+        let mut data = nonce.to_vec();
+        data.extend(ciphertext);
+
+        Ok(base64::encode(&data))
+    }
+
+    pub fn decrypt(&self, data: &str) -> failure::Fallible<String> {
+        let data = base64::decode(data)?;
+        if data.len() < NONCE_LEN {
+            failure::bail!("encrypted secret was too short");
+        }
+
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let secret = self
+            .cipher
+            .decrypt(GenericArray::from_slice(nonce), ciphertext)
+            .map_err(|_err| failure::format_err!("unable to decrypt secret"))?;
+
+        Ok(String::from_utf8(secret)?)
+    }
+}
+
+/// Encrypt any secrets that were stored before encryption was added,
+/// returning how many rows were updated.
+pub fn encrypt_stored(
+    conn: &mut rusqlite::Connection,
+    key: &SecretKey,
+) -> failure::Fallible<usize> {
+    update_secrets(conn, true, |secret| key.encrypt(secret))
+}
+
+/// Re-encrypt every stored secret with a new key, returning how many rows
+/// were updated. Nothing is changed if any secret can't be decrypted.
+pub fn rotate(
+    conn: &mut rusqlite::Connection,
+    old: &SecretKey,
+    new: &SecretKey,
+) -> failure::Fallible<usize> {
+    update_secrets(conn, false, |secret| new.encrypt(&old.decrypt(secret)?))
+}
+
+/// Replace the secrets in every table within a single transaction, only
+/// looking at rows that are not yet encrypted if `plain_only` is set.
+fn update_secrets<F>(
+    conn: &mut rusqlite::Connection,
+    plain_only: bool,
+    update: F,
+) -> failure::Fallible<usize>
+where
+    F: Fn(&str) -> failure::Fallible<String>,
+{
+    use rusqlite::types::Value;
+
+    let tx = conn.transaction()?;
+    let mut count = 0;
+
+    for (table, columns) in SECRET_COLUMNS {
+        let select = format!(
+            "SELECT id, {} FROM {}{}",
+            columns.join(", "),
+            table,
+            if plain_only {
+                " WHERE encrypted = 0"
+            } else {
+                ""
+            }
+        );
+
+        let rows = {
+            let mut stmt = tx.prepare(&select)?;
+            let rows = stmt.query_map(rusqlite::NO_PARAMS, |row| {
+                let values = (1..=columns.len())
+                    .map(|idx| row.get(idx))
+                    .collect::<Result<Vec<String>, _>>()?;
+
+                Ok((row.get::<_, i64>(0)?, values))
+            })?;
+
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+
+        let sets: Vec<String> = columns
+            .iter()
+            .enumerate()
+            .map(|(idx, column)| format!("{} = ?{}", column, idx + 1))
+            .collect();
+        let update_query = format!(
+            "UPDATE {} SET {}, encrypted = 1 WHERE id = ?{}",
+            table,
+            sets.join(", "),
+            columns.len() + 1
+        );
+
+        for (id, values) in rows {
+            let mut params = values
+                .iter()
+                .map(|value| update(value).map(Value::Text))
+                .collect::<failure::Fallible<Vec<_>>>()?;
+            params.push(Value::Integer(id));
+
+            tx.execute(&update_query, params)?;
+            count += 1;
+        }
+    }
+
+    tx.commit()?;
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SecretKey;
+
+    fn key(byte: u8) -> SecretKey {
+        SecretKey::from_base64(&base64::encode(&[byte; 32])).unwrap()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let key = key(1);
+
+        let first = key.encrypt("a secret").unwrap();
+        let second = key.encrypt("a secret").unwrap();
+        assert_ne!(first, second, "nonce should be random");
+
+        assert_eq!(key.decrypt(&first).unwrap(), "a secret");
+        assert_eq!(key.decrypt(&second).unwrap(), "a secret");
+    }
+
+    #[test]
+    fn test_invalid() {
+        let encrypted = key(1).encrypt("a secret").unwrap();
+
+        assert!(key(2).decrypt(&encrypted).is_err());
+        assert!(key(1).decrypt("dGVzdA==").is_err());
+        assert!(SecretKey::from_base64("dGVzdA==").is_err());
+    }
+
+    #[test]
+    fn test_stored_secrets() {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::migrations::runner().run(&mut conn).unwrap();
+
+        conn.execute(
+            "INSERT INTO twitter_account (user_id, consumer_key, consumer_secret) VALUES (1, 'key', 'secret')",
+            rusqlite::NO_PARAMS,
+        )
+        .unwrap();
+
+        let stored = |conn: &rusqlite::Connection| -> (String, String) {
+            conn.query_row(
+                "SELECT consumer_key, consumer_secret FROM twitter_account",
+                rusqlite::NO_PARAMS,
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap()
+        };
+
+        let old = key(1);
+        assert_eq!(super::encrypt_stored(&mut conn, &old).unwrap(), 1);
+        assert_eq!(super::encrypt_stored(&mut conn, &old).unwrap(), 0);
+
+        let (consumer_key, consumer_secret) = stored(&conn);
+        assert_ne!(consumer_key, "key");
+        assert_eq!(old.decrypt(&consumer_key).unwrap(), "key");
+        assert_eq!(old.decrypt(&consumer_secret).unwrap(), "secret");
+
+        let new = key(2);
+        assert!(super::rotate(&mut conn, &new, &old).is_err());
+        assert_eq!(super::rotate(&mut conn, &old, &new).unwrap(), 1);
+
+        let (consumer_key, consumer_secret) = stored(&conn);
+        assert!(old.decrypt(&consumer_key).is_err());
+        assert_eq!(new.decrypt(&consumer_key).unwrap(), "key");
+        assert_eq!(new.decrypt(&consumer_secret).unwrap(), "secret");
+    }
+}
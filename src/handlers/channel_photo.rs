@@ -1,13 +1,91 @@
 use async_trait::async_trait;
 use failure::ResultExt;
+use quaint::pooled::PooledConnection;
 use tgbotapi::{requests::*, *};
 
 use super::Status::*;
+use crate::models::{GroupConfig, GroupConfigKey};
 use crate::needs_field;
-use crate::utils::{find_best_photo, get_message, match_image};
+use crate::utils::{find_best_photo, get_message, match_image, MatchPolicy};
 
 pub struct ChannelPhotoHandler;
 
+/// How a channel's posts get sources, managed by the channel's admins from
+/// a private chat with the bot.
+///
+/// The defaults match how channels were handled before they could be
+/// configured.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ChannelSettings {
+    /// If sources should be added to the channel's posts.
+    pub enabled: bool,
+    /// If sources should be added to the caption instead of as a button.
+    /// Media groups always use the caption, as they can't have buttons.
+    pub caption: bool,
+    /// If sources should be added after an existing caption instead of
+    /// replacing it.
+    pub append: bool,
+    /// Names of sites to prefer when picking a source, in order.
+    pub sites: Vec<String>,
+    /// If the artist's name should be included with the source.
+    pub artist: bool,
+}
+
+impl Default for ChannelSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            caption: false,
+            append: false,
+            sites: vec![],
+            artist: false,
+        }
+    }
+}
+
+impl ChannelSettings {
+    /// Sites FuzzySearch returns matches from, the only sites worth
+    /// preferring.
+    pub const SITES: &'static [&'static str] = &["FurAffinity", "e621", "Twitter", "Weasyl"];
+
+    pub async fn load(conn: &PooledConnection, chat_id: i64) -> failure::Fallible<Self> {
+        let settings = GroupConfig::get(conn, chat_id, GroupConfigKey::ChannelSettings)
+            .await
+            .context("unable to query channel settings")?;
+
+        Ok(settings.unwrap_or_default())
+    }
+
+    pub async fn save(&self, conn: &PooledConnection, chat_id: i64) -> failure::Fallible<()> {
+        let existing: Option<Self> =
+            GroupConfig::get(conn, chat_id, GroupConfigKey::ChannelSettings)
+                .await
+                .context("unable to query channel settings")?;
+
+        GroupConfig::set(
+            conn,
+            GroupConfigKey::ChannelSettings,
+            chat_id,
+            existing.is_some(),
+            self,
+        )
+        .await
+        .context("unable to save channel settings")?;
+
+        Ok(())
+    }
+
+    /// Position of a site in the preferred sites, with every other site
+    /// after them.
+    pub fn site_position(&self, name: &str) -> usize {
+        self.sites
+            .iter()
+            .position(|site| site == name)
+            .unwrap_or_else(|| self.sites.len())
+    }
+}
+
 #[async_trait]
 impl super::Handler for ChannelPhotoHandler {
     fn name(&self) -> &'static str {
@@ -22,7 +100,9 @@ impl super::Handler for ChannelPhotoHandler {
     ) -> failure::Fallible<super::Status> {
         // Ensure we have a channel_post Message and a photo within.
         let message = needs_field!(update, channel_post);
-        let sizes = needs_field!(&message, photo);
+        if message.photo.is_none() {
+            return Ok(Ignored);
+        }
 
         // We only want messages from channels. I think this is always true
         // because this came from a channel_post.
@@ -35,32 +115,123 @@ impl super::Handler for ChannelPhotoHandler {
             return Ok(Completed);
         }
 
-        let matches = get_matches(&handler.bot, &handler.fapi, &handler.conn, &sizes)
-            .await
-            .context("unable to get matches")?;
+        let settings = {
+            let conn = handler
+                .conn
+                .check_out()
+                .await
+                .context("unable to check out database")?;
 
-        let first = match matches {
-            Some(first) => first,
-            _ => return Ok(Completed),
+            ChannelSettings::load(&conn, message.chat.id).await?
         };
 
-        // Ignore unlikely matches
-        if first.distance.unwrap() > 3 {
+        // Channels can opt out of having sources added.
+        if !settings.enabled {
             return Ok(Completed);
         }
 
-        // If this link was already in the message, we can ignore it.
-        if link_was_seen(&extract_links(&message, &handler.finder), &first.url) {
+        // Media groups are handled together from their first message.
+        let messages = match handler.media_groups.collect(&message).await {
+            Some(messages) => messages,
+            None => return Ok(Completed),
+        };
+
+        let policy = MatchPolicy::load(&handler.conn, None, Some(message.chat.id)).await?;
+
+        // Find a likely source for each photo that doesn't already link it.
+        let mut sources = Vec::with_capacity(messages.len());
+        for (idx, message) in messages.iter().enumerate() {
+            let sizes = match &message.photo {
+                Some(sizes) => sizes,
+                None => continue,
+            };
+
+            // One photo failing shouldn't prevent sourcing the rest.
+            let mut matches =
+                match get_matches(&handler.bot, &handler.fapi, &handler.conn, &sizes).await {
+                    Ok(matches) => matches,
+                    Err(err) => {
+                        tracing::warn!(
+                            message_id = message.message_id,
+                            "unable to get matches: {:?}",
+                            err
+                        );
+                        continue;
+                    }
+                };
+
+            // Ignore unlikely matches, then use the channel's preferred
+            // sites before the closest match.
+            matches.retain(|m| policy.is_automatic(m));
+            matches.sort_by_key(|m| settings.site_position(m.site_name()));
+
+            let first = match matches.into_iter().next() {
+                Some(first) => first,
+                _ => continue,
+            };
+
+            // If this link was already in the message, we can ignore it.
+            if link_was_seen(&extract_links(&message, &handler.finder), &first.url) {
+                continue;
+            }
+
+            sources.push((idx + 1, first));
+        }
+
+        if sources.is_empty() {
             return Ok(Completed);
         }
 
-        // If this photo was part of a media group, we should set a caption on
-        // the image because we can't make an inline keyboard on it.
-        if message.media_group_id.is_some() {
+        // Media groups can't have an inline keyboard, so their sources
+        // always go in the caption of the first image.
+        if message.media_group_id.is_some() || settings.caption {
+            let first_message = messages.first().unwrap();
+
+            let caption = handler
+                .get_fluent_bundle(None, |bundle| {
+                    if messages.len() == 1 {
+                        return vec![source_text(&bundle, &sources[0].1, settings.artist)];
+                    }
+
+                    sources
+                        .iter()
+                        .map(|(idx, file)| {
+                            let mut args = fluent::FluentArgs::new();
+                            args.insert("index", (*idx).into());
+                            args.insert("link", source_text(&bundle, file, settings.artist).into());
+
+                            get_message(&bundle, "automatic-album-caption", Some(args)).unwrap()
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .await;
+
+            let caption = match join_sources(caption) {
+                Some(caption) => caption,
+                None => {
+                    tracing::warn!("source was too long for a caption");
+                    return Ok(Completed);
+                }
+            };
+
+            let caption = match &first_message.caption {
+                Some(existing) if settings.append => {
+                    let appended = format!("{}\n\n{}", existing, caption);
+
+                    // The sources replace the caption if they wouldn't fit.
+                    if appended.chars().count() <= CAPTION_LIMIT {
+                        appended
+                    } else {
+                        caption
+                    }
+                }
+                _ => caption,
+            };
+
             let edit_caption_markup = EditMessageCaption {
                 chat_id: message.chat_id(),
-                message_id: Some(message.message_id),
-                caption: Some(first.url()),
+                message_id: Some(first_message.message_id),
+                caption: Some(caption),
                 ..Default::default()
             };
 
@@ -68,11 +239,19 @@ impl super::Handler for ChannelPhotoHandler {
                 .make_request(&edit_caption_markup)
                 .await
                 .context("unable to edit channel caption markup")?;
-        // Not a media group, we should create an inline keyboard.
+        // Not a media group, so we can create an inline keyboard.
         } else {
+            let (_idx, first) = sources.remove(0);
+
             let text = handler
-                .get_fluent_bundle(None, |bundle| {
-                    get_message(&bundle, "inline-source", None).unwrap()
+                .get_fluent_bundle(None, |bundle| match first.artists.as_ref() {
+                    Some(artists) if settings.artist && !artists.is_empty() => {
+                        let mut args = fluent::FluentArgs::new();
+                        args.insert("artist", artists.join(", ").into());
+
+                        get_message(&bundle, "inline-artist", Some(args)).unwrap()
+                    }
+                    _ => get_message(&bundle, "inline-source", None).unwrap(),
                 })
                 .await;
 
@@ -101,6 +280,56 @@ impl super::Handler for ChannelPhotoHandler {
     }
 }
 
+/// Most characters Telegram allows in a caption.
+const CAPTION_LIMIT: usize = 1024;
+
+/// Join sources into a caption, one per line, dropping any sources after
+/// the caption would become too long.
+///
+/// Returns None if not even the first source fits.
+fn join_sources(sources: Vec<String>) -> Option<String> {
+    let mut caption = String::new();
+    let mut len = 0;
+
+    for source in sources {
+        let separator = if caption.is_empty() { 0 } else { 1 };
+        let source_len = source.chars().count() + separator;
+        if len + source_len > CAPTION_LIMIT {
+            break;
+        }
+
+        if separator > 0 {
+            caption.push('\n');
+        }
+        caption.push_str(&source);
+        len += source_len;
+    }
+
+    if caption.is_empty() {
+        None
+    } else {
+        Some(caption)
+    }
+}
+
+/// Get the text for a source, including the artist if wanted and known.
+fn source_text(
+    bundle: &fluent::FluentBundle<fluent::FluentResource>,
+    file: &fuzzysearch::File,
+    artist: bool,
+) -> String {
+    let artists = match file.artists.as_ref() {
+        Some(artists) if artist && !artists.is_empty() => artists,
+        _ => return file.url(),
+    };
+
+    let mut args = fluent::FluentArgs::new();
+    args.insert("link", file.url().into());
+    args.insert("artist", artists.join(", ").into());
+
+    get_message(&bundle, "mirror-caption", Some(args)).unwrap()
+}
+
 /// Extract all possible links from a Message. It looks at the text,
 /// caption, and all buttons within an inline keyboard.
 pub fn extract_links<'m>(
@@ -159,11 +388,10 @@ async fn get_matches(
     fapi: &fuzzysearch::FuzzySearch,
     conn: &quaint::pooled::Quaint,
     sizes: &[PhotoSize],
-) -> failure::Fallible<Option<fuzzysearch::File>> {
+) -> failure::Fallible<Vec<fuzzysearch::File>> {
     // Find the highest resolution size of the image and download.
     let best_photo = find_best_photo(&sizes).unwrap();
-    let matches = match_image(&bot, &conn, &fapi, &best_photo).await?;
-    Ok(matches.into_iter().next())
+    match_image(&bot, &conn, &fapi, best_photo.into()).await
 }
 
 #[cfg(test)]
@@ -236,6 +464,51 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_channel_settings() {
+        use super::ChannelSettings;
+
+        // Channels without stored settings, or with settings saved before
+        // an option was added, should keep the original behavior.
+        let settings: ChannelSettings = serde_json::from_str("{}").unwrap();
+        assert_eq!(settings, ChannelSettings::default());
+        assert!(settings.enabled);
+
+        let settings: ChannelSettings =
+            serde_json::from_str(r#"{"caption":true,"sites":["e621","FurAffinity"]}"#).unwrap();
+        assert!(settings.enabled);
+        assert!(settings.caption);
+
+        assert_eq!(settings.site_position("e621"), 0);
+        assert_eq!(settings.site_position("FurAffinity"), 1);
+        assert_eq!(settings.site_position("Weasyl"), 2);
+    }
+
+    #[test]
+    fn test_join_sources() {
+        use super::{join_sources, CAPTION_LIMIT};
+
+        let source = "a".repeat(600);
+        assert_eq!(
+            join_sources(vec![source.clone(), source.clone()]),
+            Some(source),
+            "sources past the limit should be dropped"
+        );
+
+        let source = "a".repeat(CAPTION_LIMIT / 2);
+        assert_eq!(
+            join_sources(vec![source.clone(), source.clone()]),
+            Some(source.clone()),
+            "separators should count towards the limit"
+        );
+
+        assert_eq!(
+            join_sources(vec!["a".into(), "b".into()]),
+            Some("a\nb".into())
+        );
+        assert_eq!(join_sources(vec!["a".repeat(CAPTION_LIMIT + 1)]), None);
+    }
+
     #[test]
     fn test_link_was_seen() {
         let finder = get_finder();
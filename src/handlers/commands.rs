@@ -4,11 +4,18 @@ use tgbotapi::{requests::*, *};
 use tokio01::runtime::current_thread::block_on_all;
 
 use super::Status::*;
-use crate::models::{GroupConfig, GroupConfigKey, Twitter, TwitterRequest};
+use crate::blocklist::Blocklist;
+use crate::models::{
+    GroupConfig, GroupConfigKey, RecentPhotos, Twitter, TwitterRequest, UserConfig, UserConfigKey,
+    Video,
+};
 use crate::needs_field;
+use crate::sites::Rating;
 use crate::utils::{
-    build_alternate_response, continuous_action, find_best_photo, find_images, get_message,
-    match_image, parse_known_bots, sort_results,
+    build_alternate_response, build_recent_source_response, continuous_action, find_images,
+    find_searchable_file, get_message, hidden_message, link_failures_message, match_image,
+    media_kind, parse_known_bots, post_caption, sort_results, ContentFilter, FileKind, MatchPolicy,
+    MediaKind, SearchableFile, Strictness,
 };
 
 // TODO: there's a lot of shared code between these commands.
@@ -55,6 +62,9 @@ impl super::Handler for CommandHandler {
             "/error" => Err(failure::format_err!("a test error message")),
             "/groupsource" => self.enable_group_source(&handler, message).await,
             "/grouppreviews" => self.group_nopreviews(&handler, &message).await,
+            "/grouprating" => self.group_rating(&handler, &message).await,
+            "/groupstrictness" => self.group_strictness(&handler, &message).await,
+            "/blocklist" => self.handle_blocklist(&handler, &message).await,
             _ => {
                 tracing::info!("unknown command: {}", command.name);
                 return Ok(Ignored);
@@ -86,46 +96,20 @@ impl CommandHandler {
             return Ok(());
         }
 
-        let user = message.from.as_ref().unwrap();
-
-        let con_token = egg_mode::KeyPair::new(
-            handler.config.twitter_consumer_key.clone(),
-            handler.config.twitter_consumer_secret.clone(),
-        );
-
-        let request_token = block_on_all(egg_mode::request_token(&con_token, "oob"))?;
-
-        let conn = handler.conn.check_out().await?;
-        Twitter::set_request(
-            &conn,
-            user.id,
-            TwitterRequest {
-                request_key: request_token.key.to_string(),
-                request_secret: request_token.secret.to_string(),
-            },
-        )
-        .await?;
-
-        let url = egg_mode::authorize_url(&request_token);
-
-        let mut args = fluent::FluentArgs::new();
-        args.insert("link", fluent::FluentValue::from(url));
-
-        let text = handler
-            .get_fluent_bundle(user.language_code.as_deref(), |bundle| {
-                get_message(&bundle, "twitter-oob", Some(args)).unwrap()
-            })
-            .await;
-
-        let send_message = SendMessage {
-            chat_id: message.chat_id(),
-            text,
-            reply_markup: Some(ReplyMarkup::ForceReply(ForceReply::selective())),
-            reply_to_message_id: Some(message.message_id),
-            ..Default::default()
+        // Accounts can only be saved when there's a key to encrypt them.
+        let con_token = match handler.config.twitter_keys() {
+            Some(keys) if handler.secret_key.is_some() => keys,
+            _ => {
+                handler
+                    .send_generic_reply(&message, "twitter-unavailable")
+                    .await?;
+                return Ok(());
+            }
         };
 
-        handler.make_request(&send_message).await?;
+        let user = message.from.as_ref().unwrap();
+
+        start_twitter_auth(&handler, &user, con_token, Some(message.message_id)).await?;
 
         let point = influxdb::Query::write_query(influxdb::Timestamp::Now, "twitter")
             .add_tag("type", "new")
@@ -172,36 +156,94 @@ impl CommandHandler {
 
         let mut results: Vec<crate::PostInfo> = Vec::with_capacity(links.len());
 
-        let missing = {
-            let mut sites = handler.sites.lock().await;
+        let link_results = {
             let links = links.iter().map(|link| link.as_str()).collect();
-            find_images(&from, links, &mut sites, &mut |info| {
+            find_images(&from, links, &handler.sites, &mut |info| {
                 results.extend(info.results);
             })
-            .await?
+            .await
         };
 
         drop(action);
 
-        if results.is_empty() {
+        let chat_id = if message.chat.chat_type.is_group() {
+            Some(message.chat.id)
+        } else {
+            None
+        };
+        let filter = ContentFilter::load(&handler.conn, from.id, chat_id).await?;
+        let (results, hidden) = filter.filter_posts(results);
+
+        let failures = handler
+            .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+                let lines: Vec<String> = link_failures_message(&bundle, &link_results)
+                    .into_iter()
+                    .chain(hidden_message(&bundle, &hidden))
+                    .collect();
+
+                if lines.is_empty() {
+                    None
+                } else {
+                    Some(lines.join("\n"))
+                }
+            })
+            .await;
+
+        if results.is_empty() && failures.is_none() {
             handler
                 .send_generic_reply(&message, "mirror-no-results")
                 .await?;
             return Ok(());
         }
 
-        if results.len() == 1 {
-            let result = results.get(0).unwrap();
+        let captions: Vec<_> = handler
+            .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+                results
+                    .iter()
+                    .map(|result| post_caption(&bundle, result))
+                    .collect()
+            })
+            .await;
+
+        // Videos that need converting can't be sent directly, so they are
+        // sent after being processed or linked to be processed.
+        let mut media = Vec::with_capacity(results.len());
+        let mut unprocessed = Vec::new();
+
+        {
+            let conn = handler.conn.check_out().await?;
+
+            for (result, caption) in results.iter().zip(captions) {
+                match media_kind(&result.file_type) {
+                    Some(MediaKind::ConvertedVideo) => {
+                        match Video::lookup_url(&conn, &result.url).await? {
+                            Some(Video {
+                                processed: true,
+                                mp4_url: Some(mp4_url),
+                                ..
+                            }) => media.push((MediaKind::Video, mp4_url, caption)),
+                            Some(video) => unprocessed.push(video.id),
+                            None => {
+                                let source = result.source_link.as_deref().unwrap_or(&result.url);
+                                let id = Video::insert_url(&conn, &result.url, source).await?;
+                                unprocessed.push(id as i64);
+                            }
+                        }
+                    }
+                    Some(kind) => media.push((kind, result.url.clone(), caption)),
+                    None => tracing::warn!("got unusable type: {}", result.file_type),
+                }
+            }
+        }
 
-            if result.file_type == "mp4" {
+        if media.len() == 1 {
+            let (kind, url, caption) = media.remove(0);
+
+            if kind == MediaKind::Video {
                 let video = SendVideo {
                     chat_id: message.chat_id(),
-                    caption: if let Some(source_link) = &result.source_link {
-                        Some(source_link.to_owned())
-                    } else {
-                        None
-                    },
-                    video: FileType::URL(result.url.clone()),
+                    caption,
+                    video: FileType::URL(url),
                     reply_to_message_id: Some(message.message_id),
                     ..Default::default()
                 };
@@ -210,12 +252,8 @@ impl CommandHandler {
             } else {
                 let photo = SendPhoto {
                     chat_id: message.chat_id(),
-                    caption: if let Some(source_link) = &result.source_link {
-                        Some(source_link.to_owned())
-                    } else {
-                        None
-                    },
-                    photo: FileType::URL(result.url.clone()),
+                    caption,
+                    photo: FileType::URL(url),
                     reply_to_message_id: Some(message.message_id),
                     ..Default::default()
                 };
@@ -223,26 +261,18 @@ impl CommandHandler {
                 handler.make_request(&photo).await?;
             }
         } else {
-            for chunk in results.chunks(10) {
+            for chunk in media.chunks(10) {
                 let media = chunk
                     .iter()
-                    .map(|result| match result.file_type.as_ref() {
-                        "mp4" => InputMedia::Video(InputMediaVideo {
-                            media: FileType::URL(result.url.to_owned()),
-                            caption: if let Some(source_link) = &result.source_link {
-                                Some(source_link.to_owned())
-                            } else {
-                                None
-                            },
+                    .map(|(kind, url, caption)| match kind {
+                        MediaKind::Video => InputMedia::Video(InputMediaVideo {
+                            media: FileType::URL(url.to_owned()),
+                            caption: caption.clone(),
                             ..Default::default()
                         }),
                         _ => InputMedia::Photo(InputMediaPhoto {
-                            media: FileType::URL(result.url.to_owned()),
-                            caption: if let Some(source_link) = &result.source_link {
-                                Some(source_link.to_owned())
-                            } else {
-                                None
-                            },
+                            media: FileType::URL(url.to_owned()),
+                            caption: caption.clone(),
                             ..Default::default()
                         }),
                     })
@@ -259,14 +289,58 @@ impl CommandHandler {
             }
         }
 
-        if !missing.is_empty() {
-            let links: Vec<String> = missing.iter().map(|item| format!("· {}", item)).collect();
+        if !unprocessed.is_empty() {
+            let (text, button) = handler
+                .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+                    (
+                        get_message(&bundle, "mirror-process-video", None).unwrap(),
+                        get_message(&bundle, "mirror-process-video-button", None).unwrap(),
+                    )
+                })
+                .await;
+
+            let bot_username = handler.bot_user.username.as_deref().unwrap_or_default();
+
+            let inline_keyboard = unprocessed
+                .iter()
+                .map(|id| {
+                    vec![InlineKeyboardButton {
+                        text: button.clone(),
+                        url: Some(format!(
+                            "https://t.me/{}?start=process-{}",
+                            bot_username, id
+                        )),
+                        ..Default::default()
+                    }]
+                })
+                .collect();
+
+            let send_message = SendMessage {
+                chat_id: message.chat_id(),
+                reply_to_message_id: Some(reply_to_id),
+                text,
+                reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(InlineKeyboardMarkup {
+                    inline_keyboard,
+                })),
+                ..Default::default()
+            };
+
+            handler.make_request(&send_message).await?;
+        }
+
+        if let Some(failures) = failures {
             let mut args = fluent::FluentArgs::new();
-            args.insert("links", fluent::FluentValue::from(links.join("\n")));
+            args.insert("links", fluent::FluentValue::from(failures));
+
+            let name = if results.is_empty() {
+                "mirror-failed"
+            } else {
+                "mirror-missing"
+            };
 
             let text = handler
                 .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
-                    get_message(&bundle, "mirror-missing", Some(args)).unwrap()
+                    get_message(&bundle, name, Some(args)).unwrap()
                 })
                 .await;
 
@@ -297,6 +371,23 @@ impl CommandHandler {
             ChatAction::Typing,
         );
 
+        // The person asking for the source, not who posted the photo.
+        let from = message.from.as_ref().unwrap();
+
+        // Without a reply, a count sources that many of the group's most
+        // recent photos.
+        if message.reply_to_message.is_none() && message.chat.chat_type.is_group() {
+            let count = message
+                .text
+                .as_ref()
+                .and_then(|text| text.split_whitespace().nth(1))
+                .and_then(|count| count.parse::<usize>().ok());
+
+            if let Some(count) = count {
+                return self.handle_recent_sources(&handler, message, count).await;
+            }
+        }
+
         let conn = handler.conn.check_out().await?;
 
         let is_admin: Option<bool> =
@@ -340,9 +431,9 @@ impl CommandHandler {
             (message.message_id, message)
         };
 
-        let photo = match &message.photo {
-            Some(photo) if !photo.is_empty() => photo,
-            _ => {
+        let file = match find_searchable_file(message) {
+            Some(file) => file,
+            None => {
                 handler
                     .send_generic_reply(&message, "source-no-photo")
                     .await?;
@@ -370,27 +461,42 @@ impl CommandHandler {
             }
         }
 
-        let best_photo = find_best_photo(&photo).unwrap();
-        let mut matches =
-            match_image(&handler.bot, &handler.conn, &handler.fapi, &best_photo).await?;
+        let mut matches = match_image(&handler.bot, &handler.conn, &handler.fapi, file).await?;
         sort_results(
             &handler.conn,
+            &handler.sites,
             message.from.as_ref().unwrap().id,
             &mut matches,
         )
         .await?;
 
+        let chat_id = if message.chat.chat_type.is_group() {
+            Some(message.chat.id)
+        } else {
+            None
+        };
+        let filter = ContentFilter::load(&handler.conn, from.id, chat_id).await?;
+        let policy = MatchPolicy::load(&handler.conn, Some(from.id), chat_id).await?;
+        let hidden = filter.hidden_matches(from, &matches, &handler.sites).await;
+
+        let had_matches = !matches.is_empty();
+        matches.retain(|m| !hidden.contains(&m.url()));
+
         let result = match matches.first() {
             Some(result) => result,
             None => {
-                handler
-                    .send_generic_reply(&message, "reverse-no-results")
-                    .await?;
+                let name = if had_matches {
+                    "source-hidden"
+                } else {
+                    "reverse-no-results"
+                };
+
+                handler.send_generic_reply(&message, name).await?;
                 return Ok(());
             }
         };
 
-        let name = if result.distance.unwrap() < 5 {
+        let name = if policy.is_good(result) {
             "reverse-good-result"
         } else {
             "reverse-bad-result"
@@ -414,7 +520,7 @@ impl CommandHandler {
             GroupConfig::get::<bool>(&conn, message.chat.id, GroupConfigKey::GroupNoPreviews)
                 .await?
                 .is_some()
-                || result.distance.unwrap() > 5;
+                || !policy.is_good(result);
 
         let send_message = SendMessage {
             chat_id: message.chat.id.into(),
@@ -431,6 +537,85 @@ impl CommandHandler {
             .map_err(Into::into)
     }
 
+    async fn handle_recent_sources(
+        &self,
+        handler: &crate::MessageHandler,
+        message: &Message,
+        count: usize,
+    ) -> failure::Fallible<()> {
+        let from = message.from.as_ref().unwrap();
+
+        let count = count.max(1).min(RecentPhotos::LIMIT);
+        let photos = {
+            let conn = handler.conn.check_out().await?;
+            RecentPhotos::get(&conn, message.chat.id, count).await?
+        };
+
+        if photos.is_empty() {
+            handler
+                .send_generic_reply(&message, "source-recent-empty")
+                .await?;
+            return Ok(());
+        }
+
+        let policy = MatchPolicy::load(&handler.conn, Some(from.id), Some(message.chat.id)).await?;
+
+        let mut matches = Vec::with_capacity(photos.len());
+        for photo in &photos {
+            let file = SearchableFile {
+                file_id: &photo.file_id,
+                file_unique_id: &photo.file_unique_id,
+                kind: FileKind::Image,
+            };
+
+            // One photo failing shouldn't prevent sourcing the rest.
+            let mut photo_matches =
+                match match_image(&handler.bot, &handler.conn, &handler.fapi, file).await {
+                    Ok(photo_matches) => photo_matches,
+                    Err(err) => {
+                        tracing::warn!(
+                            message_id = photo.message_id,
+                            "unable to match recent photo: {:?}",
+                            err
+                        );
+                        vec![]
+                    }
+                };
+            sort_results(&handler.conn, &handler.sites, from.id, &mut photo_matches).await?;
+
+            // Matches too distant to be alternates are unlikely to be related.
+            matches.push(
+                photo_matches
+                    .into_iter()
+                    .next()
+                    .filter(|m| policy.is_alternate(std::slice::from_ref(m))),
+            );
+        }
+
+        let filter = ContentFilter::load(&handler.conn, from.id, Some(message.chat.id)).await?;
+        let hidden = filter
+            .hidden_matches(from, matches.iter().flatten(), &handler.sites)
+            .await;
+
+        let text = handler
+            .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+                build_recent_source_response(&bundle, policy, &matches, &hidden)
+            })
+            .await;
+
+        let send_message = SendMessage {
+            chat_id: message.chat_id(),
+            text,
+            disable_web_page_preview: Some(true),
+            reply_to_message_id: Some(message.message_id),
+            ..Default::default()
+        };
+
+        handler.make_request(&send_message).await?;
+
+        Ok(())
+    }
+
     async fn handle_alts(
         &self,
         handler: &crate::MessageHandler,
@@ -444,6 +629,13 @@ impl CommandHandler {
             ChatAction::Typing,
         );
 
+        let from = message.from.as_ref().unwrap();
+        let chat_id = if message.chat.chat_type.is_group() {
+            Some(message.chat.id)
+        } else {
+            None
+        };
+
         let (reply_to_id, message): (i32, &Message) =
             if let Some(reply_to_message) = &message.reply_to_message {
                 (message.message_id, &**reply_to_message)
@@ -451,11 +643,8 @@ impl CommandHandler {
                 (message.message_id, message)
             };
 
-        let matches = match &message.photo {
-            Some(photo) => {
-                let best_photo = find_best_photo(&photo).unwrap();
-                match_image(&handler.bot, &handler.conn, &handler.fapi, &best_photo).await?
-            }
+        let matches = match find_searchable_file(message) {
+            Some(file) => match_image(&handler.bot, &handler.conn, &handler.fapi, file).await?,
             None => {
                 let mut links = vec![];
 
@@ -484,18 +673,17 @@ impl CommandHandler {
                     return Ok(());
                 }
 
-                let mut sites = handler.sites.lock().await;
                 let links = links.iter().map(|link| link.as_str()).collect();
                 let mut link = None;
                 find_images(
                     &message.from.as_ref().unwrap(),
                     links,
-                    &mut sites,
+                    &handler.sites,
                     &mut |info| {
                         link = info.results.into_iter().next();
                     },
                 )
-                .await?;
+                .await;
 
                 let bytes = match link {
                     Some(link) => reqwest::get(&link.url)
@@ -528,6 +716,22 @@ impl CommandHandler {
             return Ok(());
         }
 
+        let filter = ContentFilter::load(&handler.conn, from.id, chat_id).await?;
+        let policy = MatchPolicy::load(&handler.conn, Some(from.id), chat_id).await?;
+        let hidden = filter.hidden_matches(from, &matches, &handler.sites).await;
+
+        let matches: Vec<fuzzysearch::File> = matches
+            .into_iter()
+            .filter(|m| !hidden.contains(&m.url()))
+            .collect();
+
+        if matches.is_empty() {
+            handler
+                .send_generic_reply(&message, "alternate-hidden")
+                .await?;
+            return Ok(());
+        }
+
         let has_multiple_matches = matches.len() > 1;
 
         let mut results: HashMap<Vec<String>, Vec<fuzzysearch::File>> = HashMap::new();
@@ -561,7 +765,7 @@ impl CommandHandler {
         let (text, used_hashes) = handler
             .get_fluent_bundle(
                 message.from.as_ref().unwrap().language_code.as_deref(),
-                |bundle| build_alternate_response(&bundle, items),
+                |bundle| build_alternate_response(&bundle, policy, items),
             )
             .await;
 
@@ -590,23 +794,34 @@ impl CommandHandler {
             return Ok(());
         }
 
+        let matches: Vec<fuzzysearch::File> = matches
+            .into_iter()
+            .map(|m| fuzzysearch::File {
+                id: m.id,
+                site_id: m.site_id,
+                distance: hamming::distance_fast(
+                    &m.hash.unwrap().to_be_bytes(),
+                    &m.searched_hash.unwrap().to_be_bytes(),
+                )
+                .ok(),
+                hash: m.hash,
+                url: m.url,
+                filename: m.filename,
+                artists: m.artists.clone(),
+                site_info: None,
+                searched_hash: None,
+            })
+            .collect();
+
+        let hidden = filter.hidden_matches(from, &matches, &handler.sites).await;
+
         for m in matches {
+            if hidden.contains(&m.url()) {
+                continue;
+            }
+
             if let Some(artist) = results.get_mut(&m.artists.clone().unwrap()) {
-                artist.push(fuzzysearch::File {
-                    id: m.id,
-                    site_id: m.site_id,
-                    distance: hamming::distance_fast(
-                        &m.hash.unwrap().to_be_bytes(),
-                        &m.searched_hash.unwrap().to_be_bytes(),
-                    )
-                    .ok(),
-                    hash: m.hash,
-                    url: m.url,
-                    filename: m.filename,
-                    artists: m.artists.clone(),
-                    site_info: None,
-                    searched_hash: None,
-                });
+                artist.push(m);
             }
         }
 
@@ -618,7 +833,7 @@ impl CommandHandler {
         let (updated_text, _used_hashes) = handler
             .get_fluent_bundle(
                 message.from.as_ref().unwrap().language_code.as_deref(),
-                |bundle| build_alternate_response(&bundle, items),
+                |bundle| build_alternate_response(&bundle, policy, items),
             )
             .await;
 
@@ -705,6 +920,7 @@ impl CommandHandler {
 
         if result.is_some() {
             GroupConfig::delete(&conn, GroupConfigKey::GroupAdd, message.chat.id).await?;
+            RecentPhotos::clear(&conn, message.chat.id).await?;
             handler
                 .send_generic_reply(&message, "automatic-disable")
                 .await?;
@@ -760,4 +976,340 @@ impl CommandHandler {
 
         Ok(())
     }
+
+    async fn group_rating(
+        &self,
+        handler: &crate::MessageHandler,
+        message: &Message,
+    ) -> failure::Fallible<()> {
+        if !self.is_valid_admin_group(&handler, &message, false).await? {
+            return Ok(());
+        }
+
+        let rating: Option<Rating> = message
+            .text
+            .as_ref()
+            .and_then(|text| text.split_whitespace().nth(1))
+            .and_then(|rating| rating.to_lowercase().parse().ok());
+
+        let rating = match rating {
+            Some(rating) => rating,
+            None => {
+                handler
+                    .send_generic_reply(&message, "group-rating-usage")
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        let conn = handler.conn.check_out().await?;
+
+        let existing: Option<Rating> =
+            GroupConfig::get(&conn, message.chat.id, GroupConfigKey::MaxRating).await?;
+
+        // Allowing everything is the default, so there's no need to store it.
+        if rating == Rating::Explicit {
+            if existing.is_some() {
+                GroupConfig::delete(&conn, GroupConfigKey::MaxRating, message.chat.id).await?;
+            }
+        } else {
+            GroupConfig::set(
+                &conn,
+                GroupConfigKey::MaxRating,
+                message.chat.id,
+                existing.is_some(),
+                rating,
+            )
+            .await?;
+        }
+
+        let text = handler
+            .get_fluent_bundle(
+                message
+                    .from
+                    .as_ref()
+                    .and_then(|from| from.language_code.as_deref()),
+                |bundle| {
+                    let name =
+                        get_message(&bundle, &format!("rating-{}", rating.as_str()), None).unwrap();
+
+                    let mut args = fluent::FluentArgs::new();
+                    args.insert("rating", name.into());
+
+                    get_message(&bundle, "group-rating-updated", Some(args)).unwrap()
+                },
+            )
+            .await;
+
+        let send_message = SendMessage {
+            chat_id: message.chat_id(),
+            reply_to_message_id: Some(message.message_id),
+            text,
+            ..Default::default()
+        };
+
+        handler.make_request(&send_message).await?;
+
+        Ok(())
+    }
+
+    async fn group_strictness(
+        &self,
+        handler: &crate::MessageHandler,
+        message: &Message,
+    ) -> failure::Fallible<()> {
+        if !self.is_valid_admin_group(&handler, &message, false).await? {
+            return Ok(());
+        }
+
+        let strictness: Option<Strictness> = message
+            .text
+            .as_ref()
+            .and_then(|text| text.split_whitespace().nth(1))
+            .and_then(|strictness| strictness.to_lowercase().parse().ok());
+
+        let strictness = match strictness {
+            Some(strictness) => strictness,
+            None => {
+                handler
+                    .send_generic_reply(&message, "group-strictness-usage")
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        let conn = handler.conn.check_out().await?;
+
+        let existing: Option<Strictness> =
+            GroupConfig::get(&conn, message.chat.id, GroupConfigKey::MatchStrictness).await?;
+
+        if strictness == Strictness::default() {
+            if existing.is_some() {
+                GroupConfig::delete(&conn, GroupConfigKey::MatchStrictness, message.chat.id)
+                    .await?;
+            }
+        } else {
+            GroupConfig::set(
+                &conn,
+                GroupConfigKey::MatchStrictness,
+                message.chat.id,
+                existing.is_some(),
+                strictness,
+            )
+            .await?;
+        }
+
+        let text = handler
+            .get_fluent_bundle(
+                message
+                    .from
+                    .as_ref()
+                    .and_then(|from| from.language_code.as_deref()),
+                |bundle| {
+                    let name = get_message(
+                        &bundle,
+                        &format!("strictness-{}", strictness.as_str()),
+                        None,
+                    )
+                    .unwrap();
+
+                    let mut args = fluent::FluentArgs::new();
+                    args.insert("strictness", name.into());
+
+                    get_message(&bundle, "group-strictness-updated", Some(args)).unwrap()
+                },
+            )
+            .await;
+
+        let send_message = SendMessage {
+            chat_id: message.chat_id(),
+            reply_to_message_id: Some(message.message_id),
+            text,
+            ..Default::default()
+        };
+
+        handler.make_request(&send_message).await?;
+
+        Ok(())
+    }
+
+    /// Manage a blocklist of tags. In a group this changes the group's
+    /// blocklist and is limited to admins, otherwise it's the user's own.
+    async fn handle_blocklist(
+        &self,
+        handler: &crate::MessageHandler,
+        message: &Message,
+    ) -> failure::Fallible<()> {
+        let in_group = message.chat.chat_type.is_group();
+        if in_group && !self.is_valid_admin_group(&handler, &message, false).await? {
+            return Ok(());
+        }
+
+        let from = message.from.as_ref().unwrap();
+
+        let text = message.text.as_deref().unwrap_or_default();
+        let mut parts = text.splitn(3, char::is_whitespace).skip(1);
+        let action = parts.next();
+        let rule = parts.next().map(|rule| {
+            rule.split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_lowercase()
+        });
+
+        let conn = handler.conn.check_out().await?;
+
+        let existing: Option<Vec<String>> = if in_group {
+            GroupConfig::get(&conn, message.chat.id, GroupConfigKey::Blocklist).await?
+        } else {
+            UserConfig::get(&conn, UserConfigKey::Blocklist, from.id).await?
+        };
+        let has_config = existing.is_some();
+        let mut rules = existing.unwrap_or_default();
+
+        let (name, changed) = match (action, &rule) {
+            (Some("add"), Some(rule)) if Blocklist::is_valid_rule(rule) => {
+                if !rules.contains(rule) {
+                    rules.push(rule.to_owned());
+                }
+
+                ("blocklist-added", true)
+            }
+            (Some("remove"), Some(rule)) => {
+                let len = rules.len();
+                rules.retain(|item| item != rule);
+
+                if rules.len() == len {
+                    ("blocklist-not-found", false)
+                } else {
+                    ("blocklist-removed", true)
+                }
+            }
+            (Some("clear"), _) => {
+                rules.clear();
+                ("blocklist-cleared", true)
+            }
+            (None, _) if rules.is_empty() => ("blocklist-empty", false),
+            (None, _) => ("blocklist-current", false),
+            _ => ("blocklist-usage", false),
+        };
+
+        if changed {
+            if in_group {
+                GroupConfig::set(
+                    &conn,
+                    GroupConfigKey::Blocklist,
+                    message.chat.id,
+                    has_config,
+                    &rules,
+                )
+                .await?;
+            } else {
+                UserConfig::set(&conn, "blocklist", from.id, has_config, &rules).await?;
+            }
+        }
+
+        let text = handler
+            .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+                let mut args = fluent::FluentArgs::new();
+                if let Some(rule) = &rule {
+                    args.insert("rule", rule.as_str().into());
+                }
+
+                let mut text = get_message(&bundle, name, Some(args)).unwrap();
+
+                if name == "blocklist-current" {
+                    for rule in &rules {
+                        let mut args = fluent::FluentArgs::new();
+                        args.insert("rule", rule.as_str().into());
+
+                        text.push('\n');
+                        text.push_str(&get_message(&bundle, "blocklist-item", Some(args)).unwrap());
+                    }
+                }
+
+                if name == "blocklist-current" || name == "blocklist-empty" {
+                    text.push_str("\n\n");
+                    text.push_str(&get_message(&bundle, "blocklist-usage", None).unwrap());
+                }
+
+                text
+            })
+            .await;
+
+        let send_message = SendMessage {
+            chat_id: message.chat_id(),
+            reply_to_message_id: Some(message.message_id),
+            text,
+            ..Default::default()
+        };
+
+        handler.make_request(&send_message).await?;
+
+        Ok(())
+    }
+}
+
+/// Start signing in to Twitter by sending the user a link to authorize the
+/// bot. Twitter redirects to the callback when one is configured, otherwise
+/// it gives the user a code to send to `TextHandler`.
+pub(super) async fn start_twitter_auth(
+    handler: &crate::MessageHandler,
+    user: &User,
+    con_token: egg_mode::KeyPair,
+    reply_to_message_id: Option<i32>,
+) -> failure::Fallible<()> {
+    let callback = handler.config.twitter_callback();
+
+    let request_token = block_on_all(egg_mode::request_token(
+        &con_token,
+        callback.unwrap_or("oob"),
+    ))?;
+
+    let conn = handler.conn.check_out().await?;
+    Twitter::set_request(
+        &conn,
+        user.id,
+        TwitterRequest {
+            request_key: request_token.key.to_string(),
+            request_secret: request_token.secret.to_string(),
+        },
+    )
+    .await?;
+
+    let url = egg_mode::authorize_url(&request_token);
+
+    let mut args = fluent::FluentArgs::new();
+    args.insert("link", fluent::FluentValue::from(url));
+
+    let name = if callback.is_some() {
+        "twitter-callback"
+    } else {
+        "twitter-oob"
+    };
+
+    let text = handler
+        .get_fluent_bundle(user.language_code.as_deref(), |bundle| {
+            get_message(&bundle, name, Some(args)).unwrap()
+        })
+        .await;
+
+    // Codes are sent as replies, which isn't needed with a callback.
+    let reply_markup = if callback.is_some() {
+        None
+    } else {
+        Some(ReplyMarkup::ForceReply(ForceReply::selective()))
+    };
+
+    let send_message = SendMessage {
+        chat_id: user.id.into(),
+        text,
+        reply_markup,
+        reply_to_message_id,
+        ..Default::default()
+    };
+
+    handler.make_request(&send_message).await?;
+
+    Ok(())
 }
@@ -1,13 +1,23 @@
 use super::Status::*;
-use crate::models::{GroupConfig, GroupConfigKey};
+use crate::models::{GroupConfig, GroupConfigKey, RecentPhotos};
 use crate::needs_field;
-use crate::utils::{continuous_action, find_best_photo, get_message, match_image, sort_results};
+use crate::utils::{
+    continuous_action, find_best_photo, find_searchable_file, get_message, match_image,
+    sort_results, ContentFilter, FileKind, MatchPolicy, SearchableFile,
+};
 use async_trait::async_trait;
 use failure::ResultExt;
 use tgbotapi::{requests::*, *};
 
 pub struct GroupSourceHandler;
 
+/// Find the file to automatically source in a message. Extracting frames is
+/// slow, so animations, videos, and stickers are only searched when someone
+/// asks with /source.
+fn automatic_file(message: &Message) -> Option<SearchableFile> {
+    find_searchable_file(message).filter(|file| file.kind == FileKind::Image)
+}
+
 #[async_trait]
 impl super::Handler for GroupSourceHandler {
     fn name(&self) -> &'static str {
@@ -21,13 +31,16 @@ impl super::Handler for GroupSourceHandler {
         _command: Option<&Command>,
     ) -> Result<super::Status, failure::Error> {
         let message = needs_field!(update, message);
-        let photo_sizes = needs_field!(message, photo);
+        if automatic_file(message).is_none() {
+            return Ok(Ignored);
+        }
 
         let conn = handler
             .conn
             .check_out()
             .await
             .context("unable to check out database")?;
+
         match GroupConfig::get(&conn, message.chat.id, GroupConfigKey::GroupAdd)
             .await
             .context("unable to query group add config")?
@@ -36,6 +49,31 @@ impl super::Handler for GroupSourceHandler {
             _ => return Ok(Ignored),
         }
 
+        // Groups that enabled automatic sourcing also have their recent
+        // photos remembered so they can be sourced later with /source N.
+        if let Some(photo) = message.photo.as_deref().and_then(find_best_photo) {
+            if message.chat.chat_type.is_group() {
+                RecentPhotos::add(
+                    &conn,
+                    message.chat.id,
+                    message.message_id,
+                    &photo.file_id,
+                    &photo.file_unique_id,
+                )
+                .await
+                .context("unable to save recent photo")?;
+            }
+        }
+
+        // Don't hold a connection while waiting for the rest of an album.
+        drop(conn);
+
+        // Albums are sourced together from their first message.
+        let messages = match handler.media_groups.collect(message).await {
+            Some(messages) => messages,
+            None => return Ok(Completed),
+        };
+
         let action = continuous_action(
             handler.bot.clone(),
             6,
@@ -44,43 +82,96 @@ impl super::Handler for GroupSourceHandler {
             ChatAction::Typing,
         );
 
-        let best_photo = find_best_photo(&photo_sizes).unwrap();
-        let mut matches =
-            match_image(&handler.bot, &handler.conn, &handler.fapi, &best_photo).await?;
-        sort_results(
-            &handler.conn,
-            message.from.as_ref().unwrap().id,
-            &mut matches,
-        )
-        .await?;
-
-        let wanted_matches = matches
-            .iter()
-            .filter(|m| m.distance.unwrap() <= 3)
-            .collect::<Vec<_>>();
-
-        if wanted_matches.is_empty() {
-            return Ok(Completed);
+        let from = message.from.as_ref().unwrap();
+        let policy = MatchPolicy::load(&handler.conn, Some(from.id), Some(message.chat.id)).await?;
+
+        // Matches for each image that had any, with the image's position.
+        let mut sourced = Vec::with_capacity(messages.len());
+        for (idx, message) in messages.iter().enumerate() {
+            let file = match automatic_file(message) {
+                Some(file) => file,
+                None => continue,
+            };
+
+            // One image failing shouldn't prevent sourcing the rest.
+            let mut matches =
+                match match_image(&handler.bot, &handler.conn, &handler.fapi, file).await {
+                    Ok(matches) => matches,
+                    Err(err) => {
+                        tracing::warn!(
+                            message_id = message.message_id,
+                            "unable to match album image: {:?}",
+                            err
+                        );
+                        continue;
+                    }
+                };
+            sort_results(&handler.conn, &handler.sites, from.id, &mut matches).await?;
+
+            let wanted_matches = matches
+                .into_iter()
+                .filter(|m| policy.is_automatic(m))
+                .collect::<Vec<_>>();
+
+            let links = super::channel_photo::extract_links(&message, &handler.finder);
+            if wanted_matches.is_empty()
+                || wanted_matches
+                    .iter()
+                    .any(|m| super::channel_photo::link_was_seen(&links, &m.url()))
+            {
+                continue;
+            }
+
+            sourced.push((idx + 1, wanted_matches));
         }
 
-        let links = super::channel_photo::extract_links(&message, &handler.finder);
-        if wanted_matches
-            .iter()
-            .any(|m| super::channel_photo::link_was_seen(&links, &m.url()))
-        {
+        if sourced.is_empty() {
             return Ok(Completed);
         }
 
-        let lang = message
-            .from
-            .as_ref()
-            .and_then(|from| from.language_code.as_deref());
+        let filter = ContentFilter::load(&handler.conn, from.id, Some(message.chat.id)).await?;
+        let hidden = filter
+            .hidden_matches(
+                from,
+                sourced.iter().flat_map(|(_idx, matches)| matches.iter()),
+                &handler.sites,
+            )
+            .await;
+
+        let lang = from.language_code.as_deref();
 
         let text = handler
             .get_fluent_bundle(lang, |bundle| {
+                if messages.len() > 1 {
+                    let mut buf = get_message(bundle, "automatic-album", None).unwrap();
+                    buf.push('\n');
+
+                    for (idx, matches) in &sourced {
+                        let mut args = fluent::FluentArgs::new();
+                        args.insert("index", (*idx).into());
+
+                        buf.push('\n');
+                        buf.push_str(
+                            &get_message(bundle, "automatic-album-image", Some(args)).unwrap(),
+                        );
+                        buf.push('\n');
+                        buf.push_str(&match_lines(bundle, matches, &hidden));
+                    }
+
+                    return buf;
+                }
+
+                let wanted_matches = &sourced.first().unwrap().1;
+
                 if wanted_matches.len() == 1 {
+                    let link = wanted_matches.first().unwrap().url();
+
+                    if hidden.contains(&link) {
+                        return get_message(bundle, "automatic-single-hidden", None).unwrap();
+                    }
+
                     let mut args = fluent::FluentArgs::new();
-                    args.insert("link", wanted_matches.first().unwrap().url().into());
+                    args.insert("link", link.into());
 
                     get_message(bundle, "automatic-single", Some(args)).unwrap()
                 } else {
@@ -88,17 +179,7 @@ impl super::Handler for GroupSourceHandler {
 
                     buf.push_str(&get_message(bundle, "automatic-multiple", None).unwrap());
                     buf.push('\n');
-
-                    for result in wanted_matches {
-                        let mut args = fluent::FluentArgs::new();
-                        args.insert("link", result.url().into());
-                        args.insert("distance", result.distance.unwrap().into());
-
-                        buf.push_str(
-                            &get_message(bundle, "automatic-multiple-result", Some(args)).unwrap(),
-                        );
-                        buf.push('\n');
-                    }
+                    buf.push_str(&match_lines(bundle, wanted_matches, &hidden));
 
                     buf
                 }
@@ -109,7 +190,7 @@ impl super::Handler for GroupSourceHandler {
 
         let message = SendMessage {
             chat_id: message.chat_id(),
-            reply_to_message_id: Some(message.message_id),
+            reply_to_message_id: Some(messages.first().unwrap().message_id),
             disable_web_page_preview: Some(true),
             text,
             ..Default::default()
@@ -123,3 +204,31 @@ impl super::Handler for GroupSourceHandler {
         Ok(Completed)
     }
 }
+
+/// List each match with its distance, leaving out links hidden by filters.
+fn match_lines(
+    bundle: &fluent::FluentBundle<fluent::FluentResource>,
+    matches: &[fuzzysearch::File],
+    hidden: &[String],
+) -> String {
+    let mut buf = String::new();
+
+    for result in matches {
+        let link = result.url();
+
+        let mut args = fluent::FluentArgs::new();
+        args.insert("distance", result.distance.unwrap().into());
+
+        let name = if hidden.contains(&link) {
+            "automatic-multiple-hidden"
+        } else {
+            args.insert("link", link.into());
+            "automatic-multiple-result"
+        };
+
+        buf.push_str(&get_message(bundle, name, Some(args)).unwrap());
+        buf.push('\n');
+    }
+
+    buf
+}
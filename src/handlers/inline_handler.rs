@@ -23,7 +23,6 @@ impl InlineHandler {
         message: &Message,
     ) -> failure::Fallible<()> {
         use futures::TryStreamExt;
-        use rusoto_s3::S3;
         use tokio::io::AsyncWriteExt;
         use tokio::stream::StreamExt;
 
@@ -58,49 +57,87 @@ impl InlineHandler {
         let conn = handler.conn.check_out().await?;
         let video = Video::lookup_id(&conn, id).await?.expect("missing video");
 
+        // Pixiv animations are a zip of frames, so they need the frame delays
+        // and the Referer header to be downloaded.
+        let ugoira = match crate::sites::Pixiv::ugoira_id(&video.url) {
+            Some(illust_id) => Some(
+                crate::sites::Pixiv::ugoira_meta(
+                    &reqwest::Client::new(),
+                    handler.config.pixiv_session.as_deref(),
+                    illust_id,
+                )
+                .await?,
+            ),
+            None => None,
+        };
+
         let _ = std::fs::create_dir("videos");
 
-        let name = format!("videos/{}.webm", generate_id());
+        // Playlists reference many files, so ffmpeg loads them itself
+        // instead of the video being downloaded first.
+        let playlist = crate::video::is_playlist(&video.url);
+
+        let name = if playlist {
+            video.url.clone()
+        } else {
+            let ext = if ugoira.is_some() { "zip" } else { "webm" };
+            format!("videos/{}.{}", generate_id(), ext)
+        };
         let path = std::path::Path::new(&name);
 
-        let mut stream = reqwest::get(&video.url).await?.bytes_stream();
-        let mut file = tokio::fs::File::create(&path).await?;
-        let mut size: usize = 0;
-
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk?;
-            size += chunk.len();
-
-            if size > 50_000_000 {
-                drop(file);
-                tokio::fs::remove_file(&path).await?;
-                let video_too_large = handler
-                    .get_fluent_bundle(lang, |bundle| {
-                        get_message(&bundle, "video-too-large", None).unwrap()
-                    })
-                    .await;
-                let edit_message = EditMessageText {
-                    message_id: Some(sent.message_id),
-                    chat_id: message.chat_id(),
-                    text: video_too_large,
-                    ..Default::default()
-                };
-                handler.make_request(&edit_message).await?;
-                return Ok(());
+        if !playlist {
+            let mut request = reqwest::Client::new().get(&video.url);
+            if ugoira.is_some() {
+                request = request.header(reqwest::header::REFERER, crate::sites::Pixiv::REFERER);
             }
 
-            file.write(&chunk).await?;
+            let mut stream = request.send().await?.error_for_status()?.bytes_stream();
+            let mut file = tokio::fs::File::create(&path).await?;
+            let mut size: usize = 0;
+
+            while let Some(chunk) = stream.next().await {
+                let chunk = chunk?;
+                size += chunk.len();
+
+                if size > 50_000_000 {
+                    drop(file);
+                    tokio::fs::remove_file(&path).await?;
+                    let video_too_large = handler
+                        .get_fluent_bundle(lang, |bundle| {
+                            get_message(&bundle, "video-too-large", None).unwrap()
+                        })
+                        .await;
+                    let edit_message = EditMessageText {
+                        message_id: Some(sent.message_id),
+                        chat_id: message.chat_id(),
+                        text: video_too_large,
+                        ..Default::default()
+                    };
+                    handler.make_request(&edit_message).await?;
+                    return Ok(());
+                }
+
+                file.write(&chunk).await?;
+            }
+
+            // Make sure the whole file is written before it gets processed.
+            file.flush().await?;
         }
 
         let name_clone = name.clone();
         let res = tokio::task::spawn_blocking(move || {
-            let name = name_clone;
-            crate::video::process_video(std::path::Path::new(&name))
+            let path = std::path::Path::new(&name_clone);
+
+            match ugoira {
+                Some(meta) => crate::video::process_ugoira(path, &meta.frames),
+                None => crate::video::process_video(path),
+            }
         })
         .await??;
 
-        drop(file);
-        tokio::fs::remove_file(&path).await?;
+        if !playlist {
+            tokio::fs::remove_file(&path).await?;
+        }
 
         let video_finished = handler
             .get_fluent_bundle(lang, |bundle| {
@@ -115,18 +152,6 @@ impl InlineHandler {
         };
         handler.make_request(&edit_message).await?;
 
-        let region = rusoto_core::Region::Custom {
-            name: handler.config.s3_region.clone(),
-            endpoint: handler.config.s3_endpoint.clone(),
-        };
-
-        let client = rusoto_core::request::HttpClient::new().unwrap();
-        let provider = rusoto_credential::StaticProvider::new_minimal(
-            handler.config.s3_token.clone(),
-            handler.config.s3_secret.clone(),
-        );
-        let s3client = rusoto_s3::S3Client::new_with(client, provider, region);
-
         let file = tokio::fs::File::open(&res).await.unwrap();
         let metadata = file.metadata().await?;
 
@@ -137,24 +162,11 @@ impl InlineHandler {
 
         let key = format!("{}.mp4", generate_id());
 
-        let put = rusoto_s3::PutObjectRequest {
-            acl: Some("public-read".into()),
-            bucket: handler.config.s3_bucket.clone(),
-            content_type: Some("video/mp4".into()),
-            key: key.clone(),
-            body: Some(byte_stream),
-            content_length: Some(metadata.len() as i64),
-            ..Default::default()
-        };
-
-        s3client.put_object(put).await.unwrap();
+        let mp4_url = crate::storage::Storage::new(&handler.config)
+            .put(&key, "video/mp4", byte_stream, metadata.len() as i64)
+            .await?;
         tokio::fs::remove_file(res).await.unwrap();
 
-        let mp4_url = format!(
-            "{}/{}/{}",
-            handler.config.s3_url, handler.config.s3_bucket, key
-        );
-
         Video::set_processed_url(&conn, &video.url, &mp4_url).await?;
 
         let video_return_button = handler
@@ -216,11 +228,9 @@ impl super::Handler for InlineHandler {
         tracing::debug!("found links: {:?}", links);
 
         let influx = handler.influx.clone();
-        // Lock sites in order to find which of these links are usable
-        {
-            let mut sites = handler.sites.lock().await;
+        let link_results = {
             let links = links.iter().map(|link| link.as_str()).collect();
-            find_images(&inline.from, links, &mut sites, &mut |info| {
+            find_images(&inline.from, links, &handler.sites, &mut |info| {
                 let influx = influx.clone();
                 let duration = info.duration;
                 let count = info.results.len();
@@ -239,8 +249,10 @@ impl super::Handler for InlineHandler {
                 results.extend(info.results);
             })
             .await
-            .context("unable to find images")?;
-        }
+        };
+
+        let filter = ContentFilter::load(&handler.conn, inline.from.id, None).await?;
+        let (results, hidden) = filter.filter_posts(results);
 
         let mut responses: Vec<(ResultType, InlineQueryResult)> = vec![];
 
@@ -250,6 +262,42 @@ impl super::Handler for InlineHandler {
             }
         }
 
+        // Let the user know about any links that failed to load, with the reason
+        // for each.
+        let failures = handler
+            .get_fluent_bundle(inline.from.language_code.as_deref(), |bundle| {
+                link_failures_message(&bundle, &link_results).map(|failures| {
+                    InlineQueryResult::article(
+                        generate_id(),
+                        get_message(&bundle, "inline-failed-title", None).unwrap(),
+                        failures,
+                    )
+                })
+            })
+            .await;
+
+        if let Some(article) = failures {
+            responses.push((ResultType::Ready, article));
+        }
+
+        // Results hidden by the user's rating or blocklist are replaced with
+        // a single entry listing what was hidden.
+        let hidden = handler
+            .get_fluent_bundle(inline.from.language_code.as_deref(), |bundle| {
+                hidden_message(&bundle, &hidden).map(|hidden| {
+                    InlineQueryResult::article(
+                        generate_id(),
+                        get_message(&bundle, "inline-hidden-title", None).unwrap(),
+                        hidden,
+                    )
+                })
+            })
+            .await;
+
+        if let Some(article) = hidden {
+            responses.push((ResultType::Ready, article));
+        }
+
         // If we had no responses but the query was not empty, there were likely links
         // that we were unable to convert. We need to display that the links had no results.
         if responses.is_empty() && !inline.query.is_empty() {
@@ -322,11 +370,18 @@ async fn process_result(
     result: &PostInfo,
     from: &User,
 ) -> Option<Vec<(ResultType, InlineQueryResult)>> {
-    let (direct, source) = handler
+    let (direct, source, description) = handler
         .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+            let description = result.artist.as_ref().map(|artist| {
+                let mut args = fluent::FluentArgs::new();
+                args.insert("artist", fluent::FluentValue::from(artist.as_str()));
+                get_message(&bundle, "inline-artist", Some(args)).unwrap()
+            });
+
             (
                 get_message(&bundle, "inline-direct", None).unwrap(),
                 get_message(&bundle, "inline-source", None).unwrap(),
+                description,
             )
         })
         .await;
@@ -363,44 +418,83 @@ async fn process_result(
 
     let thumb_url = result.thumb.clone().unwrap_or_else(|| result.url.clone());
 
-    match result.file_type.as_ref() {
-        "png" | "jpeg" | "jpg" => Some(build_image_result(&result, thumb_url, &keyboard)),
-        "webm" => {
+    match media_kind(&result.file_type) {
+        Some(MediaKind::Photo) => Some(build_image_result(
+            &result,
+            thumb_url,
+            &keyboard,
+            description,
+        )),
+        Some(MediaKind::Video) => Some(build_video_result(
+            &result,
+            result.url.clone(),
+            thumb_url,
+            &keyboard,
+            description,
+        )),
+        Some(MediaKind::ConvertedVideo) => {
             let source = match &result.source_link {
                 Some(link) => link.to_owned(),
                 None => result.url.clone(),
             };
 
-            let results = build_webm_result(&handler.conn, &result, thumb_url, &keyboard, &source)
-                .await
-                .expect("unable to process webm results");
+            let results = build_webm_result(
+                &handler.conn,
+                &result,
+                thumb_url,
+                &keyboard,
+                &source,
+                description,
+            )
+            .await
+            .expect("unable to process webm results");
 
             Some(results)
         }
-        "gif" => Some(build_gif_result(&result, thumb_url, &keyboard)),
-        other => {
-            tracing::warn!("Got unusable type: {}", other);
+        Some(MediaKind::Animation) => Some(build_gif_result(&result, thumb_url, &keyboard)),
+        None => {
+            tracing::warn!("Got unusable type: {}", result.file_type);
             None
         }
     }
 }
 
+/// Add dimensions, title, and description to a photo result.
+fn set_photo_metadata(
+    photo: &mut InlineQueryResult,
+    result: &crate::sites::PostInfo,
+    description: &Option<String>,
+) {
+    if let InlineQueryType::Photo(ref mut photo) = photo.content {
+        if let Some((width, height)) = result.dimensions {
+            photo.photo_width = Some(width as i32);
+            photo.photo_height = Some(height as i32);
+        }
+
+        photo.title = result.title.clone();
+        photo.description = description.clone();
+    }
+}
+
 fn build_image_result(
     result: &crate::sites::PostInfo,
     thumb_url: String,
     keyboard: &InlineKeyboardMarkup,
+    description: Option<String>,
 ) -> Vec<(ResultType, InlineQueryResult)> {
     let (full_url, thumb_url) = (result.url.clone(), thumb_url);
 
     let mut photo =
         InlineQueryResult::photo(generate_id(), full_url.to_owned(), thumb_url.to_owned());
     photo.reply_markup = Some(keyboard.clone());
+    set_photo_metadata(&mut photo, result, &description);
 
     let mut results = vec![(ResultType::Ready, photo)];
 
     if let Some(message) = &result.extra_caption {
         let mut photo = InlineQueryResult::photo(generate_id(), full_url, thumb_url);
         photo.reply_markup = Some(keyboard.clone());
+        set_photo_metadata(&mut photo, result, &description);
 
         if let InlineQueryType::Photo(ref mut result) = photo.content {
             result.caption = Some(message.to_string());
@@ -418,6 +512,7 @@ async fn build_webm_result(
     thumb_url: String,
     keyboard: &InlineKeyboardMarkup,
     source_link: &str,
+    description: Option<String>,
 ) -> failure::Fallible<Vec<(ResultType, InlineQueryResult)>> {
     let conn = conn.check_out().await?;
 
@@ -438,16 +533,36 @@ async fn build_webm_result(
         Some(video) => video,
     };
 
-    let (full_url, thumb_url) = (video.mp4_url.unwrap(), thumb_url);
+    Ok(build_video_result(
+        result,
+        video.mp4_url.unwrap(),
+        thumb_url,
+        keyboard,
+        description,
+    ))
+}
+
+fn build_video_result(
+    result: &crate::sites::PostInfo,
+    full_url: String,
+    thumb_url: String,
+    keyboard: &InlineKeyboardMarkup,
+    description: Option<String>,
+) -> Vec<(ResultType, InlineQueryResult)> {
+    // Videos require a title, so fall back to the URL if the site had none.
+    let title = result.title.clone().unwrap_or_else(|| result.url.clone());
 
     let mut video = InlineQueryResult::video(
         generate_id(),
         full_url.to_owned(),
         "video/mp4".to_owned(),
         thumb_url.to_owned(),
-        result.url.clone(),
+        title.clone(),
     );
     video.reply_markup = Some(keyboard.clone());
+    if let InlineQueryType::Video(ref mut video) = video.content {
+        video.description = description.clone();
+    }
 
     let mut results = vec![(ResultType::Ready, video)];
 
@@ -457,18 +572,19 @@ async fn build_webm_result(
             full_url,
             "video/mp4".to_owned(),
             thumb_url,
-            result.url.clone(),
+            title,
         );
         video.reply_markup = Some(keyboard.clone());
 
         if let InlineQueryType::Video(ref mut result) = video.content {
             result.caption = Some(message.to_string());
+            result.description = description;
         }
 
         results.push((ResultType::Ready, video));
     };
 
-    Ok(results)
+    results
 }
 
 fn build_gif_result(
@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use tgbotapi::Message;
+use tokio::sync::Mutex;
+
+/// How long to wait for the rest of a media group after its first message.
+const MEDIA_GROUP_WAIT: Duration = Duration::from_secs(2);
+
+/// Buffers messages from media groups, which Telegram sends as a separate
+/// update for each item, so they can be handled together.
+#[derive(Default)]
+pub struct MediaGroups {
+    groups: Mutex<HashMap<String, Vec<Message>>>,
+}
+
+impl MediaGroups {
+    /// Add a message to its media group.
+    ///
+    /// The first message of a group waits for the rest of the group to
+    /// arrive and returns every message in order. Every later message
+    /// returns `None` and should be ignored. Messages without a media group
+    /// are returned by themselves.
+    pub async fn collect(&self, message: &Message) -> Option<Vec<Message>> {
+        let media_group_id = match &message.media_group_id {
+            Some(media_group_id) => media_group_id,
+            None => return Some(vec![message.clone()]),
+        };
+
+        {
+            let mut groups = self.groups.lock().await;
+            let group = groups.entry(media_group_id.clone()).or_default();
+            group.push(message.clone());
+
+            if group.len() > 1 {
+                return None;
+            }
+        }
+
+        tokio::time::delay_for(MEDIA_GROUP_WAIT).await;
+
+        let mut messages = self
+            .groups
+            .lock()
+            .await
+            .remove(media_group_id)
+            .unwrap_or_default();
+        messages.sort_by_key(|message| message.message_id);
+
+        Some(messages)
+    }
+}
@@ -7,6 +7,7 @@ mod error_reply;
 mod group_add;
 mod group_source;
 mod inline_handler;
+mod media_group;
 mod photo;
 pub mod settings;
 mod text;
@@ -18,9 +19,10 @@ pub use error_reply::ErrorReplyHandler;
 pub use group_add::GroupAddHandler;
 pub use group_source::GroupSourceHandler;
 pub use inline_handler::InlineHandler;
+pub use media_group::MediaGroups;
 pub use photo::PhotoHandler;
 pub use settings::SettingsHandler;
-pub use text::TextHandler;
+pub use text::{twitter_callback, TextHandler, VerifierRejected};
 
 #[derive(PartialEq)]
 pub enum Status {
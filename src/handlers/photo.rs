@@ -4,7 +4,9 @@ use async_trait::async_trait;
 use failure::ResultExt;
 use tgbotapi::{requests::*, *};
 
-use crate::utils::{continuous_action, find_best_photo, get_message, match_image, sort_results};
+use crate::utils::{
+    continuous_action, find_searchable_file, get_message, match_image, sort_results, MatchPolicy,
+};
 
 pub struct PhotoHandler;
 
@@ -21,7 +23,10 @@ impl super::Handler for PhotoHandler {
         _command: Option<&Command>,
     ) -> Result<super::Status, failure::Error> {
         let message = needs_field!(update, message);
-        let photos = needs_field!(message, photo);
+        let file = match find_searchable_file(message) {
+            Some(file) => file,
+            None => return Ok(Ignored),
+        };
 
         let now = std::time::Instant::now();
 
@@ -37,11 +42,10 @@ impl super::Handler for PhotoHandler {
             ChatAction::Typing,
         );
 
-        let best_photo = find_best_photo(&photos).unwrap();
-        let mut matches =
-            match_image(&handler.bot, &handler.conn, &handler.fapi, &best_photo).await?;
+        let mut matches = match_image(&handler.bot, &handler.conn, &handler.fapi, file).await?;
         sort_results(
             &handler.conn,
+            &handler.sites,
             message.from.as_ref().unwrap().id,
             &mut matches,
         )
@@ -62,7 +66,11 @@ impl super::Handler for PhotoHandler {
             .collect();
         tracing::debug!("match has distance of {}", first.distance.unwrap());
 
-        let name = if first.distance.unwrap() < 5 {
+        let policy =
+            MatchPolicy::load(&handler.conn, Some(message.from.as_ref().unwrap().id), None).await?;
+        let good = policy.is_good(first);
+
+        let name = if good {
             "reverse-good-result"
         } else {
             "reverse-bad-result"
@@ -96,7 +104,7 @@ impl super::Handler for PhotoHandler {
         let send_message = SendMessage {
             chat_id: message.chat_id(),
             text,
-            disable_web_page_preview: Some(first.distance.unwrap() > 5),
+            disable_web_page_preview: Some(!good),
             reply_to_message_id: Some(message.message_id),
             ..Default::default()
         };
@@ -107,7 +115,7 @@ impl super::Handler for PhotoHandler {
             .context("unable to send photo source reply")?;
 
         let point = influxdb::Query::write_query(influxdb::Timestamp::Now, "source")
-            .add_tag("good", first.distance.unwrap() < 5)
+            .add_tag("good", good)
             .add_field("matches", matches.len() as i64)
             .add_field("duration", now.elapsed().as_millis() as i64);
 
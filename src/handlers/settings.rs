@@ -1,11 +1,15 @@
 use async_trait::async_trait;
 use failure::ResultExt;
+use quaint::pooled::PooledConnection;
 use tgbotapi::{requests::*, *};
 
+use super::channel_photo::ChannelSettings;
+use super::commands::start_twitter_auth;
 use super::Status::*;
-use crate::models::{Sites, UserConfig, UserConfigKey};
+use crate::models::{SiteAccount, Sites, Twitter, UserConfig, UserConfigKey};
 use crate::needs_field;
-use crate::utils::get_message;
+use crate::sites::Rating;
+use crate::utils::{get_message, site_order, Strictness};
 
 pub struct SettingsHandler;
 
@@ -28,6 +32,20 @@ impl super::Handler for SettingsHandler {
                     .context("unable to send settings message")?;
                 return Ok(Completed);
             }
+
+            if command.name == "/accounts" || command.name == "/unlink" {
+                send_accounts_message(&handler, &update.message.as_ref().unwrap())
+                    .await
+                    .context("unable to send accounts message")?;
+                return Ok(Completed);
+            }
+
+            if command.name == "/channel" {
+                send_channel_message(&handler, &update.message.as_ref().unwrap())
+                    .await
+                    .context("unable to send channel message")?;
+                return Ok(Completed);
+            }
         }
 
         let callback_query = needs_field!(update, callback_query);
@@ -45,6 +63,26 @@ impl super::Handler for SettingsHandler {
             return name(&handler, &callback_query, &data).await;
         }
 
+        if data.starts_with("s:rating:") {
+            return rating(&handler, &callback_query, &data).await;
+        }
+
+        if data.starts_with("s:strictness:") {
+            return strictness(&handler, &callback_query, &data).await;
+        }
+
+        if data.starts_with("s:blocklist:") {
+            return blocklist(&handler, &callback_query, &data).await;
+        }
+
+        if data.starts_with("s:accounts:") {
+            return accounts(&handler, &callback_query, &data).await;
+        }
+
+        if data.starts_with("s:channel:") {
+            return channel(&handler, &callback_query, &data).await;
+        }
+
         Ok(Completed)
     }
 }
@@ -87,8 +125,788 @@ async fn name(
         .context("unable to set user source name setting")?;
 
         let text = handler
-            .get_fluent_bundle(callback_query.from.language_code.as_deref(), |bundle| {
-                get_message(&bundle, "settings-name-toggled", None).unwrap()
+            .get_fluent_bundle(callback_query.from.language_code.as_deref(), |bundle| {
+                get_message(&bundle, "settings-name-toggled", None).unwrap()
+            })
+            .await;
+
+        let answer = AnswerCallbackQuery {
+            callback_query_id: callback_query.id.clone(),
+            text: Some(text),
+            ..Default::default()
+        };
+
+        let keyboard = name_keyboard(&handler, &callback_query.from).await?;
+
+        let edit_message = EditMessageReplyMarkup {
+            message_id: Some(reply_message.message_id),
+            chat_id: reply_message.chat_id(),
+            reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+            ..Default::default()
+        };
+
+        futures::try_join!(
+            handler.make_request(&answer),
+            handler.make_request(&edit_message)
+        )
+        .context("unable to send answer or edit message")?;
+
+        return Ok(Completed);
+    }
+
+    let text = handler
+        .get_fluent_bundle(from, |bundle| {
+            get_message(&bundle, "settings-name", None).unwrap()
+        })
+        .await;
+
+    let keyboard = name_keyboard(&handler, &callback_query.from).await?;
+
+    let edit_message = EditMessageText {
+        message_id: Some(reply_message.message_id),
+        chat_id: reply_message.chat_id(),
+        reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+        text,
+        ..Default::default()
+    };
+
+    handler
+        .make_request(&edit_message)
+        .await
+        .context("unable to send setting message")?;
+
+    Ok(Completed)
+}
+
+async fn name_keyboard(
+    handler: &crate::MessageHandler,
+    from: &User,
+) -> failure::Fallible<InlineKeyboardMarkup> {
+    let conn = handler
+        .conn
+        .check_out()
+        .await
+        .context("unable to check out database")?;
+
+    let enabled = UserConfig::get(&conn, UserConfigKey::SourceName, from.id)
+        .await
+        .context("unable to query user source setting")?
+        .unwrap_or(false);
+
+    let message_name = if enabled {
+        "settings-name-source"
+    } else {
+        "settings-name-site"
+    };
+
+    let text = handler
+        .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+            get_message(&bundle, &message_name, None).unwrap()
+        })
+        .await;
+
+    let keyboard = vec![vec![InlineKeyboardButton {
+        text,
+        callback_data: Some("s:name:t".into()),
+        ..Default::default()
+    }]];
+
+    Ok(InlineKeyboardMarkup {
+        inline_keyboard: keyboard,
+    })
+}
+
+async fn rating(
+    handler: &crate::MessageHandler,
+    callback_query: &CallbackQuery,
+    data: &str,
+) -> failure::Fallible<super::Status> {
+    let reply_message = needs_field!(callback_query, message);
+
+    if let Some(Ok(rating)) = data
+        .split(':')
+        .nth(2)
+        .map(|rating| rating.parse::<Rating>())
+    {
+        let conn = handler
+            .conn
+            .check_out()
+            .await
+            .context("unable to check out database")?;
+
+        let existing: Option<Rating> =
+            UserConfig::get(&conn, UserConfigKey::MaxRating, callback_query.from.id)
+                .await
+                .context("unable to query user max rating")?;
+
+        UserConfig::set(
+            &conn,
+            "max-rating",
+            callback_query.from.id,
+            existing.is_some(),
+            rating,
+        )
+        .await
+        .context("unable to set user max rating")?;
+
+        let text = handler
+            .get_fluent_bundle(callback_query.from.language_code.as_deref(), |bundle| {
+                get_message(&bundle, "settings-rating-updated", None).unwrap()
+            })
+            .await;
+
+        let answer = AnswerCallbackQuery {
+            callback_query_id: callback_query.id.clone(),
+            text: Some(text),
+            ..Default::default()
+        };
+
+        let keyboard = rating_keyboard(&handler, &callback_query.from).await?;
+
+        let edit_message = EditMessageReplyMarkup {
+            message_id: Some(reply_message.message_id),
+            chat_id: reply_message.chat_id(),
+            reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+            ..Default::default()
+        };
+
+        futures::try_join!(
+            handler.make_request(&answer),
+            handler.make_request(&edit_message)
+        )
+        .context("unable to send answer or edit message")?;
+
+        return Ok(Completed);
+    }
+
+    let text = handler
+        .get_fluent_bundle(callback_query.from.language_code.as_deref(), |bundle| {
+            get_message(&bundle, "settings-rating", None).unwrap()
+        })
+        .await;
+
+    let keyboard = rating_keyboard(&handler, &callback_query.from).await?;
+
+    let edit_message = EditMessageText {
+        message_id: Some(reply_message.message_id),
+        chat_id: reply_message.chat_id(),
+        reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+        text,
+        ..Default::default()
+    };
+
+    let answer = AnswerCallbackQuery {
+        callback_query_id: callback_query.id.clone(),
+        ..Default::default()
+    };
+
+    futures::try_join!(
+        handler.make_request(&edit_message),
+        handler.make_request(&answer)
+    )
+    .context("unable to edit message or answer callback query")?;
+
+    Ok(Completed)
+}
+
+async fn rating_keyboard(
+    handler: &crate::MessageHandler,
+    from: &User,
+) -> failure::Fallible<InlineKeyboardMarkup> {
+    let conn = handler
+        .conn
+        .check_out()
+        .await
+        .context("unable to check out database")?;
+
+    let current = UserConfig::get(&conn, UserConfigKey::MaxRating, from.id)
+        .await
+        .context("unable to query user max rating")?
+        .unwrap_or(Rating::Explicit);
+
+    let names = handler
+        .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+            Rating::ALL
+                .iter()
+                .map(|rating| {
+                    get_message(&bundle, &format!("rating-{}", rating.as_str()), None).unwrap()
+                })
+                .collect::<Vec<_>>()
+        })
+        .await;
+
+    let buttons = Rating::ALL
+        .iter()
+        .zip(names)
+        .map(|(rating, name)| {
+            let text = if *rating == current {
+                format!("✅ {}", name)
+            } else {
+                name
+            };
+
+            InlineKeyboardButton {
+                text,
+                callback_data: Some(format!("s:rating:{}", rating.as_str())),
+                ..Default::default()
+            }
+        })
+        .collect();
+
+    Ok(InlineKeyboardMarkup {
+        inline_keyboard: vec![buttons],
+    })
+}
+
+async fn strictness(
+    handler: &crate::MessageHandler,
+    callback_query: &CallbackQuery,
+    data: &str,
+) -> failure::Fallible<super::Status> {
+    let reply_message = needs_field!(callback_query, message);
+
+    if let Some(Ok(strictness)) = data
+        .split(':')
+        .nth(2)
+        .map(|strictness| strictness.parse::<Strictness>())
+    {
+        let conn = handler
+            .conn
+            .check_out()
+            .await
+            .context("unable to check out database")?;
+
+        let existing: Option<Strictness> = UserConfig::get(
+            &conn,
+            UserConfigKey::MatchStrictness,
+            callback_query.from.id,
+        )
+        .await
+        .context("unable to query user match strictness")?;
+
+        UserConfig::set(
+            &conn,
+            "match-strictness",
+            callback_query.from.id,
+            existing.is_some(),
+            strictness,
+        )
+        .await
+        .context("unable to set user match strictness")?;
+
+        let text = handler
+            .get_fluent_bundle(callback_query.from.language_code.as_deref(), |bundle| {
+                get_message(&bundle, "settings-strictness-updated", None).unwrap()
+            })
+            .await;
+
+        let answer = AnswerCallbackQuery {
+            callback_query_id: callback_query.id.clone(),
+            text: Some(text),
+            ..Default::default()
+        };
+
+        let keyboard = strictness_keyboard(&handler, &callback_query.from).await?;
+
+        let edit_message = EditMessageReplyMarkup {
+            message_id: Some(reply_message.message_id),
+            chat_id: reply_message.chat_id(),
+            reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+            ..Default::default()
+        };
+
+        futures::try_join!(
+            handler.make_request(&answer),
+            handler.make_request(&edit_message)
+        )
+        .context("unable to send answer or edit message")?;
+
+        return Ok(Completed);
+    }
+
+    let text = handler
+        .get_fluent_bundle(callback_query.from.language_code.as_deref(), |bundle| {
+            get_message(&bundle, "settings-strictness", None).unwrap()
+        })
+        .await;
+
+    let keyboard = strictness_keyboard(&handler, &callback_query.from).await?;
+
+    let edit_message = EditMessageText {
+        message_id: Some(reply_message.message_id),
+        chat_id: reply_message.chat_id(),
+        reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+        text,
+        ..Default::default()
+    };
+
+    let answer = AnswerCallbackQuery {
+        callback_query_id: callback_query.id.clone(),
+        ..Default::default()
+    };
+
+    futures::try_join!(
+        handler.make_request(&edit_message),
+        handler.make_request(&answer)
+    )
+    .context("unable to edit message or answer callback query")?;
+
+    Ok(Completed)
+}
+
+async fn strictness_keyboard(
+    handler: &crate::MessageHandler,
+    from: &User,
+) -> failure::Fallible<InlineKeyboardMarkup> {
+    let conn = handler
+        .conn
+        .check_out()
+        .await
+        .context("unable to check out database")?;
+
+    let current = UserConfig::get(&conn, UserConfigKey::MatchStrictness, from.id)
+        .await
+        .context("unable to query user match strictness")?
+        .unwrap_or_else(Strictness::default);
+
+    let names = handler
+        .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+            Strictness::ALL
+                .iter()
+                .map(|strictness| {
+                    get_message(
+                        &bundle,
+                        &format!("strictness-{}", strictness.as_str()),
+                        None,
+                    )
+                    .unwrap()
+                })
+                .collect::<Vec<_>>()
+        })
+        .await;
+
+    let buttons = Strictness::ALL
+        .iter()
+        .zip(names)
+        .map(|(strictness, name)| {
+            let text = if *strictness == current {
+                format!("✅ {}", name)
+            } else {
+                name
+            };
+
+            InlineKeyboardButton {
+                text,
+                callback_data: Some(format!("s:strictness:{}", strictness.as_str())),
+                ..Default::default()
+            }
+        })
+        .collect();
+
+    Ok(InlineKeyboardMarkup {
+        inline_keyboard: vec![buttons],
+    })
+}
+
+async fn blocklist(
+    handler: &crate::MessageHandler,
+    callback_query: &CallbackQuery,
+    data: &str,
+) -> failure::Fallible<super::Status> {
+    let reply_message = needs_field!(callback_query, message);
+
+    // Removing a rule uses callback data like s:blocklist:r:0
+    if let Some(Ok(idx)) = data.split(':').nth(3).map(|idx| idx.parse::<usize>()) {
+        let conn = handler
+            .conn
+            .check_out()
+            .await
+            .context("unable to check out database")?;
+
+        let mut rules: Vec<String> =
+            UserConfig::get(&conn, UserConfigKey::Blocklist, callback_query.from.id)
+                .await
+                .context("unable to query user blocklist")?
+                .unwrap_or_default();
+
+        if idx < rules.len() {
+            rules.remove(idx);
+
+            UserConfig::set(&conn, "blocklist", callback_query.from.id, true, rules)
+                .await
+                .context("unable to set user blocklist")?;
+        }
+
+        let text = handler
+            .get_fluent_bundle(callback_query.from.language_code.as_deref(), |bundle| {
+                get_message(&bundle, "settings-blocklist-removed", None).unwrap()
+            })
+            .await;
+
+        let answer = AnswerCallbackQuery {
+            callback_query_id: callback_query.id.clone(),
+            text: Some(text),
+            ..Default::default()
+        };
+
+        let keyboard = blocklist_keyboard(&handler.conn, callback_query.from.id).await?;
+
+        let edit_message = EditMessageReplyMarkup {
+            message_id: Some(reply_message.message_id),
+            chat_id: reply_message.chat_id(),
+            reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+            ..Default::default()
+        };
+
+        futures::try_join!(
+            handler.make_request(&answer),
+            handler.make_request(&edit_message)
+        )
+        .context("unable to send answer or edit message")?;
+
+        return Ok(Completed);
+    }
+
+    let text = handler
+        .get_fluent_bundle(callback_query.from.language_code.as_deref(), |bundle| {
+            get_message(&bundle, "settings-blocklist", None).unwrap()
+        })
+        .await;
+
+    let keyboard = blocklist_keyboard(&handler.conn, callback_query.from.id).await?;
+
+    let edit_message = EditMessageText {
+        message_id: Some(reply_message.message_id),
+        chat_id: reply_message.chat_id(),
+        reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+        text,
+        ..Default::default()
+    };
+
+    let answer = AnswerCallbackQuery {
+        callback_query_id: callback_query.id.clone(),
+        ..Default::default()
+    };
+
+    futures::try_join!(
+        handler.make_request(&edit_message),
+        handler.make_request(&answer)
+    )
+    .context("unable to edit message or answer callback query")?;
+
+    Ok(Completed)
+}
+
+async fn blocklist_keyboard(
+    conn: &quaint::pooled::Quaint,
+    user_id: i32,
+) -> failure::Fallible<InlineKeyboardMarkup> {
+    let conn = conn
+        .check_out()
+        .await
+        .context("unable to check out database")?;
+
+    let rules: Vec<String> = UserConfig::get(&conn, UserConfigKey::Blocklist, user_id)
+        .await
+        .context("unable to query user blocklist")?
+        .unwrap_or_default();
+
+    let buttons = rules
+        .into_iter()
+        .enumerate()
+        .map(|(idx, rule)| {
+            vec![InlineKeyboardButton {
+                text: format!("❌ {}", rule),
+                callback_data: Some(format!("s:blocklist:r:{}", idx)),
+                ..Default::default()
+            }]
+        })
+        .collect();
+
+    Ok(InlineKeyboardMarkup {
+        inline_keyboard: buttons,
+    })
+}
+
+async fn accounts(
+    handler: &crate::MessageHandler,
+    callback_query: &CallbackQuery,
+    data: &str,
+) -> failure::Fallible<super::Status> {
+    let reply_message = needs_field!(callback_query, message);
+    let from = &callback_query.from;
+
+    let mut parts = data.split(':').skip(2);
+    let (action, site) = (parts.next(), parts.next());
+
+    // Signing in again uses callback data like s:accounts:a:Twitter
+    if let (Some("a"), Some("Twitter")) = (action, site) {
+        let name = match handler.config.twitter_keys() {
+            Some(keys) if handler.secret_key.is_some() => {
+                start_twitter_auth(&handler, &from, keys, None).await?;
+                None
+            }
+            _ => Some("twitter-unavailable"),
+        };
+
+        let text = match name {
+            Some(name) => Some(
+                handler
+                    .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+                        get_message(&bundle, name, None).unwrap()
+                    })
+                    .await,
+            ),
+            None => None,
+        };
+
+        let answer = AnswerCallbackQuery {
+            callback_query_id: callback_query.id.clone(),
+            text,
+            ..Default::default()
+        };
+
+        handler
+            .make_request(&answer)
+            .await
+            .context("unable to answer accounts callback query")?;
+
+        return Ok(Completed);
+    }
+
+    // Linking an account uses callback data like s:accounts:l:Inkbunny
+    if let (Some("l"), Some(site)) = (action, site) {
+        return start_link(&handler, &callback_query, site).await;
+    }
+
+    // Unlinking an account uses callback data like s:accounts:r:FurAffinity
+    if let Some(site) = data.split(':').nth(3) {
+        let conn = handler
+            .conn
+            .check_out()
+            .await
+            .context("unable to check out database")?;
+
+        if site == "Twitter" {
+            unlink_twitter(&handler, &conn, from.id).await?;
+        } else {
+            SiteAccount::delete(&conn, from.id, site)
+                .await
+                .context("unable to delete site account")?;
+        }
+
+        let mut args = fluent::FluentArgs::new();
+        args.insert("site", site.into());
+
+        let text = handler
+            .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+                get_message(&bundle, "settings-accounts-removed", Some(args)).unwrap()
+            })
+            .await;
+
+        let answer = AnswerCallbackQuery {
+            callback_query_id: callback_query.id.clone(),
+            text: Some(text),
+            ..Default::default()
+        };
+
+        let keyboard = accounts_keyboard(&handler, &from).await?;
+
+        let edit_message = EditMessageReplyMarkup {
+            message_id: Some(reply_message.message_id),
+            chat_id: reply_message.chat_id(),
+            reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+            ..Default::default()
+        };
+
+        futures::try_join!(
+            handler.make_request(&answer),
+            handler.make_request(&edit_message)
+        )
+        .context("unable to send answer or edit message")?;
+
+        return Ok(Completed);
+    }
+
+    let (text, keyboard) = accounts_message(&handler, &from).await?;
+
+    let edit_message = EditMessageText {
+        message_id: Some(reply_message.message_id),
+        chat_id: reply_message.chat_id(),
+        reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+        text,
+        ..Default::default()
+    };
+
+    let answer = AnswerCallbackQuery {
+        callback_query_id: callback_query.id.clone(),
+        ..Default::default()
+    };
+
+    futures::try_join!(
+        handler.make_request(&edit_message),
+        handler.make_request(&answer)
+    )
+    .context("unable to edit message or answer callback query")?;
+
+    Ok(Completed)
+}
+
+/// Send the list of linked accounts for the /accounts command.
+async fn send_accounts_message(
+    handler: &crate::MessageHandler,
+    message: &Message,
+) -> failure::Fallible<()> {
+    // Account names shouldn't be shared with everyone in a group.
+    if message.chat.chat_type != ChatType::Private {
+        handler
+            .send_generic_reply(&message, "link-private")
+            .await
+            .context("unable to send private chat message")?;
+        return Ok(());
+    }
+
+    let from = message.from.as_ref().unwrap();
+    let (text, keyboard) = accounts_message(&handler, &from).await?;
+
+    let message = SendMessage {
+        chat_id: message.chat_id(),
+        text,
+        reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+        ..Default::default()
+    };
+
+    handler
+        .make_request(&message)
+        .await
+        .context("unable to send accounts message")?;
+
+    Ok(())
+}
+
+async fn accounts_message(
+    handler: &crate::MessageHandler,
+    from: &User,
+) -> failure::Fallible<(String, InlineKeyboardMarkup)> {
+    let name = if handler.secret_key.is_some() {
+        "settings-accounts"
+    } else {
+        "link-unavailable"
+    };
+
+    let text = handler
+        .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+            get_message(&bundle, name, None).unwrap()
+        })
+        .await;
+
+    let keyboard = accounts_keyboard(&handler, &from).await?;
+
+    Ok((text, keyboard))
+}
+
+/// Sites users can link their own accounts for.
+const LINKABLE_SITES: &[&str] = &["FurAffinity", "Inkbunny"];
+
+async fn accounts_keyboard(
+    handler: &crate::MessageHandler,
+    from: &User,
+) -> failure::Fallible<InlineKeyboardMarkup> {
+    let conn = handler
+        .conn
+        .check_out()
+        .await
+        .context("unable to check out database")?;
+
+    let mut buttons = vec![];
+
+    if let Some(name) = twitter_account_name(&handler, &conn, from.id).await? {
+        let reauth = handler
+            .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+                get_message(&bundle, "settings-accounts-reauth", None).unwrap()
+            })
+            .await;
+
+        buttons.push(vec![
+            InlineKeyboardButton {
+                text: format!("❌ {}", name),
+                callback_data: Some("s:accounts:r:Twitter".into()),
+                ..Default::default()
+            },
+            InlineKeyboardButton {
+                text: reauth,
+                callback_data: Some("s:accounts:a:Twitter".into()),
+                ..Default::default()
+            },
+        ]);
+    }
+
+    let sites = SiteAccount::sites(&conn, from.id)
+        .await
+        .context("unable to query site accounts")?;
+
+    buttons.extend(sites.into_iter().map(|site| {
+        vec![InlineKeyboardButton {
+            text: format!("❌ {}", site),
+            callback_data: Some(format!("s:accounts:r:{}", site)),
+            ..Default::default()
+        }]
+    }));
+
+    if handler.secret_key.is_some() {
+        buttons.push(
+            LINKABLE_SITES
+                .iter()
+                .map(|site| InlineKeyboardButton {
+                    text: format!("➕ {}", site),
+                    callback_data: Some(format!("s:accounts:l:{}", site)),
+                    ..Default::default()
+                })
+                .collect(),
+        );
+    }
+
+    Ok(InlineKeyboardMarkup {
+        inline_keyboard: buttons,
+    })
+}
+
+/// Ask the user for the credentials of an account to link. Their reply is
+/// handled by the text handler.
+///
+/// Credentials are only asked for in a private chat, and only the session
+/// or cookies a site needs are requested, never a password.
+async fn start_link(
+    handler: &crate::MessageHandler,
+    callback_query: &CallbackQuery,
+    site: &str,
+) -> failure::Fallible<super::Status> {
+    let from = &callback_query.from;
+
+    let prompt = match site {
+        "FurAffinity" => "link-prompt-furaffinity",
+        "Inkbunny" => "link-prompt-inkbunny",
+        _ => return Ok(Completed),
+    };
+
+    let is_private = callback_query
+        .message
+        .as_ref()
+        .map(|message| message.chat.chat_type == ChatType::Private)
+        .unwrap_or(false);
+
+    let name = if handler.secret_key.is_none() {
+        Some("link-unavailable")
+    } else if !is_private {
+        Some("link-private")
+    } else {
+        None
+    };
+
+    if let Some(name) = name {
+        let text = handler
+            .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+                get_message(&bundle, name, None).unwrap()
             })
             .await;
 
@@ -98,84 +916,127 @@ async fn name(
             ..Default::default()
         };
 
-        let keyboard = name_keyboard(&handler, &callback_query.from).await?;
-
-        let edit_message = EditMessageReplyMarkup {
-            message_id: Some(reply_message.message_id),
-            chat_id: reply_message.chat_id(),
-            reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
-            ..Default::default()
-        };
-
-        futures::try_join!(
-            handler.make_request(&answer),
-            handler.make_request(&edit_message)
-        )
-        .context("unable to send answer or edit message")?;
+        handler
+            .make_request(&answer)
+            .await
+            .context("unable to answer link callback query")?;
 
         return Ok(Completed);
     }
 
+    let conn = handler
+        .conn
+        .check_out()
+        .await
+        .context("unable to check out database")?;
+
+    UserConfig::delete(&conn, UserConfigKey::LinkingSite, from.id).await?;
+    UserConfig::set(&conn, "linking-site", from.id, false, site).await?;
+
     let text = handler
-        .get_fluent_bundle(from, |bundle| {
-            get_message(&bundle, "settings-name", None).unwrap()
+        .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+            get_message(&bundle, prompt, None).unwrap()
         })
         .await;
 
-    let keyboard = name_keyboard(&handler, &callback_query.from).await?;
-
-    let edit_message = EditMessageText {
-        message_id: Some(reply_message.message_id),
-        chat_id: reply_message.chat_id(),
-        reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+    let send_message = SendMessage {
+        chat_id: from.id.into(),
         text,
+        reply_markup: Some(ReplyMarkup::ForceReply(ForceReply::selective())),
         ..Default::default()
     };
 
-    handler
-        .make_request(&edit_message)
-        .await
-        .context("unable to send setting message")?;
+    let answer = AnswerCallbackQuery {
+        callback_query_id: callback_query.id.clone(),
+        ..Default::default()
+    };
+
+    futures::try_join!(
+        handler.make_request(&send_message),
+        handler.make_request(&answer)
+    )
+    .context("unable to send link prompt or answer callback query")?;
 
     Ok(Completed)
 }
 
-async fn name_keyboard(
+/// Get a name for the user's Twitter account, if they have one. The screen
+/// name is loaded and saved the first time it's needed.
+async fn twitter_account_name(
     handler: &crate::MessageHandler,
-    from: &User,
-) -> failure::Fallible<InlineKeyboardMarkup> {
-    let conn = handler
-        .conn
-        .check_out()
-        .await
-        .context("unable to check out database")?;
+    conn: &PooledConnection,
+    user_id: i32,
+) -> failure::Fallible<Option<String>> {
+    let key = match &handler.secret_key {
+        Some(key) => key,
+        None => return Ok(None),
+    };
 
-    let enabled = UserConfig::get(&conn, UserConfigKey::SourceName, from.id)
+    let account = match Twitter::get_account(&conn, key, user_id)
         .await
-        .context("unable to query user source setting")?
-        .unwrap_or(false);
+        .context("unable to query twitter account")?
+    {
+        Some(account) => account,
+        None => return Ok(None),
+    };
 
-    let message_name = if enabled {
-        "settings-name-source"
-    } else {
-        "settings-name-site"
+    let screen_name = match (account.screen_name, handler.config.twitter_keys()) {
+        (Some(screen_name), _) => Some(screen_name),
+        (None, Some(consumer)) => {
+            let access = egg_mode::KeyPair::new(account.consumer_key, account.consumer_secret);
+
+            match crate::sites::Twitter::screen_name(consumer, access).await {
+                Ok(screen_name) => {
+                    Twitter::set_screen_name(&conn, user_id, &screen_name)
+                        .await
+                        .context("unable to save twitter screen name")?;
+                    Some(screen_name)
+                }
+                Err(err) => {
+                    tracing::warn!("unable to load twitter screen name: {:?}", err);
+                    None
+                }
+            }
+        }
+        (None, None) => None,
     };
 
-    let text = handler
-        .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
-            get_message(&bundle, &message_name, None).unwrap()
-        })
-        .await;
+    Ok(Some(match screen_name {
+        Some(screen_name) => format!("Twitter (@{})", screen_name),
+        None => "Twitter".into(),
+    }))
+}
 
-    let keyboard = vec![vec![InlineKeyboardButton {
-        text,
-        callback_data: Some("s:name:t".into()),
-        ..Default::default()
-    }]];
+/// Delete the user's Twitter account and revoke its token.
+async fn unlink_twitter(
+    handler: &crate::MessageHandler,
+    conn: &PooledConnection,
+    user_id: i32,
+) -> failure::Fallible<()> {
+    // The account should still be removed if it can't be loaded.
+    let account = match &handler.secret_key {
+        Some(key) => Twitter::get_account(&conn, key, user_id)
+            .await
+            .unwrap_or_else(|err| {
+                tracing::warn!("unable to load twitter account: {:?}", err);
+                None
+            }),
+        None => None,
+    };
 
-    Ok(InlineKeyboardMarkup {
-        inline_keyboard: keyboard,
-    })
+    Twitter::delete_account(&conn, user_id)
+        .await
+        .context("unable to delete twitter account")?;
+
+    if let (Some(account), Some(consumer)) = (account, handler.config.twitter_keys()) {
+        let access = egg_mode::KeyPair::new(account.consumer_key, account.consumer_secret);
+
+        if let Err(err) = crate::sites::Twitter::revoke_token(&consumer, &access).await {
+            tracing::warn!("unable to revoke twitter token: {:?}", err);
+        }
+    }
+
+    Ok(())
 }
 
 async fn order(
@@ -252,23 +1113,9 @@ async fn order(
                 .await
                 .context("unable to query user site sort order")?;
         let has_config = order.is_some();
-        let mut sites = match order {
-            Some(sites) => sites.iter().map(|item| item.parse().unwrap()).collect(),
-            None => Sites::default_order(),
-        };
-
-        let mut existing_pos = None;
-        for (idx, item) in sites.iter().enumerate() {
-            if item == &site {
-                existing_pos = Some(idx);
-            }
-        }
-
-        if let Some(pos) = existing_pos {
-            sites.remove(pos);
-        }
-
-        sites.insert(pos, site.clone());
+        let available = handler.sites.iter().map(|site| site.name());
+        let mut sites = Sites::order(order, available.clone());
+        Sites::move_site(&mut sites, site.clone(), pos, available);
 
         UserConfig::set(
             &conn,
@@ -295,7 +1142,7 @@ async fn order(
             ..Default::default()
         };
 
-        let keyboard = sort_order_keyboard(&handler.conn, callback_query.from.id).await?;
+        let keyboard = sort_order_keyboard(handler, callback_query.from.id).await?;
 
         let edit_message = EditMessageReplyMarkup {
             message_id: Some(reply_message.message_id),
@@ -319,7 +1166,7 @@ async fn order(
         })
         .await;
 
-    let keyboard = sort_order_keyboard(&handler.conn, callback_query.from.id).await?;
+    let keyboard = sort_order_keyboard(handler, callback_query.from.id).await?;
 
     let edit_message = EditMessageText {
         message_id: Some(reply_message.message_id),
@@ -343,6 +1190,272 @@ async fn order(
     Ok(Completed)
 }
 
+async fn channel(
+    handler: &crate::MessageHandler,
+    callback_query: &CallbackQuery,
+    data: &str,
+) -> failure::Fallible<super::Status> {
+    let reply_message = needs_field!(callback_query, message);
+    let from = &callback_query.from;
+
+    // Changing a setting uses callback data like s:channel:-100123:caption,
+    // or s:channel:-100123:site:FurAffinity to toggle preferring a site.
+    let mut parts = data.split(':').skip(2);
+    let channel_id: i64 = match parts.next().and_then(|id| id.parse().ok()) {
+        Some(channel_id) => channel_id,
+        None => return Ok(Completed),
+    };
+
+    // Admins can change, so this is checked again for every change.
+    let name = if is_channel_admin(&handler, channel_id, from.id).await? {
+        let conn = handler
+            .conn
+            .check_out()
+            .await
+            .context("unable to check out database")?;
+
+        let mut settings = ChannelSettings::load(&conn, channel_id).await?;
+
+        match (parts.next(), parts.next()) {
+            (Some("enabled"), _) => settings.enabled = !settings.enabled,
+            (Some("caption"), _) => settings.caption = !settings.caption,
+            (Some("append"), _) => settings.append = !settings.append,
+            (Some("artist"), _) => settings.artist = !settings.artist,
+            (Some("site"), Some(site)) if ChannelSettings::SITES.contains(&site) => {
+                match settings.sites.iter().position(|existing| existing == site) {
+                    Some(pos) => {
+                        settings.sites.remove(pos);
+                    }
+                    None => settings.sites.push(site.to_string()),
+                }
+            }
+            _ => return Ok(Completed),
+        }
+
+        settings.save(&conn, channel_id).await?;
+
+        let keyboard = channel_keyboard(&handler, &from, channel_id, &settings).await;
+
+        let edit_message = EditMessageReplyMarkup {
+            message_id: Some(reply_message.message_id),
+            chat_id: reply_message.chat_id(),
+            reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+            ..Default::default()
+        };
+
+        handler
+            .make_request(&edit_message)
+            .await
+            .context("unable to edit channel settings message")?;
+
+        "channel-updated"
+    } else {
+        "channel-not-admin"
+    };
+
+    let text = handler
+        .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+            get_message(&bundle, name, None).unwrap()
+        })
+        .await;
+
+    let answer = AnswerCallbackQuery {
+        callback_query_id: callback_query.id.clone(),
+        text: Some(text),
+        ..Default::default()
+    };
+
+    handler
+        .make_request(&answer)
+        .await
+        .context("unable to answer channel callback query")?;
+
+    Ok(Completed)
+}
+
+/// Send the settings for a channel. Commands can't be sent in channels, so
+/// admins pick the channel from a private chat by replying to a message
+/// forwarded from it or by sending its ID.
+async fn send_channel_message(
+    handler: &crate::MessageHandler,
+    message: &Message,
+) -> failure::Fallible<()> {
+    if message.chat.chat_type != ChatType::Private {
+        handler
+            .send_generic_reply(&message, "channel-private")
+            .await
+            .context("unable to send private chat message")?;
+        return Ok(());
+    }
+
+    let from = message.from.as_ref().unwrap();
+
+    let forwarded = message
+        .reply_to_message
+        .as_ref()
+        .and_then(|reply| reply.forward_from_chat.as_ref())
+        .filter(|chat| chat.chat_type == ChatType::Channel);
+
+    let channel_id = message
+        .text
+        .as_ref()
+        .and_then(|text| text.split_whitespace().nth(1))
+        .and_then(|channel_id| channel_id.parse::<i64>().ok());
+
+    let (channel_id, channel_name) = match (forwarded, channel_id) {
+        (Some(chat), _) => (
+            chat.id,
+            chat.title.clone().unwrap_or_else(|| chat.id.to_string()),
+        ),
+        (None, Some(channel_id)) => (channel_id, channel_id.to_string()),
+        (None, None) => {
+            handler
+                .send_generic_reply(&message, "channel-usage")
+                .await
+                .context("unable to send channel usage message")?;
+            return Ok(());
+        }
+    };
+
+    if !is_channel_admin(&handler, channel_id, from.id).await? {
+        handler
+            .send_generic_reply(&message, "channel-not-admin")
+            .await
+            .context("unable to send channel not admin message")?;
+        return Ok(());
+    }
+
+    let conn = handler
+        .conn
+        .check_out()
+        .await
+        .context("unable to check out database")?;
+    let settings = ChannelSettings::load(&conn, channel_id).await?;
+
+    let mut args = fluent::FluentArgs::new();
+    args.insert("channel", channel_name.into());
+
+    let text = handler
+        .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+            get_message(&bundle, "channel-settings", Some(args)).unwrap()
+        })
+        .await;
+
+    let keyboard = channel_keyboard(&handler, &from, channel_id, &settings).await;
+
+    let message = SendMessage {
+        chat_id: message.chat_id(),
+        text,
+        reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+        ..Default::default()
+    };
+
+    handler
+        .make_request(&message)
+        .await
+        .context("unable to send channel settings message")?;
+
+    Ok(())
+}
+
+/// Check if a user is an admin of a channel. Telegram only allows looking
+/// up members of chats the bot is in, so this is false for anything else.
+async fn is_channel_admin(
+    handler: &crate::MessageHandler,
+    channel_id: i64,
+    user_id: i32,
+) -> failure::Fallible<bool> {
+    let get_chat_member = GetChatMember {
+        chat_id: channel_id.into(),
+        user_id,
+    };
+
+    match handler.make_request(&get_chat_member).await {
+        Ok(chat_member) => Ok(chat_member.status.is_admin()),
+        Err(tgbotapi::Error::Telegram(_err)) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Callback data for changing a channel setting. Telegram limits callback
+/// data to 64 bytes.
+fn channel_callback_data(channel_id: i64, setting: &str) -> String {
+    format!("s:channel:{}:{}", channel_id, setting)
+}
+
+async fn channel_keyboard(
+    handler: &crate::MessageHandler,
+    from: &User,
+    channel_id: i64,
+    settings: &ChannelSettings,
+) -> InlineKeyboardMarkup {
+    let (enabled, mode, caption, artist) = handler
+        .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+            let mode = if settings.caption {
+                "channel-mode-caption"
+            } else {
+                "channel-mode-button"
+            };
+
+            let caption = if settings.append {
+                "channel-caption-append"
+            } else {
+                "channel-caption-replace"
+            };
+
+            (
+                get_message(&bundle, "channel-enabled", None).unwrap(),
+                get_message(&bundle, mode, None).unwrap(),
+                get_message(&bundle, caption, None).unwrap(),
+                get_message(&bundle, "channel-artist", None).unwrap(),
+            )
+        })
+        .await;
+
+    let checked = |enabled: bool, text: String| {
+        if enabled {
+            format!("✅ {}", text)
+        } else {
+            text
+        }
+    };
+
+    let button = |text: String, setting: &str| InlineKeyboardButton {
+        text,
+        callback_data: Some(channel_callback_data(channel_id, setting)),
+        ..Default::default()
+    };
+
+    let mut keyboard = vec![
+        vec![button(checked(settings.enabled, enabled), "enabled")],
+        vec![button(mode, "caption"), button(caption, "append")],
+        vec![button(checked(settings.artist, artist), "artist")],
+    ];
+
+    // Sites are shown two to a row.
+    let mut row = Vec::with_capacity(2);
+    for name in ChannelSettings::SITES {
+        let text = match settings.sites.iter().position(|site| site == name) {
+            Some(pos) => format!("✅ {}. {}", pos + 1, name),
+            None => name.to_string(),
+        };
+
+        row.push(button(text, &format!("site:{}", name)));
+
+        if row.len() == 2 {
+            keyboard.push(std::mem::replace(&mut row, Vec::with_capacity(2)));
+        }
+    }
+
+    if !row.is_empty() {
+        keyboard.push(row);
+    }
+
+    InlineKeyboardMarkup {
+        inline_keyboard: keyboard,
+    }
+}
+
 async fn send_settings_message(
     handler: &crate::MessageHandler,
     message: &Message,
@@ -352,28 +1465,58 @@ async fn send_settings_message(
         .as_ref()
         .and_then(|user| user.language_code.as_deref());
 
-    let (site_preference, source_name) = handler
+    let (site_preference, source_name, content_rating, blocklist, strictness, accounts) = handler
         .get_fluent_bundle(from, |bundle| {
             (
                 get_message(&bundle, "settings-site-preference", None).unwrap(),
                 get_message(&bundle, "settings-source-name", None).unwrap(),
+                get_message(&bundle, "settings-content-rating", None).unwrap(),
+                get_message(&bundle, "settings-blocked-tags", None).unwrap(),
+                get_message(&bundle, "settings-match-strictness", None).unwrap(),
+                get_message(&bundle, "settings-linked-accounts", None).unwrap(),
             )
         })
         .await;
 
     let keyboard = InlineKeyboardMarkup {
-        inline_keyboard: vec![vec![
-            InlineKeyboardButton {
-                text: site_preference,
-                callback_data: Some("s:order:".into()),
-                ..Default::default()
-            },
-            InlineKeyboardButton {
-                text: source_name,
-                callback_data: Some("s:name:".into()),
-                ..Default::default()
-            },
-        ]],
+        inline_keyboard: vec![
+            vec![
+                InlineKeyboardButton {
+                    text: site_preference,
+                    callback_data: Some("s:order:".into()),
+                    ..Default::default()
+                },
+                InlineKeyboardButton {
+                    text: source_name,
+                    callback_data: Some("s:name:".into()),
+                    ..Default::default()
+                },
+            ],
+            vec![
+                InlineKeyboardButton {
+                    text: content_rating,
+                    callback_data: Some("s:rating:".into()),
+                    ..Default::default()
+                },
+                InlineKeyboardButton {
+                    text: blocklist,
+                    callback_data: Some("s:blocklist:".into()),
+                    ..Default::default()
+                },
+            ],
+            vec![
+                InlineKeyboardButton {
+                    text: strictness,
+                    callback_data: Some("s:strictness:".into()),
+                    ..Default::default()
+                },
+                InlineKeyboardButton {
+                    text: accounts,
+                    callback_data: Some("s:accounts:".into()),
+                    ..Default::default()
+                },
+            ],
+        ],
     };
 
     let text = handler
@@ -403,21 +1546,10 @@ async fn send_settings_message(
 }
 
 async fn sort_order_keyboard(
-    conn: &quaint::pooled::Quaint,
+    handler: &crate::MessageHandler,
     user_id: i32,
 ) -> failure::Fallible<InlineKeyboardMarkup> {
-    let conn = conn
-        .check_out()
-        .await
-        .context("unable to check out database")?;
-
-    let row: Option<Vec<String>> = UserConfig::get(&conn, UserConfigKey::SiteSortOrder, user_id)
-        .await
-        .context("unable to query user sort order")?;
-    let sites = match row {
-        Some(row) => row.iter().map(|item| item.parse().unwrap()).collect(),
-        None => Sites::default_order(),
-    };
+    let sites = site_order(&handler.conn, &handler.sites, user_id).await?;
 
     let mut buttons = vec![];
 
@@ -457,3 +1589,16 @@ async fn sort_order_keyboard(
         inline_keyboard: buttons,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_channel_callback_data_length() {
+        use super::{channel_callback_data, ChannelSettings};
+
+        for site in ChannelSettings::SITES {
+            let data = channel_callback_data(i64::MIN, &format!("site:{}", site));
+            assert!(data.len() <= 64, "callback data was too long: {}", data);
+        }
+    }
+}
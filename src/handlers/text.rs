@@ -1,15 +1,23 @@
 use async_trait::async_trait;
-use failure::ResultExt;
+use failure::{Fail, ResultExt};
 use tgbotapi::{requests::*, *};
 use tokio01::runtime::current_thread::block_on_all;
 
 use super::Status::*;
-use crate::models::{Twitter, TwitterAccount};
+use crate::models::{
+    SiteAccount, Twitter, TwitterAccount, TwitterRequest, UserConfig, UserConfigKey,
+};
 use crate::needs_field;
 use crate::utils::get_message;
 
 pub struct TextHandler;
 
+/// Twitter refused to exchange a verifier for an access token, usually
+/// because it was stale, already used, or never issued for the request.
+#[derive(Debug, Fail)]
+#[fail(display = "twitter rejected the verifier: {}", _0)]
+pub struct VerifierRejected(String);
+
 #[async_trait]
 impl super::Handler for TextHandler {
     fn name(&self) -> &'static str {
@@ -29,7 +37,20 @@ impl super::Handler for TextHandler {
 
         let from = message.from.as_ref().unwrap();
 
-        if text.trim().parse::<i32>().is_err() {
+        if finish_link(handler, message, text).await? {
+            return Ok(Completed);
+        }
+
+        // Codes are only needed when Twitter can't redirect back to the bot.
+        if handler.config.twitter_callback().is_some() {
+            return Ok(Ignored);
+        }
+
+        let code = text.trim();
+        if message.chat.chat_type != ChatType::Private
+            || code.is_empty()
+            || !code.chars().all(|c| c.is_ascii_digit())
+        {
             tracing::trace!("got text that wasn't oob, ignoring");
             return Ok(Ignored);
         }
@@ -52,63 +73,239 @@ impl super::Handler for TextHandler {
 
         tracing::trace!("we had waiting Twitter code");
 
-        let request_token = egg_mode::KeyPair::new(row.request_key, row.request_secret);
+        if !finish_twitter_auth(
+            &handler,
+            from.id,
+            from.language_code.as_deref(),
+            row,
+            code,
+            Some(message.message_id),
+        )
+        .await?
+        {
+            return Ok(Ignored);
+        }
 
-        let con_token = egg_mode::KeyPair::new(
-            handler.config.twitter_consumer_key.clone(),
-            handler.config.twitter_consumer_secret.clone(),
-        );
+        let point = influxdb::Query::write_query(influxdb::Timestamp::Now, "twitter")
+            .add_tag("type", "added")
+            .add_field("duration", now.elapsed().as_millis() as i64);
 
-        let token = block_on_all(egg_mode::access_token(con_token, &request_token, text))
-            .context("unable to get twitter access token")?;
+        let _ = handler.influx.query(&point).await;
 
-        tracing::trace!("got token");
+        Ok(Completed)
+    }
+}
 
-        let access = match token.0 {
-            egg_mode::Token::Access { access, .. } => access,
-            _ => unimplemented!(),
-        };
+/// Complete signing in to Twitter from the callback Twitter redirects to
+/// after a user authorizes the bot.
+///
+/// Returns false if there was no pending request for the token.
+pub async fn twitter_callback(
+    handler: &crate::MessageHandler,
+    request_key: &str,
+    verifier: &str,
+) -> failure::Fallible<bool> {
+    let conn = handler
+        .conn
+        .check_out()
+        .await
+        .context("unable to check out database")?;
 
-        tracing::trace!("got access token");
+    let (user_id, row) = match Twitter::get_request_by_key(&conn, request_key)
+        .await
+        .context("unable to query twitter requests")?
+    {
+        Some(request) => request,
+        None => return Ok(false),
+    };
 
-        Twitter::set_account(
-            &conn,
-            from.id,
-            TwitterAccount {
-                consumer_key: access.key.to_string(),
-                consumer_secret: access.secret.to_string(),
-            },
-        )
+    let now = std::time::Instant::now();
+
+    if !finish_twitter_auth(&handler, user_id, None, row, verifier, None).await? {
+        return Ok(false);
+    }
+
+    let point = influxdb::Query::write_query(influxdb::Timestamp::Now, "twitter")
+        .add_tag("type", "callback")
+        .add_field("duration", now.elapsed().as_millis() as i64);
+
+    let _ = handler.influx.query(&point).await;
+
+    Ok(true)
+}
+
+/// Exchange a pending request and its verifier for an access token, save the
+/// account, and welcome the user.
+///
+/// Returns false if Twitter accounts can't currently be saved.
+async fn finish_twitter_auth(
+    handler: &crate::MessageHandler,
+    user_id: i32,
+    language_code: Option<&str>,
+    request: TwitterRequest,
+    verifier: &str,
+    reply_to_message_id: Option<i32>,
+) -> failure::Fallible<bool> {
+    let (con_token, key) = match (handler.config.twitter_keys(), &handler.secret_key) {
+        (Some(keys), Some(key)) => (keys, key),
+        _ => return Ok(false),
+    };
+
+    let request_token = egg_mode::KeyPair::new(request.request_key, request.request_secret);
+
+    let token = match block_on_all(egg_mode::access_token(con_token, &request_token, verifier)) {
+        Ok(token) => token,
+        Err(egg_mode::error::Error::BadStatus(status)) if status.is_client_error() => {
+            return Err(VerifierRejected(status.to_string()).into())
+        }
+        Err(egg_mode::error::Error::TwitterError(errors)) => {
+            return Err(VerifierRejected(errors.to_string()).into())
+        }
+        Err(err) => return Err(err.context("unable to get twitter access token").into()),
+    };
+
+    tracing::trace!("got token");
+
+    let access = match token.0 {
+        egg_mode::Token::Access { access, .. } => access,
+        _ => failure::bail!("twitter did not return an access token"),
+    };
+
+    tracing::trace!("got access token");
+
+    let conn = handler
+        .conn
+        .check_out()
         .await
-        .context("unable to set twitter account data")?;
-
-        let mut args = fluent::FluentArgs::new();
-        args.insert("userName", fluent::FluentValue::from(token.2));
-
-        let text = handler
-            .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
-                get_message(&bundle, "twitter-welcome", Some(args)).unwrap()
-            })
-            .await;
-
-        let message = SendMessage {
-            chat_id: from.id.into(),
-            text,
-            reply_to_message_id: Some(message.message_id),
-            ..Default::default()
-        };
+        .context("unable to check out database")?;
 
-        handler
-            .make_request(&message)
-            .await
-            .context("unable to send twitter welcome message")?;
+    Twitter::set_account(
+        &conn,
+        key,
+        user_id,
+        TwitterAccount {
+            consumer_key: access.key.to_string(),
+            consumer_secret: access.secret.to_string(),
+            screen_name: Some(token.2.clone()),
+        },
+    )
+    .await
+    .context("unable to set twitter account data")?;
 
-        let point = influxdb::Query::write_query(influxdb::Timestamp::Now, "twitter")
-            .add_tag("type", "added")
-            .add_field("duration", now.elapsed().as_millis() as i64);
+    let mut args = fluent::FluentArgs::new();
+    args.insert("userName", fluent::FluentValue::from(token.2));
 
-        let _ = handler.influx.query(&point).await;
+    let text = handler
+        .get_fluent_bundle(language_code, |bundle| {
+            get_message(&bundle, "twitter-welcome", Some(args)).unwrap()
+        })
+        .await;
 
-        Ok(Completed)
+    let message = SendMessage {
+        chat_id: user_id.into(),
+        text,
+        reply_to_message_id,
+        ..Default::default()
+    };
+
+    handler
+        .make_request(&message)
+        .await
+        .context("unable to send twitter welcome message")?;
+
+    Ok(true)
+}
+
+/// Save the credentials a user replied with after choosing a site to link
+/// from /settings.
+///
+/// Returns if the message was a reply to a pending link prompt.
+async fn finish_link(
+    handler: &crate::MessageHandler,
+    message: &Message,
+    text: &str,
+) -> failure::Fallible<bool> {
+    let from = message.from.as_ref().unwrap();
+
+    if message.chat.chat_type != ChatType::Private {
+        return Ok(false);
+    }
+
+    let is_prompt_reply = message
+        .reply_to_message
+        .as_ref()
+        .and_then(|reply| reply.from.as_ref())
+        .map(|user| user.id == handler.bot_user.id)
+        .unwrap_or(false);
+
+    let key = match &handler.secret_key {
+        Some(key) if is_prompt_reply => key,
+        _ => return Ok(false),
+    };
+
+    let conn = handler
+        .conn
+        .check_out()
+        .await
+        .context("unable to check out database")?;
+
+    let site: String = match UserConfig::get(&conn, UserConfigKey::LinkingSite, from.id)
+        .await
+        .context("unable to get linking site")?
+    {
+        Some(site) => site,
+        None => return Ok(false),
+    };
+
+    UserConfig::delete(&conn, UserConfigKey::LinkingSite, from.id).await?;
+
+    let delete_message = DeleteMessage {
+        chat_id: message.chat_id(),
+        message_id: message.message_id,
+    };
+
+    if let Err(err) = handler.make_request(&delete_message).await {
+        tracing::warn!("unable to delete link message: {:?}", err);
     }
+
+    let parts: Vec<&str> = text.split_whitespace().collect();
+
+    let secret = match (site.as_str(), parts.as_slice()) {
+        ("FurAffinity", [a, b]) => Some(crate::sites::FurAffinity::account_secret(a, b)),
+        ("Inkbunny", [sid]) => Some(sid.to_string()),
+        _ => None,
+    };
+
+    let name = match secret {
+        Some(secret) => {
+            SiteAccount::set(&conn, key, from.id, &site, &secret)
+                .await
+                .context("unable to save site account")?;
+
+            "link-success"
+        }
+        None => "link-invalid",
+    };
+
+    let mut args = fluent::FluentArgs::new();
+    args.insert("site", fluent::FluentValue::from(site));
+
+    let text = handler
+        .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+            get_message(&bundle, name, Some(args)).unwrap()
+        })
+        .await;
+
+    let send_message = SendMessage {
+        chat_id: message.chat_id(),
+        text,
+        ..Default::default()
+    };
+
+    handler
+        .make_request(&send_message)
+        .await
+        .context("unable to send link result")?;
+
+    Ok(true)
 }
@@ -5,17 +5,20 @@ use sites::{PostInfo, Site};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tgbotapi::{requests::*, *};
-use tokio::sync::{Mutex, RwLock};
+use tokio::sync::RwLock;
 use tracing_futures::Instrument;
 use unic_langid::LanguageIdentifier;
 
 #[macro_use]
 extern crate failure;
 
+mod blocklist;
+mod crypto;
 mod handlers;
 mod migrations;
 pub mod models;
 mod sites;
+mod storage;
 mod utils;
 mod video;
 
@@ -53,15 +56,34 @@ static L10N_LANGS: &[&str] = &["en-US"];
 #[derive(serde::Deserialize, Debug, Clone)]
 pub struct Config {
     // Site config
-    pub fa_a: String,
-    pub fa_b: String,
-    pub weasyl_apitoken: String,
-    pub inkbunny_username: String,
-    pub inkbunny_password: String,
+    pub sites: Option<Vec<String>>,
+    pub fa_a: Option<String>,
+    pub fa_b: Option<String>,
+    pub weasyl_apitoken: Option<String>,
+    pub inkbunny_username: Option<String>,
+    pub inkbunny_password: Option<String>,
+    pub pixiv_session: Option<String>,
+    pub deviantart_client_id: Option<String>,
+    pub deviantart_client_secret: Option<String>,
+    pub danbooru_hosts: Option<Vec<String>>,
+    pub gelbooru_hosts: Option<Vec<String>>,
+    pub philomena_hosts: Option<Vec<String>>,
+    pub tumblr_api_key: Option<String>,
+    pub imgur_client_id: Option<String>,
+
+    // Linked accounts
+    pub account_key: Option<String>,
+
+    // Site request handling
+    pub site_timeout: Option<u64>,
+    pub site_retries: Option<u32>,
+    pub site_breaker_threshold: Option<u32>,
+    pub site_breaker_cooldown: Option<u64>,
 
     // Twitter config
-    pub twitter_consumer_key: String,
-    pub twitter_consumer_secret: String,
+    pub twitter_consumer_key: Option<String>,
+    pub twitter_consumer_secret: Option<String>,
+    pub twitter_callback: Option<String>,
 
     // InfluxDB config
     influx_host: String,
@@ -95,6 +117,28 @@ pub struct Config {
     pub database: String,
 }
 
+impl Config {
+    /// Get the Twitter app keys, if they were configured.
+    pub fn twitter_keys(&self) -> Option<egg_mode::KeyPair> {
+        match (&self.twitter_consumer_key, &self.twitter_consumer_secret) {
+            (Some(key), Some(secret)) => Some(egg_mode::KeyPair::new(key.clone(), secret.clone())),
+            _ => None,
+        }
+    }
+
+    /// Get the public URL Twitter should redirect to after signing in.
+    ///
+    /// This is only used with webhooks, as the callback is served by the
+    /// same HTTP server. Otherwise users have to send the code they're given.
+    pub fn twitter_callback(&self) -> Option<&str> {
+        if self.use_webhooks.unwrap_or(false) {
+            self.twitter_callback.as_deref()
+        } else {
+            None
+        }
+    }
+}
+
 // MARK: Initialization
 
 /// Configure tracing with Jaeger.
@@ -153,12 +197,229 @@ fn configure_tracing(collector: String) {
         .expect("Unable to set default tracing subscriber");
 }
 
-async fn run_migrations(database: &str) {
+/// Create a site for every configured host of a booru, or None if no hosts
+/// were configured.
+fn booru_sites(kind: sites::BooruKind, hosts: &Option<Vec<String>>) -> Option<Vec<BoxedSite>> {
+    let hosts = hosts.as_ref()?;
+
+    if hosts.is_empty() {
+        tracing::warn!(?kind, "empty booru host list in configuration");
+    }
+
+    let mut sites = Vec::with_capacity(hosts.len());
+
+    for config in hosts {
+        let (host, file_host) = match sites::Booru::parse_config(config) {
+            Some(hosts) => hosts,
+            None => {
+                tracing::warn!(?kind, host = %config, "invalid booru host in configuration");
+                continue;
+            }
+        };
+
+        // Sites are only created once at startup, so each name is only
+        // allocated once.
+        let name: &'static str = Box::leak(sites::Booru::default_name(&host).into_boxed_str());
+
+        sites.push(Box::new(sites::Booru::new(kind, host, &file_host, name)) as BoxedSite);
+    }
+
+    Some(sites)
+}
+
+/// Names of every site that can be enabled, in the default order.
+const SITE_NAMES: &[&str] = &[
+    "e621",
+    "danbooru",
+    "gelbooru",
+    "philomena",
+    "furaffinity",
+    "weasyl",
+    "twitter",
+    "inkbunny",
+    "mastodon",
+    "newgrounds",
+    "sofurry",
+    "furrynetwork",
+    "deviantart",
+    "pixiv",
+    "tumblr",
+    "reddit",
+    "imgur",
+    "bluesky",
+    "activitypub",
+    "direct",
+];
+
+/// Create a site from its name, or None if it is missing credentials.
+fn create_site(
+    name: &str,
+    config: &Config,
+    pool: &quaint::pooled::Quaint,
+    fapi: &Arc<fuzzysearch::FuzzySearch>,
+    secret_key: &Option<Arc<crypto::SecretKey>>,
+) -> Option<Vec<BoxedSite>> {
+    let accounts = secret_key
+        .clone()
+        .map(|key| sites::LinkedAccounts::new(pool.clone(), key));
+
+    let site: BoxedSite = match name {
+        "e621" => Box::new(sites::E621::new()),
+        "danbooru" => return booru_sites(sites::BooruKind::Danbooru, &config.danbooru_hosts),
+        "gelbooru" => return booru_sites(sites::BooruKind::Gelbooru, &config.gelbooru_hosts),
+        "philomena" => return booru_sites(sites::BooruKind::Philomena, &config.philomena_hosts),
+        "furaffinity" => Box::new(sites::FurAffinity::new(
+            (config.fa_a.clone()?, config.fa_b.clone()?),
+            config.fautil_apitoken.clone(),
+            accounts,
+        )),
+        "weasyl" => Box::new(sites::Weasyl::new(config.weasyl_apitoken.clone()?)),
+        "twitter" => Box::new(sites::Twitter::new(
+            config.twitter_consumer_key.clone()?,
+            config.twitter_consumer_secret.clone()?,
+            accounts,
+        )),
+        "inkbunny" => Box::new(sites::Inkbunny::new(
+            config.inkbunny_username.clone()?,
+            config.inkbunny_password.clone()?,
+            accounts,
+        )),
+        "mastodon" => Box::new(sites::Mastodon::new()),
+        "newgrounds" => Box::new(sites::Newgrounds::new()),
+        "sofurry" => Box::new(sites::SoFurry::new()),
+        "furrynetwork" => Box::new(sites::FurryNetwork::new()),
+        "deviantart" => Box::new(sites::DeviantArt::new(
+            config.deviantart_client_id.clone()?,
+            config.deviantart_client_secret.clone()?,
+        )),
+        "pixiv" => Box::new(sites::Pixiv::new(
+            config.pixiv_session.clone(),
+            storage::Storage::new(config),
+        )),
+        "tumblr" => Box::new(sites::Tumblr::new(config.tumblr_api_key.clone()?)),
+        "reddit" => Box::new(sites::Reddit::new()),
+        "imgur" => Box::new(sites::Imgur::new(config.imgur_client_id.clone()?)),
+        "bluesky" => Box::new(sites::Bluesky::new()),
+        "activitypub" => Box::new(sites::ActivityPub::new()),
+        "direct" => Box::new(sites::Direct::new(fapi.clone())),
+        _ => unreachable!("site names should be checked before creating sites"),
+    };
+
+    Some(vec![site])
+}
+
+/// Create every enabled site.
+///
+/// Sites are enabled and ordered by the `SITES` list when it is set,
+/// otherwise every site is enabled. Only enabled sites are created, and any
+/// that are missing credentials are skipped.
+fn build_sites(
+    config: &Config,
+    pool: &quaint::pooled::Quaint,
+    fapi: &Arc<fuzzysearch::FuzzySearch>,
+    secret_key: &Option<Arc<crypto::SecretKey>>,
+) -> Vec<BoxedSite> {
+    let enabled: Vec<String> = match &config.sites {
+        Some(sites) => sites
+            .iter()
+            .map(|name| name.trim().to_lowercase())
+            .collect(),
+        None => SITE_NAMES.iter().map(|name| name.to_string()).collect(),
+    };
+
+    let mut sites = vec![];
+
+    for name in enabled {
+        if !SITE_NAMES.contains(&name.as_str()) {
+            tracing::warn!(site = %name, "unknown site in configuration");
+            continue;
+        }
+
+        match create_site(&name, config, pool, fapi, secret_key) {
+            Some(created) => sites.extend(created),
+            None => tracing::warn!(site = %name, "missing credentials, disabling site"),
+        }
+    }
+
+    sites
+}
+
+/// Build the timeout, retry, and circuit breaker settings for sites.
+fn resilience_config(config: &Config) -> sites::ResilienceConfig {
+    use std::time::Duration;
+
+    let default = sites::ResilienceConfig::default();
+
+    sites::ResilienceConfig {
+        timeout: config
+            .site_timeout
+            .map(Duration::from_secs)
+            .unwrap_or(default.timeout),
+        retries: config.site_retries.unwrap_or(default.retries),
+        failure_threshold: config
+            .site_breaker_threshold
+            .unwrap_or(default.failure_threshold),
+        cooldown: config
+            .site_breaker_cooldown
+            .map(Duration::from_secs)
+            .unwrap_or(default.cooldown),
+        ..default
+    }
+}
+
+/// Load the key used to encrypt stored secrets, if one was configured.
+fn load_secret_key(config: &Config) -> Option<crypto::SecretKey> {
+    config
+        .account_key
+        .as_deref()
+        .map(|key| crypto::SecretKey::from_base64(key).expect("Unable to parse ACCOUNT_KEY"))
+}
+
+/// Migrate the database and encrypt any secrets that are still stored in
+/// plain text.
+async fn run_migrations(database: &str, secret_key: Option<&crypto::SecretKey>) {
     let mut conn = rusqlite::Connection::open(database).expect("Unable to open database");
 
     migrations::runner()
         .run(&mut conn)
         .expect("Unable to migrate database");
+
+    match secret_key {
+        Some(key) => {
+            let count =
+                crypto::encrypt_stored(&mut conn, key).expect("Unable to encrypt stored secrets");
+            if count > 0 {
+                tracing::info!(count, "encrypted stored secrets");
+            }
+        }
+        None => tracing::warn!("missing ACCOUNT_KEY, disabling linked accounts"),
+    }
+}
+
+/// Re-encrypt every stored secret with the key in `NEW_ACCOUNT_KEY`,
+/// returning the number of rows that were encrypted.
+///
+/// Run with `foxbot rotate-key` while the bot is stopped, then replace
+/// `ACCOUNT_KEY` with the new key.
+async fn rotate_key(config: &Config) -> failure::Fallible<usize> {
+    let old = match &config.account_key {
+        Some(key) => crypto::SecretKey::from_base64(key)
+            .map_err(|err| failure::format_err!("unable to parse ACCOUNT_KEY: {}", err))?,
+        None => failure::bail!("missing ACCOUNT_KEY"),
+    };
+
+    let new = std::env::var("NEW_ACCOUNT_KEY")
+        .map_err(|_err| failure::format_err!("missing NEW_ACCOUNT_KEY"))?;
+    let new = crypto::SecretKey::from_base64(&new)
+        .map_err(|err| failure::format_err!("unable to parse NEW_ACCOUNT_KEY: {}", err))?;
+
+    run_migrations(&config.database, Some(&old)).await;
+
+    let mut conn = rusqlite::Connection::open(&config.database)
+        .map_err(|err| failure::format_err!("unable to open database: {}", err))?;
+
+    crypto::rotate(&mut conn, &old, &new)
+        .map_err(|err| failure::format_err!("unable to rotate secrets: {}", err))
 }
 
 #[tokio::main]
@@ -168,6 +429,23 @@ async fn main() {
         Err(err) => panic!("{:#?}", err),
     };
 
+    if std::env::args().nth(1).as_deref() == Some("rotate-key") {
+        tracing_subscriber::fmt::init();
+
+        match rotate_key(&config).await {
+            Ok(count) => tracing::info!(
+                count,
+                "encrypted rows with the new key, set ACCOUNT_KEY to NEW_ACCOUNT_KEY before starting"
+            ),
+            Err(err) => {
+                tracing::error!("{}", err);
+                std::process::exit(1);
+            }
+        }
+
+        return;
+    }
+
     let jaeger_collector = match &config.jaeger_collector {
         Some(collector) => collector.to_owned(),
         _ => panic!("Missing JAEGER_COLLECTOR"),
@@ -175,7 +453,9 @@ async fn main() {
 
     configure_tracing(jaeger_collector);
 
-    run_migrations(&config.database).await;
+    let secret_key = load_secret_key(&config).map(Arc::new);
+
+    run_migrations(&config.database, secret_key.as_deref()).await;
 
     let pool = quaint::pooled::Quaint::builder(&format!("file:{}", config.database))
         .expect("Unable to connect to database")
@@ -185,25 +465,13 @@ async fn main() {
         config.fautil_apitoken.clone(),
     ));
 
-    let sites: Vec<BoxedSite> = vec![
-        Box::new(sites::E621::new()),
-        Box::new(sites::FurAffinity::new(
-            (config.fa_a.clone(), config.fa_b.clone()),
-            config.fautil_apitoken.clone(),
-        )),
-        Box::new(sites::Weasyl::new(config.weasyl_apitoken.clone())),
-        Box::new(sites::Twitter::new(
-            config.twitter_consumer_key.clone(),
-            config.twitter_consumer_secret.clone(),
-            pool.clone(),
-        )),
-        Box::new(sites::Inkbunny::new(
-            config.inkbunny_username.clone(),
-            config.inkbunny_password.clone(),
-        )),
-        Box::new(sites::Mastodon::new()),
-        Box::new(sites::Direct::new(fapi.clone())),
-    ];
+    let sites = build_sites(&config, &pool, &fapi, &secret_key);
+
+    let resilience = resilience_config(&config);
+    let sites: Vec<BoxedSite> = sites
+        .into_iter()
+        .map(|site| Box::new(sites::ResilientSite::new(site, resilience.clone())) as BoxedSite)
+        .collect();
 
     let bot = Arc::new(Telegram::new(config.telegram_apitoken.clone()));
 
@@ -259,6 +527,7 @@ async fn main() {
         langs,
         best_lang: RwLock::new(HashMap::new()),
         handlers,
+        media_groups: handlers::MediaGroups::default(),
         config: config.clone(),
 
         bot: bot.clone(),
@@ -266,8 +535,9 @@ async fn main() {
         influx: Arc::new(influx),
         finder,
 
-        sites: Mutex::new(sites),
+        sites,
         conn: pool,
+        secret_key,
     });
 
     let _guard = if let Some(dsn) = config.sentry_dsn {
@@ -367,6 +637,11 @@ async fn handle_request(
 
             Ok(Response::new(Body::from("✓")))
         }
+        (&hyper::Method::GET, "/twitter/callback")
+            if handler.config.twitter_callback().is_some() =>
+        {
+            Ok(twitter_callback(&req, &handler).await)
+        }
         _ => {
             let mut not_found = Response::default();
             *not_found.status_mut() = StatusCode::NOT_FOUND;
@@ -442,6 +717,70 @@ async fn receive_webhook(host: String, secret: String, handler: Arc<MessageHandl
     }
 }
 
+/// Finish signing in to Twitter after the user authorizes the bot, sending
+/// them back to Telegram.
+async fn twitter_callback(
+    req: &hyper::Request<hyper::Body>,
+    handler: &MessageHandler,
+) -> hyper::Response<hyper::Body> {
+    use hyper::{header, Body, Response, StatusCode};
+
+    // The URI is only a path, so it needs a base to be parsed.
+    let params: HashMap<String, String> =
+        match reqwest::Url::parse(&format!("http://localhost{}", req.uri())) {
+            Ok(url) => url.query_pairs().into_owned().collect(),
+            Err(_) => HashMap::new(),
+        };
+
+    let completed = match (params.get("oauth_token"), params.get("oauth_verifier")) {
+        _ if params.contains_key("denied") => Ok(false),
+        (Some(token), Some(verifier)) => {
+            handlers::twitter_callback(&handler, token, verifier).await
+        }
+        _ => Ok(false),
+    };
+
+    let name = match completed {
+        // Twitter adds denied instead of a verifier when the user cancels.
+        Ok(false) if params.contains_key("denied") => "twitter-callback-denied",
+        Ok(true) => {
+            let mut resp = Response::default();
+            *resp.status_mut() = StatusCode::FOUND;
+            resp.headers_mut().insert(
+                header::LOCATION,
+                header::HeaderValue::from_str(&format!(
+                    "https://t.me/{}",
+                    handler.bot_user.username.as_deref().unwrap_or_default()
+                ))
+                .unwrap(),
+            );
+            return resp;
+        }
+        Ok(false) => "twitter-callback-expired",
+        // Anyone can request the callback, so stale or forged verifiers are
+        // expected and not worth reporting.
+        Err(err) if err.downcast_ref::<handlers::VerifierRejected>().is_some() => {
+            tracing::warn!("twitter rejected callback verifier: {}", err);
+            "twitter-callback-expired"
+        }
+        Err(err) => {
+            tracing::error!("unable to finish twitter callback: {:?}", err);
+            capture_error(&err);
+            "twitter-callback-error"
+        }
+    };
+
+    let text = handler
+        .get_fluent_bundle(None, |bundle| {
+            utils::get_message(&bundle, name, None).unwrap()
+        })
+        .await;
+
+    let mut resp = Response::new(Body::from(text));
+    *resp.status_mut() = StatusCode::BAD_REQUEST;
+    resp
+}
+
 /// Start polling updates using Bot API long polling.
 async fn poll_updates(bot: Arc<Telegram>, handler: Arc<MessageHandler>) {
     let mut update_req = GetUpdates::default();
@@ -529,6 +868,8 @@ pub struct MessageHandler {
     langs: HashMap<LanguageIdentifier, Vec<String>>,
     best_lang: RwLock<HashMap<String, fluent::FluentBundle<fluent::FluentResource>>>,
     handlers: Vec<BoxedHandler>,
+    /// Media groups waiting for the rest of their messages.
+    pub media_groups: handlers::MediaGroups,
 
     // API clients
     pub bot: Arc<Telegram>,
@@ -537,11 +878,13 @@ pub struct MessageHandler {
     pub finder: linkify::LinkFinder,
 
     // Configuration
-    pub sites: Mutex<Vec<BoxedSite>>, // We always need mutable access, no reason to use a RwLock
+    pub sites: Vec<BoxedSite>,
     pub config: Config,
 
     // Storage
     pub conn: quaint::pooled::Quaint,
+    /// Key for linked account secrets, if linking accounts is enabled.
+    pub secret_key: Option<Arc<crypto::SecretKey>>,
 }
 
 impl MessageHandler {
@@ -0,0 +1,17 @@
+use barrel::{types, Migration, backend::Sqlite};
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+
+    m.create_table("recent_photo", |t| {
+        t.add_column("id", types::primary());
+        t.add_column("chat_id", types::integer().nullable(false));
+        t.add_column("message_id", types::integer().nullable(false));
+        t.add_column("file_id", types::varchar(255).nullable(false));
+        t.add_column("file_unique_id", types::varchar(255).nullable(false));
+
+        t.add_index("recent_photo_lookup", types::index(vec!["chat_id"]).nullable(false));
+    });
+
+    m.make::<Sqlite>()
+}
@@ -0,0 +1,16 @@
+use barrel::{types, Migration, backend::Sqlite};
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+
+    m.create_table("site_account", |t| {
+        t.add_column("id", types::primary());
+        t.add_column("user_id", types::integer().nullable(false));
+        t.add_column("site", types::varchar(255).nullable(false));
+        t.add_column("secret", types::text().nullable(false));
+
+        t.add_index("site_account_lookup", types::index(vec!["user_id", "site"]).unique(true).nullable(false));
+    });
+
+    m.make::<Sqlite>()
+}
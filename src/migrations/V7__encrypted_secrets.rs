@@ -0,0 +1,17 @@
+use barrel::{types, Migration, backend::Sqlite};
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+
+    // Twitter accounts were stored in plain text and are encrypted on
+    // startup, site accounts have always been encrypted.
+    m.change_table("twitter_account", |t| {
+        t.add_column("encrypted", types::boolean().nullable(false).default(false));
+    });
+
+    m.change_table("site_account", |t| {
+        t.add_column("encrypted", types::boolean().nullable(false).default(true));
+    });
+
+    m.make::<Sqlite>()
+}
@@ -0,0 +1,11 @@
+use barrel::{types, Migration, backend::Sqlite};
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+
+    m.change_table("twitter_account", |t| {
+        t.add_column("screen_name", types::varchar(255).nullable(true));
+    });
+
+    m.make::<Sqlite>()
+}
@@ -0,0 +1,15 @@
+use barrel::{types, Migration, backend::Sqlite};
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+
+    m.create_table("frame_cache", |t| {
+        t.add_column("id", types::primary());
+        t.add_column("file_id", types::varchar(255).nullable(false));
+        t.add_column("hash", types::integer().nullable(true));
+
+        t.add_index("frame_cache_lookup", types::index(vec!["file_id"]).nullable(false));
+    });
+
+    m.make::<Sqlite>()
+}
@@ -6,15 +6,18 @@ static USER_CONFIG: &str = "user_config";
 static TWITTER_ACCOUNT: &str = "twitter_account";
 static TWITTER_AUTH: &str = "twitter_auth";
 static FILE_ID_CACHE: &str = "file_id_cache";
+static FRAME_CACHE: &str = "frame_cache";
+static RECENT_PHOTO: &str = "recent_photo";
 static GROUP_CONFIG: &str = "group_config";
+static SITE_ACCOUNT: &str = "site_account";
 
-/// Each available site, for configuration usage.
+/// A site that results can be ordered by, identified by the name it was
+/// registered with.
+///
+/// Any name can be parsed, so stored orders keep working as sites are added
+/// or removed.
 #[derive(Clone, Debug, PartialEq)]
-pub enum Sites {
-    FurAffinity,
-    E621,
-    Twitter,
-}
+pub struct Sites(String);
 
 impl serde::Serialize for Sites {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -25,35 +28,73 @@ impl serde::Serialize for Sites {
     }
 }
 
-#[derive(Debug)]
-pub struct ParseSitesError;
-
 impl std::str::FromStr for Sites {
-    type Err = ParseSitesError;
+    type Err = std::convert::Infallible;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "FurAffinity" => Ok(Sites::FurAffinity),
-            "e621" => Ok(Sites::E621),
-            "Twitter" => Ok(Sites::Twitter),
-            _ => Err(ParseSitesError),
-        }
+        Ok(Sites(s.to_string()))
     }
 }
 
 impl Sites {
+    /// Sites shown first for anyone who hasn't chosen an order.
+    const PREFERRED: &'static [&'static str] = &["FurAffinity", "e621", "Twitter"];
+
     /// Get the user-understandable name of the site.
-    pub fn as_str(&self) -> &'static str {
-        match *self {
-            Sites::FurAffinity => "FurAffinity",
-            Sites::E621 => "e621",
-            Sites::Twitter => "Twitter",
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Get the order to store for every site. Sites in the stored order keep
+    /// their position, including sites that aren't currently available so
+    /// they keep their place if they return, and new sites are added to the
+    /// end.
+    pub fn order<'a, I>(stored: Option<Vec<String>>, available: I) -> Vec<Sites>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let stored = stored.unwrap_or_else(|| {
+            Self::PREFERRED
+                .iter()
+                .map(|name| name.to_string())
+                .collect()
+        });
+
+        let mut order: Vec<Sites> = vec![];
+        for name in stored {
+            if !order.iter().any(|site| site.0 == name) {
+                order.push(Sites(name));
+            }
+        }
+
+        for name in available {
+            if !order.iter().any(|site| site.0 == name) {
+                order.push(Sites(name.to_string()));
+            }
         }
+
+        order
     }
 
-    /// The bot's default site ordering.
-    pub fn default_order() -> Vec<Sites> {
-        vec![Sites::FurAffinity, Sites::E621, Sites::Twitter]
+    /// Move a site to a position among the available sites, leaving sites
+    /// that aren't available where they are.
+    pub fn move_site<'a, I>(order: &mut Vec<Sites>, site: Sites, pos: usize, available: I)
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let available: Vec<&str> = available.into_iter().collect();
+
+        order.retain(|item| item != &site);
+
+        let idx = order
+            .iter()
+            .enumerate()
+            .filter(|(_idx, item)| available.contains(&item.as_str()))
+            .nth(pos)
+            .map(|(idx, _item)| idx)
+            .unwrap_or_else(|| order.len());
+
+        order.insert(idx, site);
     }
 }
 
@@ -101,6 +142,10 @@ pub struct UserConfig;
 pub enum UserConfigKey {
     SourceName,
     SiteSortOrder,
+    MaxRating,
+    Blocklist,
+    LinkingSite,
+    MatchStrictness,
 }
 
 impl UserConfigKey {
@@ -108,6 +153,10 @@ impl UserConfigKey {
         match self {
             UserConfigKey::SourceName => "source-name",
             UserConfigKey::SiteSortOrder => "site-sort-order",
+            UserConfigKey::MaxRating => "max-rating",
+            UserConfigKey::Blocklist => "blocklist",
+            UserConfigKey::LinkingSite => "linking-site",
+            UserConfigKey::MatchStrictness => "match-strictness",
         }
     }
 }
@@ -157,6 +206,19 @@ impl UserConfig {
 
         Ok(())
     }
+
+    pub async fn delete(
+        conn: &PooledConnection,
+        key: UserConfigKey,
+        user_id: i32,
+    ) -> failure::Fallible<()> {
+        let delete = Delete::from_table(USER_CONFIG)
+            .so_that("user_id".equals(user_id).and("name".equals(key.as_str())));
+        Config::delete(&conn, delete)
+            .await
+            .context("unable to delete user config")?;
+        Ok(())
+    }
 }
 
 pub struct GroupConfig;
@@ -165,6 +227,10 @@ pub enum GroupConfigKey {
     GroupAdd,
     IsAdmin,
     GroupNoPreviews,
+    MaxRating,
+    Blocklist,
+    MatchStrictness,
+    ChannelSettings,
 }
 
 impl GroupConfigKey {
@@ -173,6 +239,10 @@ impl GroupConfigKey {
             GroupConfigKey::GroupAdd => "group_add",
             GroupConfigKey::IsAdmin => "is_admin",
             GroupConfigKey::GroupNoPreviews => "group_no_previews",
+            GroupConfigKey::MaxRating => "group_max_rating",
+            GroupConfigKey::Blocklist => "group_blocklist",
+            GroupConfigKey::MatchStrictness => "group_match_strictness",
+            GroupConfigKey::ChannelSettings => "channel_settings",
         }
     }
 }
@@ -236,6 +306,8 @@ impl GroupConfig {
 pub struct TwitterAccount {
     pub consumer_key: String,
     pub consumer_secret: String,
+    /// Screen name of the account, if it has been loaded.
+    pub screen_name: Option<String>,
 }
 
 pub struct TwitterRequest {
@@ -246,14 +318,16 @@ pub struct TwitterRequest {
 pub struct Twitter;
 
 impl Twitter {
-    /// Look up a user's Twitter credentials.
+    /// Look up and decrypt a user's Twitter credentials.
     pub async fn get_account(
         conn: &PooledConnection,
+        key: &crate::crypto::SecretKey,
         user_id: i32,
     ) -> failure::Fallible<Option<TwitterAccount>> {
         let select = Select::from_table(TWITTER_ACCOUNT)
             .column("consumer_key")
             .column("consumer_secret")
+            .column("screen_name")
             .so_that("user_id".equals(user_id));
         let rows = conn
             .select(select)
@@ -268,9 +342,17 @@ impl Twitter {
             .into_single()
             .context("impossible missing twitter consumer")?;
 
+        let consumer_key = key
+            .decrypt(&row["consumer_key"].to_string().unwrap())
+            .context("unable to decrypt twitter consumer key")?;
+        let consumer_secret = key
+            .decrypt(&row["consumer_secret"].to_string().unwrap())
+            .context("unable to decrypt twitter consumer secret")?;
+
         Ok(Some(TwitterAccount {
-            consumer_key: row["consumer_key"].to_string().unwrap(),
-            consumer_secret: row["consumer_secret"].to_string().unwrap(),
+            consumer_key,
+            consumer_secret,
+            screen_name: row["screen_name"].to_string(),
         }))
     }
 
@@ -302,17 +384,57 @@ impl Twitter {
         }))
     }
 
+    /// Look up a pending request by its key, returning the user who made it.
+    pub async fn get_request_by_key(
+        conn: &PooledConnection,
+        request_key: &str,
+    ) -> failure::Fallible<Option<(i32, TwitterRequest)>> {
+        let select = Select::from_table(TWITTER_AUTH)
+            .column("user_id")
+            .column("request_key")
+            .column("request_secret")
+            .so_that("request_key".equals(request_key));
+        let rows = conn
+            .select(select)
+            .await
+            .context("unable to select twitter request")?;
+
+        if rows.is_empty() {
+            return Ok(None);
+        }
+
+        let row = rows
+            .into_single()
+            .context("impossible missing twitter request")?;
+
+        Ok(Some((
+            row["user_id"].as_i64().unwrap() as i32,
+            TwitterRequest {
+                request_key: row["request_key"].to_string().unwrap(),
+                request_secret: row["request_secret"].to_string().unwrap(),
+            },
+        )))
+    }
+
     /// Update a user's Twitter account with new credentials.
     ///
     /// Takes care of the following housekeeping items:
     /// * Deletes any previous accounts
-    /// * Inserts the key and secret for the user
+    /// * Inserts the encrypted key and secret for the user
     /// * Deletes the pending request
     pub async fn set_account(
         conn: &PooledConnection,
+        key: &crate::crypto::SecretKey,
         user_id: i32,
         creds: TwitterAccount,
     ) -> failure::Fallible<()> {
+        let consumer_key = key
+            .encrypt(&creds.consumer_key)
+            .context("unable to encrypt twitter consumer key")?;
+        let consumer_secret = key
+            .encrypt(&creds.consumer_secret)
+            .context("unable to encrypt twitter consumer secret")?;
+
         let delete = Delete::from_table(TWITTER_ACCOUNT).so_that("user_id".equals(user_id));
         conn.delete(delete)
             .await
@@ -320,8 +442,10 @@ impl Twitter {
 
         let insert = Insert::single_into(TWITTER_ACCOUNT)
             .value("user_id", user_id)
-            .value("consumer_key", creds.consumer_key)
-            .value("consumer_secret", creds.consumer_secret)
+            .value("consumer_key", consumer_key)
+            .value("consumer_secret", consumer_secret)
+            .value("encrypted", true)
+            .value("screen_name", creds.screen_name)
             .build();
         conn.insert(insert)
             .await
@@ -335,6 +459,32 @@ impl Twitter {
         Ok(())
     }
 
+    /// Save the screen name of a user's account, so it doesn't need to be
+    /// loaded every time their accounts are shown.
+    pub async fn set_screen_name(
+        conn: &PooledConnection,
+        user_id: i32,
+        screen_name: &str,
+    ) -> failure::Fallible<()> {
+        let update = Update::table(TWITTER_ACCOUNT)
+            .set("screen_name", screen_name)
+            .so_that("user_id".equals(user_id));
+        conn.update(update)
+            .await
+            .context("unable to update twitter screen name")?;
+
+        Ok(())
+    }
+
+    pub async fn delete_account(conn: &PooledConnection, user_id: i32) -> failure::Fallible<()> {
+        let delete = Delete::from_table(TWITTER_ACCOUNT).so_that("user_id".equals(user_id));
+        conn.delete(delete)
+            .await
+            .context("unable to delete twitter account")?;
+
+        Ok(())
+    }
+
     pub async fn set_request(
         conn: &PooledConnection,
         user_id: i32,
@@ -358,6 +508,103 @@ impl Twitter {
     }
 }
 
+/// Credentials a user has linked for a site other than Twitter.
+///
+/// Secrets are encrypted before being stored, and each site decides what
+/// the secret contains.
+pub struct SiteAccount;
+
+impl SiteAccount {
+    /// Look up and decrypt a user's secret for a site.
+    pub async fn get(
+        conn: &PooledConnection,
+        key: &crate::crypto::SecretKey,
+        user_id: i32,
+        site: &str,
+    ) -> failure::Fallible<Option<String>> {
+        let select = Select::from_table(SITE_ACCOUNT)
+            .column("secret")
+            .so_that("user_id".equals(user_id).and("site".equals(site)));
+        let rows = conn
+            .select(select)
+            .await
+            .context("unable to select site account")?;
+
+        if rows.is_empty() {
+            return Ok(None);
+        }
+
+        let row = rows
+            .into_single()
+            .context("impossible missing site account")?;
+
+        let secret = key
+            .decrypt(&row["secret"].to_string().unwrap())
+            .context("unable to decrypt site account")?;
+
+        Ok(Some(secret))
+    }
+
+    /// Encrypt and save a user's secret for a site, replacing any previous
+    /// secret for that site.
+    pub async fn set(
+        conn: &PooledConnection,
+        key: &crate::crypto::SecretKey,
+        user_id: i32,
+        site: &str,
+        secret: &str,
+    ) -> failure::Fallible<()> {
+        let secret = key
+            .encrypt(secret)
+            .context("unable to encrypt site account")?;
+
+        Self::delete(&conn, user_id, site).await?;
+
+        let insert = Insert::single_into(SITE_ACCOUNT)
+            .value("user_id", user_id)
+            .value("site", site)
+            .value("secret", secret)
+            .value("encrypted", true)
+            .build();
+        conn.insert(insert)
+            .await
+            .context("unable to insert site account")?;
+
+        Ok(())
+    }
+
+    pub async fn delete(
+        conn: &PooledConnection,
+        user_id: i32,
+        site: &str,
+    ) -> failure::Fallible<()> {
+        let delete = Delete::from_table(SITE_ACCOUNT)
+            .so_that("user_id".equals(user_id).and("site".equals(site)));
+        conn.delete(delete)
+            .await
+            .context("unable to delete site account")?;
+
+        Ok(())
+    }
+
+    /// Get the names of every site a user has linked.
+    pub async fn sites(conn: &PooledConnection, user_id: i32) -> failure::Fallible<Vec<String>> {
+        let select = Select::from_table(SITE_ACCOUNT)
+            .column("site")
+            .so_that("user_id".equals(user_id))
+            .order_by("site");
+        let rows = conn
+            .select(select)
+            .await
+            .context("unable to select site accounts")?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| row["site"].to_string())
+            .collect())
+    }
+}
+
 pub struct FileCache;
 
 impl FileCache {
@@ -395,6 +642,162 @@ impl FileCache {
     }
 }
 
+/// Hashes of the frames extracted from animations, videos and stickers.
+pub struct FrameCache;
+
+impl FrameCache {
+    /// Look up the cached frame hashes for a file by its unique ID.
+    pub async fn get(
+        conn: &PooledConnection,
+        file_id: &str,
+    ) -> failure::Fallible<Option<Vec<i64>>> {
+        let select = Select::from_table(FRAME_CACHE)
+            .column("hash")
+            .so_that("file_id".equals(file_id))
+            .order_by("id");
+        let rows = conn
+            .select(select)
+            .await
+            .context("unable to query frame cache")?;
+
+        if rows.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            rows.into_iter()
+                .filter_map(|row| row["hash"].as_i64())
+                .collect(),
+        ))
+    }
+
+    /// Cache the frame hashes for a file by its unique ID.
+    ///
+    /// Files without any searchable frames are stored as a single row
+    /// without a hash so they aren't extracted again.
+    pub async fn set(
+        conn: &PooledConnection,
+        file_id: &str,
+        hashes: &[i64],
+    ) -> failure::Fallible<()> {
+        // Every row is inserted at once so a file is never partially cached.
+        let mut insert = Insert::multi_into(FRAME_CACHE, vec!["file_id", "hash"]);
+
+        if hashes.is_empty() {
+            insert = insert.values((file_id, Option::<i64>::None));
+        }
+
+        for hash in hashes {
+            insert = insert.values((file_id, *hash));
+        }
+
+        conn.insert(insert.build())
+            .await
+            .context("unable to insert frame cache items")?;
+
+        Ok(())
+    }
+}
+
+/// A photo recently posted in a group.
+pub struct RecentPhoto {
+    pub message_id: i32,
+    pub file_id: String,
+    pub file_unique_id: String,
+}
+
+/// A rolling store of the most recent photos posted in each group.
+pub struct RecentPhotos;
+
+impl RecentPhotos {
+    /// Most photos to remember for each chat.
+    pub const LIMIT: usize = 25;
+
+    /// Remember a photo, forgetting the oldest photos in the chat past the
+    /// limit.
+    pub async fn add(
+        conn: &PooledConnection,
+        chat_id: i64,
+        message_id: i32,
+        file_id: &str,
+        file_unique_id: &str,
+    ) -> failure::Fallible<()> {
+        let insert = Insert::single_into(RECENT_PHOTO)
+            .value("chat_id", chat_id)
+            .value("message_id", message_id)
+            .value("file_id", file_id)
+            .value("file_unique_id", file_unique_id)
+            .build();
+        conn.insert(insert)
+            .await
+            .context("unable to insert recent photo")?;
+
+        let select = Select::from_table(RECENT_PHOTO)
+            .column("id")
+            .so_that("chat_id".equals(chat_id))
+            .order_by("id".descend());
+        let rows = conn
+            .select(select)
+            .await
+            .context("unable to select recent photos")?;
+
+        let old_ids: Vec<i64> = rows
+            .into_iter()
+            .skip(Self::LIMIT)
+            .filter_map(|row| row["id"].as_i64())
+            .collect();
+
+        if !old_ids.is_empty() {
+            let delete = Delete::from_table(RECENT_PHOTO).so_that("id".in_selection(old_ids));
+            conn.delete(delete)
+                .await
+                .context("unable to delete old recent photos")?;
+        }
+
+        Ok(())
+    }
+
+    /// Forget every photo in a chat.
+    pub async fn clear(conn: &PooledConnection, chat_id: i64) -> failure::Fallible<()> {
+        let delete = Delete::from_table(RECENT_PHOTO).so_that("chat_id".equals(chat_id));
+        conn.delete(delete)
+            .await
+            .context("unable to delete recent photos")?;
+
+        Ok(())
+    }
+
+    /// Get up to `count` of the most recent photos in a chat, newest first.
+    pub async fn get(
+        conn: &PooledConnection,
+        chat_id: i64,
+        count: usize,
+    ) -> failure::Fallible<Vec<RecentPhoto>> {
+        let select = Select::from_table(RECENT_PHOTO)
+            .column("message_id")
+            .column("file_id")
+            .column("file_unique_id")
+            .so_that("chat_id".equals(chat_id))
+            .order_by("id".descend())
+            .limit(count);
+        let rows = conn
+            .select(select)
+            .await
+            .context("unable to select recent photos")?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                Some(RecentPhoto {
+                    message_id: row["message_id"].as_i64()? as i32,
+                    file_id: row["file_id"].to_string()?,
+                    file_unique_id: row["file_unique_id"].to_string()?,
+                })
+            })
+            .collect())
+    }
+}
+
 pub struct Video {
     /// Database identifier of the video.
     pub id: i64,
@@ -507,3 +910,64 @@ impl Video {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Sites;
+
+    fn names(order: &[Sites]) -> Vec<&str> {
+        order.iter().map(Sites::as_str).collect()
+    }
+
+    #[test]
+    fn test_sites_order() {
+        let available = vec!["e621", "FurAffinity", "Weasyl", "Twitter", "Pixiv"];
+
+        let order = Sites::order(None, available.clone());
+        assert_eq!(
+            names(&order),
+            vec!["FurAffinity", "e621", "Twitter", "Weasyl", "Pixiv"]
+        );
+
+        let stored = vec!["Twitter".into(), "Removed".into(), "e621".into()];
+        let mut order = Sites::order(Some(stored), available.clone());
+        assert_eq!(
+            names(&order),
+            vec![
+                "Twitter",
+                "Removed",
+                "e621",
+                "FurAffinity",
+                "Weasyl",
+                "Pixiv"
+            ]
+        );
+
+        // Positions only count available sites.
+        Sites::move_site(&mut order, "Weasyl".parse().unwrap(), 1, available.clone());
+        assert_eq!(
+            names(&order),
+            vec![
+                "Twitter",
+                "Removed",
+                "Weasyl",
+                "e621",
+                "FurAffinity",
+                "Pixiv"
+            ]
+        );
+
+        Sites::move_site(&mut order, "Twitter".parse().unwrap(), 4, available);
+        assert_eq!(
+            names(&order),
+            vec![
+                "Removed",
+                "Weasyl",
+                "e621",
+                "FurAffinity",
+                "Pixiv",
+                "Twitter"
+            ]
+        );
+    }
+}
@@ -1,9 +1,11 @@
 use async_trait::async_trait;
-use failure::ResultExt;
+use chrono::{DateTime, Utc};
 use fuzzysearch::MatchType;
 use reqwest::header;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
 use tokio01::runtime::current_thread::block_on_all;
 
 use crate::models::Twitter as TwitterModel;
@@ -28,45 +30,436 @@ pub struct PostInfo {
     pub source_link: Option<String>,
     /// Additional caption to add as a second result for the provided query
     pub extra_caption: Option<String>,
-    /// Title for video results
+    /// Title of the post, if available
     pub title: Option<String>,
+    /// Name of the artist who created the post, if available
+    pub artist: Option<String>,
+    /// Content rating of the post, if known
+    pub rating: Option<Rating>,
+    /// Tags attached to the post
+    pub tags: Vec<String>,
+    /// Width and height of the full image, if known
+    pub dimensions: Option<(u32, u32)>,
+    /// When the post was originally made, if known
+    pub posted_at: Option<DateTime<Utc>>,
     /// Human readable name of the site
     pub site_name: &'static str,
 }
 
+/// Content rating of a post, ordered from least to most explicit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Rating {
+    Safe,
+    Questionable,
+    Explicit,
+}
+
+#[derive(Debug)]
+pub struct ParseRatingError;
+
+impl std::str::FromStr for Rating {
+    type Err = ParseRatingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "safe" => Ok(Rating::Safe),
+            "questionable" => Ok(Rating::Questionable),
+            "explicit" => Ok(Rating::Explicit),
+            _ => Err(ParseRatingError),
+        }
+    }
+}
+
+impl Rating {
+    /// Every rating, from least to most explicit.
+    pub const ALL: &'static [Rating] = &[Rating::Safe, Rating::Questionable, Rating::Explicit];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Rating::Safe => "safe",
+            Rating::Questionable => "questionable",
+            Rating::Explicit => "explicit",
+        }
+    }
+}
+
+/// Parse an RFC 3339 date, as used by most site APIs.
+fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(date)
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+/// Convert a Unix timestamp in seconds into a date.
+fn from_timestamp(secs: i64) -> DateTime<Utc> {
+    DateTime::from_utc(chrono::NaiveDateTime::from_timestamp(secs, 0), Utc)
+}
+
+/// Get the extension of the file at the end of a path or URL, ignoring any
+/// query string.
 fn get_file_ext(name: &str) -> Option<&str> {
-    name.split('.')
-        .last()
-        .map(|ext| ext.split('?').next())
-        .flatten()
+    let path = name.split('?').next()?;
+    let file = path.rsplit('/').next()?;
+    let idx = file.rfind('.')?;
+
+    Some(&file[idx + 1..])
+}
+
+/// Get the extension of a file, treating a missing extension as a bad
+/// response from the site.
+fn file_ext(name: &str) -> Result<String, SiteError> {
+    get_file_ext(name)
+        .map(ToString::to_string)
+        .ok_or_else(|| SiteError::Parse(format!("missing file extension: {}", name)))
+}
+
+/// Errors that can occur while loading images from a site.
+#[derive(Debug, Fail)]
+pub enum SiteError {
+    #[fail(display = "post was not found")]
+    NotFound,
+    #[fail(display = "unable to authenticate: {}", _0)]
+    AuthFailed(String),
+    #[fail(display = "linked account must be linked again")]
+    AccountExpired,
+    #[fail(display = "rate limited")]
+    RateLimited,
+    #[fail(display = "request was blocked")]
+    Blocked,
+    #[fail(display = "unable to parse response: {}", _0)]
+    Parse(String),
+    #[fail(display = "request was rejected: {}", _0)]
+    Rejected(String),
+    #[fail(display = "upstream error: {}", _0)]
+    Upstream(String),
+    #[fail(display = "request timed out")]
+    Timeout,
+    #[fail(display = "site is temporarily unavailable")]
+    Unavailable,
+    #[fail(display = "internal error: {}", _0)]
+    Internal(String),
+    #[fail(display = "linked account error: {}", _0)]
+    LinkedAccount(Box<SiteError>),
+}
+
+impl SiteError {
+    /// If the same request may succeed when attempted again.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            SiteError::Upstream(_) | SiteError::Timeout => true,
+            _ => false,
+        }
+    }
+
+    /// If the error was caused by the site having issues, rather than an
+    /// issue with the requested post, a user's account, or the bot itself.
+    pub fn is_site_failure(&self) -> bool {
+        match self {
+            SiteError::Timeout
+            | SiteError::Upstream(_)
+            | SiteError::RateLimited
+            | SiteError::Blocked => true,
+            _ => false,
+        }
+    }
+
+    /// Mark an error as caused by a request made with a user's linked
+    /// account, so it doesn't count against the site as a whole.
+    pub fn for_account(self) -> Self {
+        match self {
+            SiteError::Blocked | SiteError::RateLimited | SiteError::AuthFailed(_) => {
+                SiteError::LinkedAccount(Box::new(self))
+            }
+            err => err,
+        }
+    }
+
+    /// The error, without any linked account marker.
+    pub fn inner(&self) -> &SiteError {
+        match self {
+            SiteError::LinkedAccount(err) => err.inner(),
+            err => err,
+        }
+    }
+}
+
+impl From<reqwest::Error> for SiteError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            return SiteError::Timeout;
+        }
+
+        if err.is_decode() {
+            return SiteError::Parse(err.to_string());
+        }
+
+        match err.status().map(|status| status.as_u16()) {
+            Some(404) | Some(410) => SiteError::NotFound,
+            Some(401) => SiteError::AuthFailed(err.to_string()),
+            Some(429) => SiteError::RateLimited,
+            Some(403) => SiteError::Blocked,
+            Some(400..=499) => SiteError::Rejected(err.to_string()),
+            _ => SiteError::Upstream(err.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for SiteError {
+    fn from(err: serde_json::Error) -> Self {
+        SiteError::Parse(err.to_string())
+    }
+}
+
+/// Credentials users have linked with their own accounts, for sites that
+/// can load posts as that user.
+#[derive(Clone)]
+pub struct LinkedAccounts {
+    conn: quaint::pooled::Quaint,
+    key: std::sync::Arc<crate::crypto::SecretKey>,
+}
+
+impl LinkedAccounts {
+    pub fn new(
+        conn: quaint::pooled::Quaint,
+        key: std::sync::Arc<crate::crypto::SecretKey>,
+    ) -> Self {
+        Self { conn, key }
+    }
+
+    async fn check_out(&self) -> Result<quaint::pooled::PooledConnection, SiteError> {
+        self.conn
+            .check_out()
+            .await
+            .map_err(|err| SiteError::Internal(err.to_string()))
+    }
+
+    /// Get the user's secret for a site, if they have linked an account.
+    async fn secret(&self, user_id: i32, site: &str) -> Result<Option<String>, SiteError> {
+        let conn = self.check_out().await?;
+
+        crate::models::SiteAccount::get(&conn, &self.key, user_id, site)
+            .await
+            .map_err(|err| SiteError::Internal(err.to_string()))
+    }
+
+    /// Get the user's Twitter account, if they have signed in with /twitter.
+    async fn twitter(
+        &self,
+        user_id: i32,
+    ) -> Result<Option<crate::models::TwitterAccount>, SiteError> {
+        let conn = self.check_out().await?;
+
+        TwitterModel::get_account(&conn, &self.key, user_id)
+            .await
+            .map_err(|err| SiteError::Internal(err.to_string()))
+    }
 }
 
 #[async_trait]
 pub trait Site {
     fn name(&self) -> &'static str;
-    async fn url_supported(&mut self, url: &str) -> bool;
+    async fn url_supported(&self, url: &str) -> bool;
+    async fn get_images(&self, user_id: i32, url: &str)
+        -> Result<Option<Vec<PostInfo>>, SiteError>;
+
+    /// Time allowed for copying files to storage after loading a post, in
+    /// addition to the configured timeout. Sites that copy files must give
+    /// up with an error that isn't a site failure once this has passed, so
+    /// slow copies don't open the circuit breaker.
+    fn proxy_timeout(&self) -> Duration {
+        Duration::from_secs(0)
+    }
+
+    /// If failures should count toward a circuit breaker shared by every
+    /// request to the site. Sites that load from many independent servers
+    /// should return false so one broken server can't disable the rest.
+    fn shares_breaker(&self) -> bool {
+        true
+    }
+}
+
+/// How requests to a site should be limited and retried.
+#[derive(Clone, Debug)]
+pub struct ResilienceConfig {
+    /// Maximum time to wait for a single attempt at loading images.
+    pub timeout: Duration,
+    /// Number of times to retry after the first attempt fails.
+    pub retries: u32,
+    /// Delay before the first retry, doubled for each following retry.
+    pub backoff: Duration,
+    /// Number of failed requests in a row before the site is skipped.
+    pub failure_threshold: u32,
+    /// How long to skip the site before trying it again.
+    pub cooldown: Duration,
+}
+
+impl Default for ResilienceConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(4),
+            retries: 1,
+            backoff: Duration::from_millis(500),
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Circuit breaker tracking consecutive failures for a site.
+#[derive(Debug, Default)]
+struct Breaker {
+    failures: u32,
+    opened_at: Option<Instant>,
+    /// When the request checking if an open site has recovered started.
+    probing_since: Option<Instant>,
+}
+
+impl Breaker {
+    /// If a request should be attempted. Once the cooldown has passed a
+    /// single request is allowed through to check if the site has recovered.
+    /// Another is only allowed if that request never finished within the
+    /// cooldown.
+    fn allows(&mut self, cooldown: Duration) -> bool {
+        let opened_at = match self.opened_at {
+            Some(opened_at) => opened_at,
+            None => return true,
+        };
+
+        if opened_at.elapsed() < cooldown {
+            return false;
+        }
+
+        match self.probing_since {
+            Some(started) if started.elapsed() < cooldown => false,
+            _ => {
+                self.probing_since = Some(Instant::now());
+                true
+            }
+        }
+    }
+
+    fn record_success(&mut self) {
+        self.failures = 0;
+        self.opened_at = None;
+        self.probing_since = None;
+    }
+
+    /// Record a request that failed for a reason other than the site being
+    /// down, leaving the failure count unchanged.
+    fn record_ignored(&mut self) {
+        self.probing_since = None;
+    }
+
+    /// Record a failed request, returning true if the breaker just opened.
+    fn record_failure(&mut self, threshold: u32) -> bool {
+        self.failures += 1;
+        self.probing_since = None;
+
+        if self.failures < threshold {
+            return false;
+        }
+
+        let was_open = self.opened_at.is_some();
+        self.opened_at = Some(Instant::now());
+
+        !was_open
+    }
+}
+
+/// Wraps a site with timeouts, retries, and a circuit breaker.
+///
+/// While the breaker is open no requests are made and
+/// [SiteError::Unavailable] is returned.
+pub struct ResilientSite {
+    site: crate::BoxedSite,
+    config: ResilienceConfig,
+    breaker: std::sync::Mutex<Breaker>,
+}
+
+impl ResilientSite {
+    pub fn new(site: crate::BoxedSite, config: ResilienceConfig) -> Self {
+        Self {
+            site,
+            config,
+            breaker: std::sync::Mutex::new(Breaker::default()),
+        }
+    }
+}
+
+#[async_trait]
+impl Site for ResilientSite {
+    fn name(&self) -> &'static str {
+        self.site.name()
+    }
+
+    async fn url_supported(&self, url: &str) -> bool {
+        self.site.url_supported(url).await
+    }
+
+    fn shares_breaker(&self) -> bool {
+        self.site.shares_breaker()
+    }
+
+    fn proxy_timeout(&self) -> Duration {
+        self.site.proxy_timeout()
+    }
+
     async fn get_images(
-        &mut self,
+        &self,
         user_id: i32,
         url: &str,
-    ) -> failure::Fallible<Option<Vec<PostInfo>>>;
-}
+    ) -> Result<Option<Vec<PostInfo>>, SiteError> {
+        if !self.breaker.lock().unwrap().allows(self.config.cooldown) {
+            tracing::warn!(site = self.name(), "circuit breaker open, skipping");
+            return Err(SiteError::Unavailable);
+        }
 
-// workaround for NoneError not actually being an Error
-// https://github.com/rust-lang-nursery/failure/issues/59#issuecomment-602862336
-#[derive(Debug, Fail)]
-#[fail(display = "NoneError")]
-struct NoneError;
+        let timeout = self.config.timeout + self.proxy_timeout();
+        let mut attempt = 0;
 
-trait OptionExt {
-    type T;
-    fn unwrap_fail(self) -> Result<Self::T, NoneError>;
-}
+        loop {
+            let result =
+                match tokio::time::timeout(timeout, self.site.get_images(user_id, url)).await {
+                    Ok(result) => result,
+                    Err(_elapsed) => Err(SiteError::Timeout),
+                };
 
-impl<U> OptionExt for Option<U> {
-    type T = U;
-    fn unwrap_fail(self) -> Result<Self::T, NoneError> {
-        self.ok_or(NoneError)
+            let err = match result {
+                Ok(images) => {
+                    self.breaker.lock().unwrap().record_success();
+                    return Ok(images);
+                }
+                Err(err) if !err.is_site_failure() => {
+                    self.breaker.lock().unwrap().record_ignored();
+                    return Err(err);
+                }
+                Err(err) => err,
+            };
+
+            if attempt >= self.config.retries || !err.is_retryable() {
+                if self.shares_breaker()
+                    && self
+                        .breaker
+                        .lock()
+                        .unwrap()
+                        .record_failure(self.config.failure_threshold)
+                {
+                    tracing::error!(site = self.name(), "circuit breaker opened");
+                }
+
+                return Err(err);
+            }
+
+            tracing::warn!(
+                site = self.name(),
+                attempt,
+                "unable to get images: {:?}",
+                err
+            );
+
+            tokio::time::delay_for(self.config.backoff * 2u32.pow(attempt)).await;
+            attempt += 1;
+        }
     }
 }
 
@@ -116,7 +509,7 @@ impl Site for Direct {
         "direct link"
     }
 
-    async fn url_supported(&mut self, url: &str) -> bool {
+    async fn url_supported(&self, url: &str) -> bool {
         // If the URL extension isn't one in our list, ignore.
         if !Direct::EXTENSIONS.iter().any(|ext| url.ends_with(ext)) {
             return false;
@@ -148,10 +541,10 @@ impl Site for Direct {
     }
 
     async fn get_images(
-        &mut self,
+        &self,
         _user_id: i32,
         url: &str,
-    ) -> failure::Fallible<Option<Vec<PostInfo>>> {
+    ) -> Result<Option<Vec<PostInfo>>, SiteError> {
         let u = url.to_string();
         let mut source_link = None;
         let mut source_name = None;
@@ -172,7 +565,7 @@ impl Site for Direct {
         }
 
         Ok(Some(vec![PostInfo {
-            file_type: get_file_ext(url).unwrap().to_string(),
+            file_type: file_ext(url)?,
             url: u.clone(),
             source_link,
             site_name: source_name.unwrap_or_else(|| self.name()),
@@ -192,6 +585,8 @@ pub struct E621 {
 struct E621PostFile {
     ext: String,
     url: String,
+    width: u32,
+    height: u32,
 }
 
 #[derive(Debug, Deserialize)]
@@ -204,6 +599,10 @@ struct E621Post {
     id: i32,
     file: E621PostFile,
     preview: E621PostPreview,
+    rating: String,
+    created_at: String,
+    /// Tags grouped by category, such as artist or species.
+    tags: HashMap<String, Vec<String>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -212,6 +611,14 @@ struct E621Resp {
 }
 
 impl E621 {
+    /// Tags in the artist category that don't name an artist.
+    const NON_ARTIST_TAGS: &'static [&'static str] = &[
+        "conditional_dnp",
+        "sound_warning",
+        "third-party_edit",
+        "unknown_artist",
+    ];
+
     pub fn new() -> Self {
         Self {
             show: regex::Regex::new(r"https?://(?P<host>e(?:621|926)\.net)/(?:post/show/|posts/)(?P<id>\d+)(?:/(?P<tags>.+))?").unwrap(),
@@ -228,15 +635,15 @@ impl Site for E621 {
         "e621"
     }
 
-    async fn url_supported(&mut self, url: &str) -> bool {
+    async fn url_supported(&self, url: &str) -> bool {
         self.show.is_match(url) || self.data.is_match(url)
     }
 
     async fn get_images(
-        &mut self,
+        &self,
         _user_id: i32,
         url: &str,
-    ) -> failure::Fallible<Option<Vec<PostInfo>>> {
+    ) -> Result<Option<Vec<PostInfo>>, SiteError> {
         let endpoint = if self.show.is_match(url) {
             let captures = self.show.captures(url).unwrap();
             let id = &captures["id"];
@@ -254,171 +661,791 @@ impl Site for E621 {
             .get(&endpoint)
             .header(header::USER_AGENT, USER_AGENT)
             .send()
-            .await
-            .context("unable to request e621 api")?
+            .await?
+            .error_for_status()?
             .json()
-            .await
-            .context("unable to parse e621 json")?;
+            .await?;
+
+        let post = resp.post;
+
+        let artist = post.tags.get("artist").and_then(|artists| {
+            artists
+                .iter()
+                .find(|artist| !E621::NON_ARTIST_TAGS.contains(&artist.as_str()))
+                .cloned()
+        });
+
+        let rating = match post.rating.as_ref() {
+            "s" => Some(Rating::Safe),
+            "q" => Some(Rating::Questionable),
+            "e" => Some(Rating::Explicit),
+            _ => None,
+        };
 
         Ok(Some(vec![PostInfo {
-            file_type: resp.post.file.ext,
-            url: resp.post.file.url,
-            thumb: Some(resp.post.preview.url),
-            source_link: Some(format!("https://e621.net/posts/{}", resp.post.id)),
+            file_type: post.file.ext,
+            url: post.file.url,
+            thumb: Some(post.preview.url),
+            source_link: Some(format!("https://e621.net/posts/{}", post.id)),
+            artist,
+            rating,
+            tags: post.tags.into_iter().flat_map(|(_, tags)| tags).collect(),
+            dimensions: Some((post.file.width, post.file.height)),
+            posted_at: parse_date(&post.created_at),
             site_name: self.name(),
             ..Default::default()
         }]))
     }
 }
 
-pub struct Twitter {
-    matcher: regex::Regex,
-    consumer: egg_mode::KeyPair,
-    token: egg_mode::Token,
-    conn: quaint::pooled::Quaint,
+/// The API used by a booru.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BooruKind {
+    Danbooru,
+    Gelbooru,
+    Philomena,
 }
 
-impl Twitter {
-    pub fn new(
-        consumer_key: String,
-        consumer_secret: String,
-        conn: quaint::pooled::Quaint,
-    ) -> Self {
-        use egg_mode::KeyPair;
+/// An image board using one of the common booru APIs, configured with the
+/// host it runs on.
+pub struct Booru {
+    kind: BooruKind,
+    name: &'static str,
+    host: String,
 
-        let consumer = KeyPair::new(consumer_key, consumer_secret);
-        let token = block_on_all(egg_mode::bearer_token(&consumer)).unwrap();
+    show: regex::Regex,
+    data: regex::Regex,
 
-        Self {
-            matcher: regex::Regex::new(
-                r"https://(?:mobile\.)?twitter.com/(?:\w+)/status/(?P<id>\d+)",
-            )
-            .unwrap(),
-            consumer,
-            token,
-            conn,
-        }
-    }
+    client: reqwest::Client,
 }
 
-#[async_trait]
-impl Site for Twitter {
-    fn name(&self) -> &'static str {
-        "Twitter"
-    }
+#[derive(Debug, Deserialize)]
+struct DanbooruPost {
+    id: i32,
+    file_url: Option<String>,
+    preview_file_url: Option<String>,
+    file_ext: String,
+    rating: String,
+    created_at: String,
+    image_width: u32,
+    image_height: u32,
+    tag_string: String,
+    tag_string_artist: String,
+}
 
-    async fn url_supported(&mut self, url: &str) -> bool {
-        self.matcher.is_match(url)
-    }
+/// Gelbooru 0.2.5 wraps posts in an object, but older versions return an
+/// array of posts.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum GelbooruResp {
+    Wrapped {
+        #[serde(default)]
+        post: Vec<GelbooruPost>,
+    },
+    Posts(Vec<GelbooruPost>),
+}
 
-    async fn get_images(
-        &mut self,
-        user_id: i32,
-        url: &str,
-    ) -> failure::Fallible<Option<Vec<PostInfo>>> {
-        let captures = self.matcher.captures(url).unwrap();
-        let id = captures["id"].to_owned().parse::<u64>().unwrap();
+#[derive(Debug, Deserialize)]
+struct GelbooruPost {
+    id: i32,
+    file_url: String,
+    preview_url: Option<String>,
+    rating: String,
+    created_at: Option<String>,
+    width: u32,
+    height: u32,
+    tags: String,
+}
 
-        tracing::trace!(user_id, "attempting to find saved credentials",);
+#[derive(Debug, Deserialize)]
+struct PhilomenaResp {
+    image: PhilomenaImage,
+}
 
-        let conn = self
-            .conn
-            .check_out()
-            .await
-            .context("unable to check out database")?;
-        let account = TwitterModel::get_account(&conn, user_id)
-            .await
-            .context("unable to query twitter account")?;
+#[derive(Debug, Deserialize)]
+struct PhilomenaImage {
+    id: i32,
+    format: String,
+    representations: PhilomenaRepresentations,
+    created_at: String,
+    width: u32,
+    height: u32,
+    tags: Vec<String>,
+}
 
-        let token = match account {
-            Some(account) => egg_mode::Token::Access {
-                consumer: self.consumer.clone(),
-                access: egg_mode::KeyPair::new(account.consumer_key, account.consumer_secret),
-            },
-            _ => self.token.clone(),
-        };
+#[derive(Debug, Deserialize)]
+struct PhilomenaRepresentations {
+    full: String,
+    thumb: String,
+}
 
-        let tweet = match block_on_all(egg_mode::tweet::show(id, &token)) {
-            Ok(tweet) => tweet.response,
-            Err(e) => return Err(e.into()),
+impl Booru {
+    /// Parse a configured host, optionally followed by `|` and the host
+    /// serving its files, such as `derpibooru.org|derpicdn.net`. Returns the
+    /// host and file host, or None if either is not a valid host.
+    pub fn parse_config(config: &str) -> Option<(String, String)> {
+        let mut hosts = config.split('|').map(|host| host.trim().to_lowercase());
+        let host = hosts.next().filter(|host| is_valid_host(host))?;
+
+        let file_host = match hosts.next() {
+            Some(file_host) if is_valid_host(&file_host) => file_host,
+            Some(_) => return None,
+            None => host.clone(),
         };
 
-        let user = tweet.user.unwrap();
+        if hosts.next().is_some() {
+            return None;
+        }
 
-        let media = match tweet.extended_entities {
-            Some(entity) => entity.media,
-            None => return Ok(None),
+        Some((host, file_host))
+    }
+
+    /// Name a booru after the first part of its host, such as Danbooru for
+    /// danbooru.donmai.us.
+    pub fn default_name(host: &str) -> String {
+        let host = host.trim_start_matches("www.");
+        let label = host.split('.').next().unwrap_or(host);
+
+        let mut chars = label.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => host.to_string(),
+        }
+    }
+
+    /// Create a booru for a host, with links to files only accepted from the
+    /// file host or its subdomains.
+    pub fn new(kind: BooruKind, host: String, file_host: &str, name: &'static str) -> Self {
+        let file_domain = regex::escape(file_host);
+        let site_host = regex::escape(&host);
+
+        let (show, data) = match kind {
+            BooruKind::Danbooru => (
+                format!(
+                    r"https?://(?:www\.)?{}/(?:posts|post/show)/(?P<id>\d+)",
+                    site_host
+                ),
+                format!(
+                    r"https?://(?:[\w-]+\.)*{}/(?:data/)?(?:(?:original|sample|preview|\d+x\d+)/)?[0-9a-f]{{2}}/[0-9a-f]{{2}}/(?:sample-)?(?P<md5>[0-9a-f]{{32}})\.\w+",
+                    file_domain
+                ),
+            ),
+            BooruKind::Gelbooru => (
+                format!(
+                    r"https?://(?:www\.)?{}/index\.php\?(?:\S*&)?id=(?P<id>\d+)",
+                    site_host
+                ),
+                format!(
+                    r"https?://(?:[\w-]+\.)*{}/(?:images|samples|thumbnails)/(?:[0-9a-f]{{2}}/[0-9a-f]{{2}}|\d+)/(?:sample_|thumbnail_)?(?P<md5>[0-9a-f]{{32}})\.\w+",
+                    file_domain
+                ),
+            ),
+            // Philomena names files by ID instead of hash.
+            BooruKind::Philomena => (
+                format!(r"https?://(?:www\.)?{}/(?:images/)?(?P<id>\d+)", site_host),
+                format!(
+                    r"https?://(?:[\w-]+\.)*{}/img/(?:view/)?\d+/\d+/\d+/(?P<id>\d+)",
+                    file_domain
+                ),
+            ),
         };
 
-        let text = tweet.text.clone();
+        Self {
+            kind,
+            name,
+            host,
 
-        Ok(Some(
-            media
-                .into_iter()
-                .map(|item| match get_best_video(&item) {
-                    Some(video_url) => PostInfo {
-                        file_type: get_file_ext(video_url).unwrap().to_owned(),
-                        url: video_url.to_string(),
-                        thumb: Some(format!("{}:thumb", item.media_url_https.clone())),
-                        source_link: Some(item.expanded_url),
-                        personal: user.protected,
-                        title: Some(user.screen_name.clone()),
-                        extra_caption: Some(text.clone()),
-                        site_name: self.name(),
-                    },
-                    None => PostInfo {
-                        file_type: get_file_ext(&item.media_url_https).unwrap().to_owned(),
-                        url: item.media_url_https.clone(),
-                        thumb: Some(format!("{}:thumb", item.media_url_https.clone())),
-                        source_link: Some(item.expanded_url),
-                        personal: user.protected,
-                        site_name: self.name(),
-                        ..Default::default()
-                    },
-                })
-                .collect(),
-        ))
+            show: regex::Regex::new(&show).unwrap(),
+            data: regex::Regex::new(&data).unwrap(),
+
+            client: reqwest::Client::new(),
+        }
     }
-}
 
-fn get_best_video(media: &egg_mode::entities::MediaEntity) -> Option<&str> {
-    let video_info = match &media.video_info {
-        Some(video_info) => video_info,
-        None => return None,
-    };
+    async fn get_json<T>(&self, endpoint: &str) -> Result<T, SiteError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let resp = self
+            .client
+            .get(endpoint)
+            .header(header::USER_AGENT, USER_AGENT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
 
-    let highest_bitrate = video_info
-        .variants
-        .iter()
-        .max_by_key(|video| video.bitrate.unwrap_or(0))
-        .unwrap();
+        Ok(resp)
+    }
+}
 
-    Some(&highest_bitrate.url)
+/// If a configured value is only a hostname, without a scheme, port, or
+/// path.
+fn is_valid_host(host: &str) -> bool {
+    if host.is_empty() {
+        return false;
+    }
+
+    match reqwest::Url::parse(&format!("https://{}/", host)) {
+        Ok(url) => url.domain() == Some(host) && url.port().is_none() && url.path() == "/",
+        Err(_) => false,
+    }
 }
 
-pub struct FurAffinity {
-    cookies: std::collections::HashMap<String, String>,
-    fapi: fuzzysearch::FuzzySearch,
-    submission: scraper::Selector,
-    client: reqwest::Client,
+/// Map the ratings used by Danbooru and Gelbooru. Newer versions split safe
+/// into general and sensitive, which both use `s`, so it's treated as the
+/// more explicit of the two.
+fn booru_rating(rating: &str) -> Option<Rating> {
+    match rating {
+        "g" | "general" | "safe" => Some(Rating::Safe),
+        "s" | "sensitive" | "q" | "questionable" => Some(Rating::Questionable),
+        "e" | "explicit" => Some(Rating::Explicit),
+        _ => None,
+    }
 }
 
-impl FurAffinity {
-    pub fn new(cookies: (String, String), util_api: String) -> Self {
-        let mut c = std::collections::HashMap::new();
+fn danbooru_post(
+    post: DanbooruPost,
+    host: &str,
+    site_name: &'static str,
+) -> Result<PostInfo, SiteError> {
+    // Posts that need an account to view don't include file URLs.
+    let url = post
+        .file_url
+        .ok_or_else(|| SiteError::AuthFailed("post requires an account to view".into()))?;
+
+    Ok(PostInfo {
+        file_type: post.file_ext,
+        url,
+        thumb: post.preview_file_url,
+        source_link: Some(format!("https://{}/posts/{}", host, post.id)),
+        artist: post
+            .tag_string_artist
+            .split_whitespace()
+            .next()
+            .map(ToString::to_string),
+        rating: booru_rating(&post.rating),
+        tags: post
+            .tag_string
+            .split_whitespace()
+            .map(ToString::to_string)
+            .collect(),
+        dimensions: Some((post.image_width, post.image_height)),
+        posted_at: parse_date(&post.created_at),
+        site_name,
+        ..Default::default()
+    })
+}
 
-        c.insert("a".into(), cookies.0);
-        c.insert("b".into(), cookies.1);
+fn gelbooru_post(
+    post: GelbooruPost,
+    host: &str,
+    site_name: &'static str,
+) -> Result<PostInfo, SiteError> {
+    let posted_at = post.created_at.as_deref().and_then(|date| {
+        DateTime::parse_from_str(date, "%a %b %d %H:%M:%S %z %Y")
+            .ok()
+            .map(|date| date.with_timezone(&Utc))
+    });
+
+    Ok(PostInfo {
+        file_type: file_ext(&post.file_url)?,
+        url: post.file_url,
+        thumb: post.preview_url,
+        source_link: Some(format!(
+            "https://{}/index.php?page=post&s=view&id={}",
+            host, post.id
+        )),
+        rating: booru_rating(&post.rating),
+        tags: post
+            .tags
+            .split_whitespace()
+            .map(ToString::to_string)
+            .collect(),
+        dimensions: Some((post.width, post.height)),
+        posted_at,
+        site_name,
+        ..Default::default()
+    })
+}
+
+fn philomena_post(image: PhilomenaImage, host: &str, site_name: &'static str) -> PostInfo {
+    // Philomena stores ratings and artists as tags.
+    let rating = image
+        .tags
+        .iter()
+        .filter_map(|tag| match tag.as_str() {
+            "safe" => Some(Rating::Safe),
+            "suggestive" | "questionable" => Some(Rating::Questionable),
+            "explicit" => Some(Rating::Explicit),
+            _ => None,
+        })
+        .max();
+
+    let artist = image
+        .tags
+        .iter()
+        .find(|tag| tag.starts_with("artist:"))
+        .map(|tag| tag["artist:".len()..].to_string());
+
+    PostInfo {
+        file_type: image.format.to_lowercase(),
+        url: image.representations.full,
+        thumb: Some(image.representations.thumb),
+        source_link: Some(format!("https://{}/images/{}", host, image.id)),
+        artist,
+        rating,
+        tags: image.tags,
+        dimensions: Some((image.width, image.height)),
+        posted_at: parse_date(&image.created_at),
+        site_name,
+        ..Default::default()
+    }
+}
+
+#[async_trait]
+impl Site for Booru {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    async fn url_supported(&self, url: &str) -> bool {
+        self.show.is_match(url) || self.data.is_match(url)
+    }
+
+    async fn get_images(
+        &self,
+        _user_id: i32,
+        url: &str,
+    ) -> Result<Option<Vec<PostInfo>>, SiteError> {
+        let (show, captures) = match self.show.captures(url) {
+            Some(captures) => (true, captures),
+            None => (false, self.data.captures(url).unwrap()),
+        };
+
+        let post = match self.kind {
+            BooruKind::Danbooru => {
+                let posts: Vec<DanbooruPost> = if show {
+                    let endpoint = format!("https://{}/posts/{}.json", self.host, &captures["id"]);
+                    vec![self.get_json(&endpoint).await?]
+                } else {
+                    let endpoint = format!(
+                        "https://{}/posts.json?tags=md5:{}",
+                        self.host, &captures["md5"]
+                    );
+                    self.get_json(&endpoint).await?
+                };
+
+                let post = posts.into_iter().next().ok_or(SiteError::NotFound)?;
+                danbooru_post(post, &self.host, self.name)?
+            }
+            BooruKind::Gelbooru => {
+                let query = if show {
+                    format!("id={}", &captures["id"])
+                } else {
+                    format!("tags=md5:{}", &captures["md5"])
+                };
+
+                let endpoint = format!(
+                    "https://{}/index.php?page=dapi&s=post&q=index&json=1&{}",
+                    self.host, query
+                );
+
+                let posts = match self.get_json(&endpoint).await? {
+                    GelbooruResp::Wrapped { post } => post,
+                    GelbooruResp::Posts(posts) => posts,
+                };
+
+                let post = posts.into_iter().next().ok_or(SiteError::NotFound)?;
+                gelbooru_post(post, &self.host, self.name)?
+            }
+            BooruKind::Philomena => {
+                let endpoint = format!(
+                    "https://{}/api/v1/json/images/{}",
+                    self.host, &captures["id"]
+                );
+
+                let resp: PhilomenaResp = self.get_json(&endpoint).await?;
+                philomena_post(resp.image, &self.host, self.name)
+            }
+        };
+
+        Ok(Some(vec![post]))
+    }
+}
+
+pub struct Twitter {
+    matcher: regex::Regex,
+    consumer: egg_mode::KeyPair,
+    /// App token, loaded the first time it is needed.
+    token: RwLock<Option<egg_mode::Token>>,
+    accounts: Option<LinkedAccounts>,
+}
+
+impl Twitter {
+    pub fn new(
+        consumer_key: String,
+        consumer_secret: String,
+        accounts: Option<LinkedAccounts>,
+    ) -> Self {
+        use egg_mode::KeyPair;
+
+        Self {
+            matcher: regex::Regex::new(
+                r"https://(?:mobile\.)?twitter.com/(?:\w+)/status/(?P<id>\d+)",
+            )
+            .unwrap(),
+            consumer: KeyPair::new(consumer_key, consumer_secret),
+            token: RwLock::new(None),
+            accounts,
+        }
+    }
+
+    /// Get the app's bearer token, requesting it if it hasn't been loaded
+    /// yet.
+    async fn app_token(&self) -> Result<egg_mode::Token, SiteError> {
+        if let Some(token) = &*self.token.read().await {
+            return Ok(token.clone());
+        }
+
+        let consumer = self.consumer.clone();
+        let token =
+            tokio::task::spawn_blocking(move || block_on_all(egg_mode::bearer_token(&consumer)))
+                .await
+                .map_err(|err| SiteError::Internal(err.to_string()))??;
+
+        *self.token.write().await = Some(token.clone());
+
+        Ok(token)
+    }
+
+    /// Load the screen name of the account a token belongs to.
+    pub async fn screen_name(
+        consumer: egg_mode::KeyPair,
+        access: egg_mode::KeyPair,
+    ) -> Result<String, SiteError> {
+        let token = egg_mode::Token::Access { consumer, access };
+
+        let user =
+            tokio::task::spawn_blocking(move || block_on_all(egg_mode::verify_tokens(&token)))
+                .await
+                .map_err(|err| SiteError::Internal(err.to_string()))??
+                .response;
+
+        Ok(user.screen_name)
+    }
+
+    /// Revoke a user's access token, so it can't be used again after their
+    /// account is unlinked.
+    pub async fn revoke_token(
+        consumer: &egg_mode::KeyPair,
+        access: &egg_mode::KeyPair,
+    ) -> Result<(), SiteError> {
+        const INVALIDATE_TOKEN: &str = "https://api.twitter.com/1.1/oauth/invalidate_token";
+
+        let nonce = crate::generate_id();
+        let timestamp = Utc::now().timestamp().to_string();
+
+        let params = vec![
+            ("oauth_consumer_key", consumer.key.as_ref()),
+            ("oauth_nonce", nonce.as_str()),
+            ("oauth_signature_method", "HMAC-SHA1"),
+            ("oauth_timestamp", timestamp.as_str()),
+            ("oauth_token", access.key.as_ref()),
+            ("oauth_version", "1.0"),
+        ];
+
+        let signature = oauth_signature(
+            "POST",
+            INVALIDATE_TOKEN,
+            &params,
+            &consumer.secret,
+            &access.secret,
+        );
+
+        let header = params
+            .iter()
+            .chain(std::iter::once(&("oauth_signature", signature.as_str())))
+            .map(|(name, value)| format!(r#"{}="{}""#, name, oauth_encode(value)))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        reqwest::Client::new()
+            .post(INVALIDATE_TOKEN)
+            .header(header::AUTHORIZATION, format!("OAuth {}", header))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Site for Twitter {
+    fn name(&self) -> &'static str {
+        "Twitter"
+    }
+
+    async fn url_supported(&self, url: &str) -> bool {
+        self.matcher.is_match(url)
+    }
+
+    async fn get_images(
+        &self,
+        user_id: i32,
+        url: &str,
+    ) -> Result<Option<Vec<PostInfo>>, SiteError> {
+        let captures = self.matcher.captures(url).unwrap();
+        let id = captures["id"]
+            .parse::<u64>()
+            .map_err(|err| SiteError::Parse(err.to_string()))?;
+
+        tracing::trace!(user_id, "attempting to find saved credentials",);
+
+        let account = match &self.accounts {
+            Some(accounts) => accounts.twitter(user_id).await?,
+            None => None,
+        };
+
+        let personal = account.is_some();
+
+        let token = match account {
+            Some(account) => egg_mode::Token::Access {
+                consumer: self.consumer.clone(),
+                access: egg_mode::KeyPair::new(account.consumer_key, account.consumer_secret),
+            },
+            _ => self.app_token().await?,
+        };
+
+        // egg-mode still uses tokio 0.1, so this has to block a thread
+        // instead of holding up other queries.
+        let tweet =
+            tokio::task::spawn_blocking(move || block_on_all(egg_mode::tweet::show(id, &token)))
+                .await
+                .map_err(|err| SiteError::Internal(err.to_string()))?
+                .map_err(|err| {
+                    let err = SiteError::from(err);
+                    if personal {
+                        err.for_account()
+                    } else {
+                        err
+                    }
+                })?
+                .response;
+
+        let user = tweet
+            .user
+            .ok_or_else(|| SiteError::Parse("tweet was missing user".into()))?;
+
+        let media = match tweet.extended_entities {
+            Some(entity) => entity.media,
+            None => return Ok(None),
+        };
+
+        let text = tweet.text.clone();
+        let rating = tweet.possibly_sensitive.map(|sensitive| {
+            if sensitive {
+                Rating::Explicit
+            } else {
+                Rating::Safe
+            }
+        });
+
+        let posts = media
+            .into_iter()
+            .map(|item| match get_best_video(&item) {
+                Some(video_url) => Ok(PostInfo {
+                    file_type: file_ext(video_url)?,
+                    url: video_url.to_string(),
+                    thumb: Some(format!("{}:thumb", item.media_url_https.clone())),
+                    source_link: Some(item.expanded_url),
+                    personal: user.protected,
+                    extra_caption: Some(text.clone()),
+                    artist: Some(user.screen_name.clone()),
+                    rating,
+                    posted_at: Some(tweet.created_at),
+                    site_name: self.name(),
+                    ..Default::default()
+                }),
+                None => Ok(PostInfo {
+                    file_type: file_ext(&item.media_url_https)?,
+                    url: item.media_url_https.clone(),
+                    thumb: Some(format!("{}:thumb", item.media_url_https.clone())),
+                    source_link: Some(item.expanded_url),
+                    personal: user.protected,
+                    artist: Some(user.screen_name.clone()),
+                    rating,
+                    dimensions: Some((item.sizes.medium.w as u32, item.sizes.medium.h as u32)),
+                    posted_at: Some(tweet.created_at),
+                    site_name: self.name(),
+                    ..Default::default()
+                }),
+            })
+            .collect::<Result<_, SiteError>>()?;
+
+        Ok(Some(posts))
+    }
+}
+
+impl From<egg_mode::error::Error> for SiteError {
+    fn from(err: egg_mode::error::Error) -> Self {
+        use egg_mode::error::Error;
+
+        match err {
+            Error::RateLimit(_) => SiteError::RateLimited,
+            Error::TwitterError(ref errors) => {
+                // https://developer.twitter.com/en/docs/basics/response-codes
+                if errors
+                    .errors
+                    .iter()
+                    .any(|e| e.code == 34 || e.code == 50 || e.code == 63 || e.code == 144)
+                {
+                    SiteError::NotFound
+                } else if errors.errors.iter().any(|e| e.code == 179) {
+                    SiteError::AuthFailed(err.to_string())
+                } else if errors.errors.iter().any(|e| e.code == 88) {
+                    SiteError::RateLimited
+                } else if errors.errors.iter().any(|e| e.code == 130 || e.code == 131) {
+                    SiteError::Upstream(err.to_string())
+                } else {
+                    SiteError::Rejected(err.to_string())
+                }
+            }
+            Error::BadStatus(status) if !status.is_server_error() => {
+                SiteError::Rejected(err.to_string())
+            }
+            _ => SiteError::Upstream(err.to_string()),
+        }
+    }
+}
+
+/// Percent encode a value as required by OAuth 1.0a.
+fn oauth_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Sign a request with HMAC-SHA1, as egg-mode can't make requests to
+/// endpoints it doesn't know about.
+///
+/// The params must include every OAuth parameter and any query or form
+/// parameters sent with the request.
+fn oauth_signature(
+    method: &str,
+    url: &str,
+    params: &[(&str, &str)],
+    consumer_secret: &str,
+    token_secret: &str,
+) -> String {
+    use hmac::Mac;
+
+    let mut params: Vec<(String, String)> = params
+        .iter()
+        .map(|(name, value)| (oauth_encode(name), oauth_encode(value)))
+        .collect();
+    params.sort();
+
+    let params = params
+        .into_iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let base = format!("{}&{}&{}", method, oauth_encode(url), oauth_encode(&params));
+    let key = format!(
+        "{}&{}",
+        oauth_encode(consumer_secret),
+        oauth_encode(token_secret)
+    );
+
+    let mut mac = hmac::Hmac::<sha1::Sha1>::new_varkey(key.as_bytes()).unwrap();
+    mac.input(base.as_bytes());
+
+    base64::encode(&mac.result().code())
+}
+
+fn get_best_video(media: &egg_mode::entities::MediaEntity) -> Option<&str> {
+    let video_info = match &media.video_info {
+        Some(video_info) => video_info,
+        None => return None,
+    };
+
+    let highest_bitrate = video_info
+        .variants
+        .iter()
+        .max_by_key(|video| video.bitrate.unwrap_or(0))?;
+
+    Some(&highest_bitrate.url)
+}
+
+pub struct FurAffinity {
+    cookies: RwLock<HashMap<String, String>>,
+    fapi: fuzzysearch::FuzzySearch,
+    submission: scraper::Selector,
+    title: scraper::Selector,
+    rating: scraper::Selector,
+    client: reqwest::Client,
+    accounts: Option<LinkedAccounts>,
+}
+
+impl FurAffinity {
+    pub fn new(
+        cookies: (String, String),
+        util_api: String,
+        accounts: Option<LinkedAccounts>,
+    ) -> Self {
+        let mut c = HashMap::new();
+
+        c.insert("a".into(), cookies.0);
+        c.insert("b".into(), cookies.1);
 
         Self {
-            cookies: c,
+            cookies: RwLock::new(c),
             fapi: fuzzysearch::FuzzySearch::new(util_api),
             submission: scraper::Selector::parse("#submissionImg").unwrap(),
+            title: scraper::Selector::parse(r#"meta[property="og:title"]"#).unwrap(),
+            rating: scraper::Selector::parse(".rating-box").unwrap(),
             client: reqwest::Client::new(),
+            accounts,
+        }
+    }
+
+    /// Create the secret stored for a linked account from a user's `a` and
+    /// `b` cookies.
+    pub fn account_secret(a: &str, b: &str) -> String {
+        let mut cookies = HashMap::new();
+        cookies.insert("a", a);
+        cookies.insert("b", b);
+
+        serde_json::to_string(&cookies).unwrap()
+    }
+
+    /// Load the cookies for a user's linked account, if they have one.
+    async fn account_cookies(
+        &self,
+        user_id: i32,
+    ) -> Result<Option<HashMap<String, String>>, SiteError> {
+        let accounts = match &self.accounts {
+            Some(accounts) => accounts,
+            None => return Ok(None),
+        };
+
+        match accounts.secret(user_id, self.name()).await? {
+            Some(secret) => Ok(Some(serde_json::from_str(&secret)?)),
+            None => Ok(None),
         }
     }
 
-    async fn load_direct_url(&self, url: &str) -> failure::Fallible<Option<PostInfo>> {
+    async fn load_direct_url(&self, url: &str) -> Result<Option<PostInfo>, SiteError> {
         let url = if url.starts_with("http://") {
             url.replace("http://", "https://")
         } else {
@@ -429,7 +1456,7 @@ impl FurAffinity {
             Ok(mut results) if !results.is_empty() => results.remove(0),
             _ => {
                 return Ok(Some(PostInfo {
-                    file_type: get_file_ext(&url).unwrap().to_string(),
+                    file_type: file_ext(&url)?,
                     url: url.clone(),
                     site_name: self.name(),
                     ..Default::default()
@@ -438,60 +1465,85 @@ impl FurAffinity {
         };
 
         Ok(Some(PostInfo {
-            file_type: get_file_ext(&sub.filename).unwrap().to_string(),
+            file_type: file_ext(&sub.filename)?,
             url: sub.url.clone(),
             source_link: Some(sub.url()),
+            artist: sub
+                .artists
+                .as_ref()
+                .and_then(|artists| artists.first().cloned()),
             site_name: self.name(),
             ..Default::default()
         }))
     }
 
-    fn stringify_cookies(&self) -> String {
-        let mut cookies = vec![];
-        for (name, value) in &self.cookies {
-            cookies.push(format!("{}={}", name, value));
+    /// Build the cookie header, replacing the global account's cookies with
+    /// the user's own when they have linked an account.
+    async fn stringify_cookies(&self, account: Option<&HashMap<String, String>>) -> String {
+        let mut cookies = self.cookies.read().await.clone();
+        if let Some(account) = account {
+            cookies.extend(account.clone());
         }
-        cookies.join("; ")
+
+        cookies
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("; ")
     }
 
-    async fn load_submission(&mut self, url: &str) -> failure::Fallible<Option<PostInfo>> {
+    async fn load_submission(
+        &self,
+        url: &str,
+        account: Option<&HashMap<String, String>>,
+    ) -> Result<Option<PostInfo>, SiteError> {
         let resp = self
             .client
             .get(url)
-            .header(header::COOKIE, self.stringify_cookies())
+            .header(header::COOKIE, self.stringify_cookies(account).await)
             .header(header::USER_AGENT, USER_AGENT)
             .send()
-            .await
-            .context("unable to request furaffinity submission")?;
+            .await?;
 
         let resp = if resp.status() == 429 || resp.status() == 503 {
-            let cfscrape::CfscrapeData { cookies, .. } =
-                cfscrape::get_cookie_string(url, Some(USER_AGENT))
-                    .map_err(|err| format_err!("python error: {}", err))
-                    .context("unable to use cfscrape")?;
-            let cookies = cookies.split("; ");
-            for cookie in cookies {
-                let mut parts = cookie.split('=');
-                let name = parts.next().unwrap_fail().context("missing cookie data")?;
-                let value = parts.next().unwrap_fail().context("missing cookie data")?;
-
-                self.cookies.insert(name.into(), value.into());
+            // cfscrape runs Python and can take several seconds, so keep it
+            // off of the async workers.
+            let cf_url = url.to_string();
+            let cfscrape::CfscrapeData { cookies, .. } = tokio::task::spawn_blocking(move || {
+                cfscrape::get_cookie_string(&cf_url, Some(USER_AGENT)).map_err(|err| {
+                    tracing::warn!("unable to use cfscrape: {}", err);
+                    SiteError::Blocked
+                })
+            })
+            .await
+            .map_err(|err| SiteError::Internal(err.to_string()))??;
+
+            {
+                let mut saved_cookies = self.cookies.write().await;
+
+                let cookies = cookies.split("; ");
+                for cookie in cookies {
+                    let mut parts = cookie.split('=');
+                    let (name, value) = match (parts.next(), parts.next()) {
+                        (Some(name), Some(value)) => (name, value),
+                        _ => return Err(SiteError::Parse("missing cookie data".into())),
+                    };
+
+                    saved_cookies.insert(name.into(), value.into());
+                }
             }
 
             self.client
                 .get(url)
-                .header(header::COOKIE, self.stringify_cookies())
+                .header(header::COOKIE, self.stringify_cookies(account).await)
                 .header(header::USER_AGENT, USER_AGENT)
                 .send()
-                .await
-                .context("unable to send furaffinity request with cfscrape cookies")?
+                .await?
+                .error_for_status()?
                 .text()
-                .await
-                .context("unable to get text from furaffinity with cfscrape cookies")?
+                .await?
         } else {
-            resp.text()
-                .await
-                .context("unable to get text from furaffinity submission")?
+            resp.error_for_status()?.text().await?
         };
 
         let body = scraper::Html::parse_document(&resp);
@@ -504,14 +1556,42 @@ impl FurAffinity {
             "https:{}",
             img.value()
                 .attr("src")
-                .unwrap_fail()
-                .context("furaffinity was missing src")?
+                .ok_or_else(|| SiteError::Parse("furaffinity was missing src".into()))?
         );
 
+        // The title is in the form of "Title by artist".
+        let (title, artist) = match body
+            .select(&self.title)
+            .next()
+            .and_then(|meta| meta.value().attr("content"))
+        {
+            Some(content) => match content.rfind(" by ") {
+                Some(idx) => (
+                    Some(content[..idx].to_string()),
+                    Some(content[idx + 4..].to_string()),
+                ),
+                None => (Some(content.to_string()), None),
+            },
+            None => (None, None),
+        };
+
+        let rating = body.select(&self.rating).next().and_then(|rating| {
+            match rating.text().collect::<String>().trim() {
+                "General" => Some(Rating::Safe),
+                "Mature" => Some(Rating::Questionable),
+                "Adult" => Some(Rating::Explicit),
+                _ => None,
+            }
+        });
+
         Ok(Some(PostInfo {
-            file_type: get_file_ext(&image_url).unwrap().to_string(),
+            file_type: file_ext(&image_url)?,
             url: image_url.clone(),
+            personal: account.is_some(),
             source_link: Some(url.to_string()),
+            title,
+            artist,
+            rating,
             site_name: self.name(),
             ..Default::default()
         }))
@@ -524,21 +1604,27 @@ impl Site for FurAffinity {
         "FurAffinity"
     }
 
-    async fn url_supported(&mut self, url: &str) -> bool {
+    async fn url_supported(&self, url: &str) -> bool {
         url.contains("furaffinity.net/view/")
             || url.contains("furaffinity.net/full/")
             || url.contains("facdn.net/art/")
     }
 
     async fn get_images(
-        &mut self,
-        _user_id: i32,
+        &self,
+        user_id: i32,
         url: &str,
-    ) -> failure::Fallible<Option<Vec<PostInfo>>> {
+    ) -> Result<Option<Vec<PostInfo>>, SiteError> {
         let image = if url.contains("facdn.net/art/") {
             self.load_direct_url(url).await
         } else {
-            self.load_submission(url).await
+            let account = self.account_cookies(user_id).await?;
+            self.load_submission(url, account.as_ref())
+                .await
+                .map_err(|err| match account {
+                    Some(_) => err.for_account(),
+                    None => err,
+                })
         };
 
         image.map(|sub| sub.map(|post| vec![post]))
@@ -546,26 +1632,47 @@ impl Site for FurAffinity {
 }
 
 pub struct Mastodon {
-    instance_cache: HashMap<String, bool>,
+    instance_cache: RwLock<HashMap<String, bool>>,
     matcher: regex::Regex,
 }
 
 #[derive(Deserialize)]
 struct MastodonStatus {
     url: String,
+    account: MastodonAccount,
+    #[serde(default)]
+    sensitive: bool,
+    created_at: String,
     media_attachments: Vec<MastodonMediaAttachments>,
 }
 
+#[derive(Deserialize)]
+struct MastodonAccount {
+    acct: String,
+}
+
 #[derive(Deserialize)]
 struct MastodonMediaAttachments {
     url: String,
     preview_url: String,
+    meta: Option<MastodonMediaMeta>,
+}
+
+#[derive(Deserialize)]
+struct MastodonMediaMeta {
+    original: Option<MastodonMediaSize>,
+}
+
+#[derive(Deserialize)]
+struct MastodonMediaSize {
+    width: u32,
+    height: u32,
 }
 
 impl Mastodon {
     pub fn new() -> Self {
         Self {
-            instance_cache: HashMap::new(),
+            instance_cache: RwLock::new(HashMap::new()),
             matcher: regex::Regex::new(
                 r#"(?P<host>https?://(?:\S+))/(?:notice|users/\w+/statuses|@\w+)/(?P<id>\d+)"#,
             )
@@ -580,7 +1687,7 @@ impl Site for Mastodon {
         "Mastodon"
     }
 
-    async fn url_supported(&mut self, url: &str) -> bool {
+    async fn url_supported(&self, url: &str) -> bool {
         let captures = match self.matcher.captures(url) {
             Some(captures) => captures,
             None => return false,
@@ -588,7 +1695,7 @@ impl Site for Mastodon {
 
         let base = captures["host"].to_owned();
 
-        if let Some(is_masto) = self.instance_cache.get(&base) {
+        if let Some(is_masto) = self.instance_cache.read().await.get(&base) {
             if !is_masto {
                 return false;
             }
@@ -602,13 +1709,13 @@ impl Site for Mastodon {
         {
             Ok(resp) => resp,
             Err(_) => {
-                self.instance_cache.insert(base, false);
+                self.instance_cache.write().await.insert(base, false);
                 return false;
             }
         };
 
         if !resp.status().is_success() {
-            self.instance_cache.insert(base, false);
+            self.instance_cache.write().await.insert(base, false);
             return false;
         }
 
@@ -616,10 +1723,10 @@ impl Site for Mastodon {
     }
 
     async fn get_images(
-        &mut self,
+        &self,
         _user_id: i32,
         url: &str,
-    ) -> failure::Fallible<Option<Vec<PostInfo>>> {
+    ) -> Result<Option<Vec<PostInfo>>, SiteError> {
         let captures = self.matcher.captures(url).unwrap();
 
         let base = captures["host"].to_owned();
@@ -629,29 +1736,45 @@ impl Site for Mastodon {
             .get(&format!("{}/api/v1/statuses/{}", base, status_id))
             .header(header::USER_AGENT, USER_AGENT)
             .send()
-            .await
-            .context("unable to request mastodon api")?
+            .await?
+            .error_for_status()?
             .json()
-            .await
-            .context("unable to decode mastodon api")?;
+            .await?;
 
         if json.media_attachments.is_empty() {
             return Ok(None);
         }
 
-        Ok(Some(
-            json.media_attachments
-                .iter()
-                .map(|media| PostInfo {
-                    file_type: get_file_ext(&media.url).unwrap().to_owned(),
+        let posts = json
+            .media_attachments
+            .iter()
+            .map(|media| {
+                Ok(PostInfo {
+                    file_type: file_ext(&media.url)?,
                     url: media.url.clone(),
                     thumb: Some(media.preview_url.clone()),
                     source_link: Some(json.url.clone()),
+                    artist: Some(json.account.acct.clone()),
+                    // Mastodon only marks posts as sensitive, so it's
+                    // unknown how explicit they are.
+                    rating: Some(if json.sensitive {
+                        Rating::Questionable
+                    } else {
+                        Rating::Safe
+                    }),
+                    dimensions: media
+                        .meta
+                        .as_ref()
+                        .and_then(|meta| meta.original.as_ref())
+                        .map(|size| (size.width, size.height)),
+                    posted_at: parse_date(&json.created_at),
                     site_name: self.name(),
                     ..Default::default()
                 })
-                .collect(),
-        ))
+            })
+            .collect::<Result<_, SiteError>>()?;
+
+        Ok(Some(posts))
     }
 }
 
@@ -660,6 +1783,30 @@ pub struct Weasyl {
     matcher: regex::Regex,
 }
 
+#[derive(Deserialize)]
+struct WeasylMediaItem {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct WeasylMedia {
+    #[serde(default)]
+    submission: Vec<WeasylMediaItem>,
+    #[serde(default)]
+    thumbnail: Vec<WeasylMediaItem>,
+}
+
+#[derive(Deserialize)]
+struct WeasylSubmission {
+    title: String,
+    owner_login: String,
+    rating: String,
+    posted_at: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    media: WeasylMedia,
+}
+
 impl Weasyl {
     pub fn new(api_key: String) -> Self {
         Self {
@@ -675,19 +1822,19 @@ impl Site for Weasyl {
         "Weasyl"
     }
 
-    async fn url_supported(&mut self, url: &str) -> bool {
+    async fn url_supported(&self, url: &str) -> bool {
         self.matcher.is_match(url)
     }
 
     async fn get_images(
-        &mut self,
+        &self,
         _user_id: i32,
         url: &str,
-    ) -> failure::Fallible<Option<Vec<PostInfo>>> {
+    ) -> Result<Option<Vec<PostInfo>>, SiteError> {
         let captures = self.matcher.captures(url).unwrap();
         let sub_id = captures["id"].to_owned();
 
-        let resp: serde_json::Value = reqwest::Client::new()
+        let resp: WeasylSubmission = reqwest::Client::new()
             .get(&format!(
                 "https://www.weasyl.com/api/submissions/{}/view",
                 sub_id
@@ -695,59 +1842,49 @@ impl Site for Weasyl {
             .header("X-Weasyl-API-Key", self.api_key.as_bytes())
             .header(header::USER_AGENT, USER_AGENT)
             .send()
-            .await
-            .context("unable to request weasyl api")?
+            .await?
+            .error_for_status()?
             .json()
-            .await
-            .context("unable to parse weasyl json api")?;
-
-        let submissions = resp
-            .as_object()
-            .unwrap_fail()?
-            .get("media")
-            .unwrap_fail()?
-            .as_object()
-            .unwrap_fail()?
-            .get("submission")
-            .unwrap_fail()?
-            .as_array()
-            .unwrap_fail()?;
-
-        if submissions.is_empty() {
+            .await?;
+
+        let WeasylMedia {
+            submission,
+            thumbnail,
+        } = resp.media;
+
+        let rating = match resp.rating.as_ref() {
+            "general" => Some(Rating::Safe),
+            "moderate" | "mature" => Some(Rating::Questionable),
+            "explicit" => Some(Rating::Explicit),
+            _ => None,
+        };
+        let posted_at = parse_date(&resp.posted_at);
+
+        if submission.is_empty() {
             return Ok(None);
         }
 
-        let thumbs = resp
-            .as_object()
-            .unwrap_fail()?
-            .get("media")
-            .unwrap_fail()?
-            .as_object()
-            .unwrap_fail()?
-            .get("thumbnail")
-            .unwrap_fail()?
-            .as_array()
-            .unwrap_fail()?;
-
-        Ok(Some(
-            submissions
-                .iter()
-                .zip(thumbs)
-                .map(|(sub, thumb)| {
-                    let sub_url = sub.get("url").unwrap().as_str().unwrap().to_owned();
-                    let thumb_url = thumb.get("url").unwrap().as_str().unwrap().to_owned();
-
-                    PostInfo {
-                        file_type: get_file_ext(&sub_url).unwrap().to_owned(),
-                        url: sub_url.clone(),
-                        thumb: Some(thumb_url),
-                        source_link: Some(url.to_string()),
-                        site_name: self.name(),
-                        ..Default::default()
-                    }
+        let posts = submission
+            .into_iter()
+            .enumerate()
+            .map(|(idx, sub)| {
+                Ok(PostInfo {
+                    file_type: file_ext(&sub.url)?,
+                    thumb: thumbnail.get(idx).map(|thumb| thumb.url.clone()),
+                    url: sub.url,
+                    source_link: Some(url.to_string()),
+                    title: Some(resp.title.clone()),
+                    artist: Some(resp.owner_login.clone()),
+                    rating,
+                    tags: resp.tags.clone(),
+                    posted_at,
+                    site_name: self.name(),
+                    ..Default::default()
                 })
-                .collect(),
-        ))
+            })
+            .collect::<Result<_, SiteError>>()?;
+
+        Ok(Some(posts))
     }
 }
 
@@ -758,7 +1895,8 @@ pub struct Inkbunny {
     username: String,
     password: String,
 
-    sid: Option<String>,
+    sid: RwLock<Option<String>>,
+    accounts: Option<LinkedAccounts>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -774,11 +1912,26 @@ pub struct InkbunnyFile {
     file_name: String,
     thumbnail_url_medium_noncustom: String,
     file_url_screen: String,
+    #[serde(default)]
+    screen_size_x: Option<String>,
+    #[serde(default)]
+    screen_size_y: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct InkbunnyKeyword {
+    keyword_name: String,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct InkbunnySubmission {
     submission_id: String,
+    title: String,
+    username: String,
+    rating_id: String,
+    create_datetime: String,
+    #[serde(default)]
+    keywords: Vec<InkbunnyKeyword>,
     files: Vec<InkbunnyFile>,
 }
 
@@ -799,8 +1952,8 @@ impl Inkbunny {
     const API_LOGIN: &'static str = "https://inkbunny.net/api_login.php";
     const API_SUBMISSIONS: &'static str = "https://inkbunny.net/api_submissions.php";
 
-    pub async fn get_sid(&mut self) -> failure::Fallible<String> {
-        if let Some(sid) = &self.sid {
+    pub async fn get_sid(&self) -> Result<String, SiteError> {
+        if let Some(sid) = &*self.sid.read().await {
             return Ok(sid.clone());
         }
 
@@ -813,35 +1966,59 @@ impl Inkbunny {
             ])
             .send()
             .await?
+            .error_for_status()?
             .json()
             .await?;
 
         let login = match resp {
             InkbunnyResponse::Success(login) => login,
             InkbunnyResponse::Error { error_code: 0 } => {
-                panic!("Invalid Inkbunny username/password")
+                return Err(SiteError::AuthFailed(
+                    "invalid inkbunny username or password".into(),
+                ))
+            }
+            InkbunnyResponse::Error { error_code } => {
+                return Err(SiteError::Upstream(format!(
+                    "inkbunny error code {}",
+                    error_code
+                )))
             }
-            _ => panic!("Unhandled Inkbunny error code"),
         };
 
         if login.ratingsmask != "11111" {
-            panic!("Inkbunny user is missing viewing permissions");
+            return Err(SiteError::AuthFailed(
+                "inkbunny user is missing viewing permissions".into(),
+            ));
         }
 
-        self.sid = Some(login.sid.clone());
+        *self.sid.write().await = Some(login.sid.clone());
         Ok(login.sid)
     }
 
-    pub async fn get_submissions(&mut self, ids: &[i32]) -> failure::Fallible<InkbunnySubmissions> {
+    /// Load submissions, using the session of the user's linked account if
+    /// they have one.
+    ///
+    /// A linked session can't be renewed without the user's password, so
+    /// [SiteError::AccountExpired] is returned once it expires.
+    pub async fn get_submissions(
+        &self,
+        ids: &[i32],
+        account_sid: Option<String>,
+    ) -> Result<InkbunnySubmissions, SiteError> {
         let ids: String = ids
             .iter()
             .map(|id| id.to_string())
             .collect::<Vec<_>>()
             .join(",");
 
+        let mut logged_in = false;
+
         let submissions = loop {
             tracing::debug!(?ids, "Attempting to load Inkbunny submissions");
-            let sid = self.get_sid().await?;
+            let sid = match &account_sid {
+                Some(sid) => sid.clone(),
+                None => self.get_sid().await?,
+            };
 
             let resp: InkbunnyResponse<InkbunnySubmissions> = self
                 .client
@@ -849,24 +2026,35 @@ impl Inkbunny {
                 .form(&vec![("sid", &sid), ("submission_ids", &ids)])
                 .send()
                 .await?
+                .error_for_status()?
                 .json()
                 .await?;
 
             match resp {
                 InkbunnyResponse::Success(submissions) => break submissions,
-                InkbunnyResponse::Error { error_code: 2 } => {
+                InkbunnyResponse::Error { error_code: 2 } if account_sid.is_some() => {
+                    tracing::info!("Linked Inkbunny SID expired");
+                    return Err(SiteError::AccountExpired);
+                }
+                InkbunnyResponse::Error { error_code: 2 } if !logged_in => {
                     tracing::info!("Inkbunny SID expired");
-                    self.sid = None;
+                    *self.sid.write().await = None;
+                    logged_in = true;
                     continue;
                 }
-                _ => panic!("Unhandled Inkbunny error"),
+                InkbunnyResponse::Error { error_code } => {
+                    return Err(SiteError::Upstream(format!(
+                        "inkbunny error code {}",
+                        error_code
+                    )))
+                }
             };
         };
 
         Ok(submissions)
     }
 
-    pub fn new(username: String, password: String) -> Self {
+    pub fn new(username: String, password: String, accounts: Option<LinkedAccounts>) -> Self {
         let client = reqwest::Client::new();
 
         Self {
@@ -876,7 +2064,8 @@ impl Inkbunny {
             username,
             password,
 
-            sid: None,
+            sid: RwLock::new(None),
+            accounts,
         }
     }
 }
@@ -887,29 +2076,75 @@ impl Site for Inkbunny {
         "Inkbunny"
     }
 
-    async fn url_supported(&mut self, url: &str) -> bool {
+    async fn url_supported(&self, url: &str) -> bool {
         self.matcher.is_match(url)
     }
 
     async fn get_images(
-        &mut self,
-        _user_id: i32,
+        &self,
+        user_id: i32,
         url: &str,
-    ) -> failure::Fallible<Option<Vec<PostInfo>>> {
+    ) -> Result<Option<Vec<PostInfo>>, SiteError> {
         let captures = self.matcher.captures(url).unwrap();
-        let sub_id: i32 = captures["id"].to_owned().parse().unwrap();
+        let sub_id: i32 = captures["id"]
+            .parse()
+            .map_err(|err: std::num::ParseIntError| SiteError::Parse(err.to_string()))?;
+
+        let account_sid = match &self.accounts {
+            Some(accounts) => accounts.secret(user_id, self.name()).await?,
+            None => None,
+        };
+        let personal = account_sid.is_some();
 
-        let submissions = self.get_submissions(&vec![sub_id]).await?;
+        let submissions = self.get_submissions(&[sub_id], account_sid).await?;
+
+        if submissions.submissions.is_empty() {
+            return Err(SiteError::NotFound);
+        }
 
         let mut results = Vec::with_capacity(1);
 
         for submission in submissions.submissions {
+            let rating = match submission.rating_id.as_ref() {
+                "0" => Some(Rating::Safe),
+                "1" => Some(Rating::Questionable),
+                "2" => Some(Rating::Explicit),
+                _ => None,
+            };
+
+            // Dates look like 2011-01-13 04:42:49.5+00
+            let posted_at =
+                DateTime::parse_from_str(&submission.create_datetime, "%Y-%m-%d %H:%M:%S%.f%#z")
+                    .ok()
+                    .map(|date| date.with_timezone(&Utc));
+
+            let tags: Vec<String> = submission
+                .keywords
+                .into_iter()
+                .map(|keyword| keyword.keyword_name)
+                .collect();
+
             for file in submission.files {
+                let dimensions = match (
+                    file.screen_size_x.as_ref().and_then(|x| x.parse().ok()),
+                    file.screen_size_y.as_ref().and_then(|y| y.parse().ok()),
+                ) {
+                    (Some(x), Some(y)) => Some((x, y)),
+                    _ => None,
+                };
+
                 results.push(PostInfo {
-                    file_type: get_file_ext(&file.file_url_screen).unwrap().to_owned(),
+                    file_type: file_ext(&file.file_url_screen)?,
                     url: file.file_url_screen.clone(),
+                    personal,
                     thumb: Some(file.thumbnail_url_medium_noncustom.clone()),
                     source_link: Some(url.to_owned()),
+                    title: Some(submission.title.clone()),
+                    artist: Some(submission.username.clone()),
+                    rating,
+                    tags: tags.clone(),
+                    dimensions,
+                    posted_at,
                     site_name: self.name(),
                     ..Default::default()
                 });
@@ -919,3 +2154,2916 @@ impl Site for Inkbunny {
         Ok(Some(results))
     }
 }
+
+pub struct Pixiv {
+    client: reqwest::Client,
+    storage: crate::storage::Storage,
+    session: Option<String>,
+
+    artwork: regex::Regex,
+    legacy: regex::Regex,
+    direct: regex::Regex,
+}
+
+#[derive(Deserialize)]
+struct PixivResponse {
+    error: bool,
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    body: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct PixivUrls {
+    regular: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PixivTag {
+    tag: String,
+    #[serde(default)]
+    translation: Option<HashMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+struct PixivTags {
+    tags: Vec<PixivTag>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PixivIllust {
+    illust_id: String,
+    illust_title: String,
+    user_name: String,
+    /// 0 for illustrations, 1 for manga, and 2 for ugoira.
+    illust_type: u8,
+    /// 0 for all ages, 1 for R-18, and 2 for R-18G.
+    x_restrict: u8,
+    create_date: String,
+    tags: PixivTags,
+    urls: PixivUrls,
+}
+
+#[derive(Deserialize)]
+struct PixivPage {
+    urls: PixivUrls,
+}
+
+/// A single frame of a ugoira animation.
+#[derive(Clone, Debug, Deserialize)]
+pub struct UgoiraFrame {
+    /// Name of the image within the zip.
+    pub file: String,
+    /// How long to show the frame, in milliseconds.
+    pub delay: u32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct UgoiraMeta {
+    #[serde(rename = "originalSrc")]
+    pub original_src: String,
+    pub frames: Vec<UgoiraFrame>,
+}
+
+impl Pixiv {
+    /// Pixiv's image server rejects requests without this Referer.
+    pub const REFERER: &'static str = "https://www.pixiv.net/";
+    /// Most images to copy to storage at once.
+    const PROXY_CONCURRENCY: usize = 4;
+    /// Longest to wait for images to be copied to storage.
+    const PROXY_TIMEOUT: Duration = Duration::from_secs(10);
+    /// Most pages of a work to load, the size of a Telegram album.
+    const MAX_PAGES: usize = 10;
+
+    pub fn new(session: Option<String>, storage: crate::storage::Storage) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            storage,
+            session,
+
+            artwork: regex::Regex::new(
+                r"https?://(?:www\.)?pixiv\.net/(?:\w{2}/)?artworks/(?P<id>\d+)",
+            )
+            .unwrap(),
+            legacy: regex::Regex::new(
+                r"https?://(?:www\.)?pixiv\.net/member_illust\.php\?\S*illust_id=(?P<id>\d+)",
+            )
+            .unwrap(),
+            direct: regex::Regex::new(r"https?://i\.pximg\.net/\S+/(?P<id>\d+)_p(?P<page>\d+)")
+                .unwrap(),
+        }
+    }
+
+    /// Get the illustration ID from a ugoira zip URL, such as
+    /// `https://i.pximg.net/img-zip-ugoira/img/.../12345_ugoira1920x1080.zip`.
+    pub fn ugoira_id(url: &str) -> Option<u64> {
+        if !url.starts_with("https://i.pximg.net/") {
+            return None;
+        }
+
+        let name = url.rsplit('/').next()?;
+        let idx = name.find("_ugoira")?;
+
+        name[..idx].parse().ok()
+    }
+
+    async fn api<T: serde::de::DeserializeOwned>(
+        client: &reqwest::Client,
+        session: Option<&str>,
+        path: &str,
+    ) -> Result<T, SiteError> {
+        let mut request = client
+            .get(&format!("https://www.pixiv.net/ajax/{}", path))
+            .header(header::USER_AGENT, USER_AGENT)
+            .header(header::REFERER, Self::REFERER);
+
+        if let Some(session) = session {
+            request = request.header(header::COOKIE, format!("PHPSESSID={}", session));
+        }
+
+        let resp: PixivResponse = request.send().await?.error_for_status()?.json().await?;
+
+        if resp.error {
+            // Restricted works appear missing without a session.
+            tracing::debug!("pixiv error: {}", resp.message);
+            return Err(SiteError::NotFound);
+        }
+
+        Ok(serde_json::from_value(resp.body)?)
+    }
+
+    /// Load the frames of a ugoira animation.
+    pub async fn ugoira_meta(
+        client: &reqwest::Client,
+        session: Option<&str>,
+        illust_id: u64,
+    ) -> Result<UgoiraMeta, SiteError> {
+        Self::api(
+            client,
+            session,
+            &format!("illust/{}/ugoira_meta", illust_id),
+        )
+        .await
+    }
+
+    /// Copy images to storage so they can be loaded without the Referer
+    /// header, returning the new URLs in the same order.
+    ///
+    /// Uploads run in their own task, so they still finish and can be
+    /// reused by the next request if copying them times out.
+    async fn proxy_all(&self, urls: Vec<String>) -> Result<Vec<String>, SiteError> {
+        use futures::stream::{StreamExt, TryStreamExt};
+
+        let client = self.client.clone();
+        let storage = self.storage.clone();
+
+        let uploads = tokio::spawn(async move {
+            futures::stream::iter(urls)
+                .map(|url| Self::proxy(&client, &storage, url))
+                .buffered(Self::PROXY_CONCURRENCY)
+                .try_collect()
+                .await
+        });
+
+        match tokio::time::timeout(Self::PROXY_TIMEOUT, uploads).await {
+            Ok(result) => result.map_err(|err| SiteError::Internal(err.to_string()))?,
+            // Slow uploads aren't a problem with Pixiv, so they shouldn't
+            // count toward its circuit breaker.
+            Err(_elapsed) => Err(SiteError::Internal("copying images timed out".into())),
+        }
+    }
+
+    /// Copy an image to storage, returning the new URL.
+    async fn proxy(
+        client: &reqwest::Client,
+        storage: &crate::storage::Storage,
+        url: String,
+    ) -> Result<String, SiteError> {
+        let name = url
+            .rsplit('/')
+            .next()
+            .ok_or_else(|| SiteError::Parse("pixiv image was missing name".into()))?;
+        let key = format!("pixiv/{}", name);
+
+        if storage.exists(&key).await {
+            return Ok(storage.url(&key));
+        }
+
+        let resp = client
+            .get(&url)
+            .header(header::USER_AGENT, USER_AGENT)
+            .header(header::REFERER, Self::REFERER)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let content_type = resp
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .unwrap_or("image/jpeg")
+            .to_string();
+
+        let bytes = resp.bytes().await?.to_vec();
+        let len = bytes.len() as i64;
+
+        storage
+            .put(&key, &content_type, bytes.into(), len)
+            .await
+            .map_err(|err| SiteError::Internal(err.to_string()))
+    }
+}
+
+#[async_trait]
+impl Site for Pixiv {
+    fn name(&self) -> &'static str {
+        "Pixiv"
+    }
+
+    async fn url_supported(&self, url: &str) -> bool {
+        self.artwork.is_match(url) || self.legacy.is_match(url) || self.direct.is_match(url)
+    }
+
+    fn proxy_timeout(&self) -> Duration {
+        Self::PROXY_TIMEOUT
+    }
+
+    async fn get_images(
+        &self,
+        _user_id: i32,
+        url: &str,
+    ) -> Result<Option<Vec<PostInfo>>, SiteError> {
+        let captures = self
+            .artwork
+            .captures(url)
+            .or_else(|| self.legacy.captures(url))
+            .or_else(|| self.direct.captures(url))
+            .unwrap();
+
+        let id = &captures["id"];
+        // Direct links are to a single page of the illustration.
+        let page: Option<usize> = captures
+            .name("page")
+            .and_then(|page| page.as_str().parse().ok());
+
+        let session = self.session.as_deref();
+        let illust: PixivIllust =
+            Self::api(&self.client, session, &format!("illust/{}", id)).await?;
+
+        let source_link = format!("https://www.pixiv.net/artworks/{}", illust.illust_id);
+        let rating = Some(if illust.x_restrict == 0 {
+            Rating::Safe
+        } else {
+            Rating::Explicit
+        });
+        let posted_at = parse_date(&illust.create_date);
+
+        let mut tags = Vec::with_capacity(illust.tags.tags.len());
+        for tag in illust.tags.tags {
+            tags.push(tag.tag);
+            if let Some(english) = tag.translation.and_then(|mut tr| tr.remove("en")) {
+                tags.push(english);
+            }
+        }
+
+        let post = PostInfo {
+            source_link: Some(source_link),
+            title: Some(illust.illust_title),
+            artist: Some(illust.user_name),
+            rating,
+            tags,
+            posted_at,
+            site_name: self.name(),
+            ..Default::default()
+        };
+
+        if illust.illust_type == 2 {
+            let id: u64 = illust
+                .illust_id
+                .parse()
+                .map_err(|_err| SiteError::Parse("pixiv id was not a number".into()))?;
+            let meta = Self::ugoira_meta(&self.client, session, id).await?;
+
+            let thumb = match illust.urls.regular {
+                Some(regular) => self.proxy_all(vec![regular]).await?.pop(),
+                None => None,
+            };
+
+            return Ok(Some(vec![PostInfo {
+                file_type: file_ext(&meta.original_src)?,
+                url: meta.original_src,
+                thumb,
+                ..post
+            }]));
+        }
+
+        let pages: Vec<PixivPage> =
+            Self::api(&self.client, session, &format!("illust/{}/pages", id)).await?;
+
+        // The regular size is downscaled, so the dimensions of the original
+        // page can't be used.
+        let regular: Vec<String> = pages
+            .into_iter()
+            .enumerate()
+            .filter(|(idx, _page)| page.map(|page| page == *idx).unwrap_or(true))
+            .filter_map(|(_idx, page)| page.urls.regular)
+            .take(Self::MAX_PAGES)
+            .collect();
+
+        // Direct links may be to a page past the end of the work.
+        if regular.is_empty() {
+            return Ok(None);
+        }
+
+        let urls = self.proxy_all(regular.clone()).await?;
+
+        let posts = regular
+            .into_iter()
+            .zip(urls)
+            .map(|(regular, url)| {
+                Ok(PostInfo {
+                    file_type: file_ext(&regular)?,
+                    url,
+                    ..post.clone()
+                })
+            })
+            .collect::<Result<_, SiteError>>()?;
+
+        Ok(Some(posts))
+    }
+}
+
+/// Any ActivityPub server, such as Misskey, Pleroma, or Akkoma, loaded by
+/// requesting the ActivityStreams object for a post.
+pub struct ActivityPub {
+    client: reqwest::Client,
+    instance_cache: RwLock<HashMap<String, (bool, Instant)>>,
+    matcher: regex::Regex,
+}
+
+/// Hostname suffixes that only resolve on local networks.
+const LOCAL_SUFFIXES: &[&str] = &[
+    ".localhost",
+    ".local",
+    ".internal",
+    ".intranet",
+    ".lan",
+    ".home.arpa",
+];
+
+/// If a URL points at a public hostname that is safe to request.
+///
+/// Because any unknown host may be an ActivityPub server, this prevents
+/// links from making requests to IP addresses, such as cloud metadata
+/// services, or to hosts on the local network.
+fn is_public_url(url: &reqwest::Url) -> bool {
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+
+    // IP addresses are not domains, so they are always rejected.
+    let domain = match url.domain() {
+        Some(domain) => domain.trim_end_matches('.'),
+        None => return false,
+    };
+
+    domain.contains('.')
+        && domain != "localhost"
+        && !LOCAL_SUFFIXES.iter().any(|suffix| domain.ends_with(suffix))
+}
+
+/// ActivityStreams properties may be a single value or an array.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    fn into_vec(self) -> Vec<T> {
+        match self {
+            OneOrMany::One(item) => vec![item],
+            OneOrMany::Many(items) => items,
+        }
+    }
+}
+
+/// A link may be a plain URL or a Link object.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ActivityPubLink {
+    Url(String),
+    Link { href: String },
+}
+
+impl ActivityPubLink {
+    fn href(&self) -> &str {
+        match self {
+            ActivityPubLink::Url(url) => url,
+            ActivityPubLink::Link { href } => href,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ActivityPubNote {
+    id: String,
+    url: Option<OneOrMany<ActivityPubLink>>,
+    attributed_to: Option<OneOrMany<ActivityPubLink>>,
+    #[serde(default)]
+    sensitive: bool,
+    published: Option<String>,
+    summary: Option<String>,
+    attachment: Option<OneOrMany<ActivityPubAttachment>>,
+    tag: Option<OneOrMany<ActivityPubTag>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ActivityPubAttachment {
+    media_type: Option<String>,
+    url: OneOrMany<ActivityPubLink>,
+    name: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ActivityPubTag {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ActivityPubActor {
+    id: String,
+    preferred_username: String,
+}
+
+/// Get a file extension from a MIME type.
+fn mime_ext(mime: &str) -> Option<&'static str> {
+    let ext = match mime {
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "video/mp4" => "mp4",
+        "video/webm" => "webm",
+        _ => return None,
+    };
+
+    Some(ext)
+}
+
+impl ActivityPub {
+    const ACCEPT: &'static str = r#"application/activity+json, application/ld+json; profile="https://www.w3.org/ns/activitystreams""#;
+
+    /// Maximum time to wait for an instance to respond to a NodeInfo request.
+    const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+    /// How long to remember if a host is an ActivityPub server.
+    const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+    /// Maximum number of hosts to remember.
+    const CACHE_SIZE: usize = 1024;
+
+    pub fn new() -> Self {
+        // Redirects must also stay on public hosts.
+        let redirect = reqwest::redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() > 5 || !is_public_url(attempt.url()) {
+                attempt.stop()
+            } else {
+                attempt.follow()
+            }
+        });
+
+        let client = reqwest::Client::builder()
+            .redirect(redirect)
+            .build()
+            .expect("unable to build ActivityPub client");
+
+        Self {
+            client,
+            instance_cache: RwLock::new(HashMap::new()),
+            matcher: regex::Regex::new(r#"(?P<host>https?://[^/\s]+)/\S+"#).unwrap(),
+        }
+    }
+
+    /// Check if a host is an ActivityPub server, returning `None` if it
+    /// could not be determined.
+    async fn probe_instance(&self, base: &str) -> Option<bool> {
+        // Every ActivityPub server we care about publishes NodeInfo, which
+        // is cheaper to check than loading the object itself.
+        let resp = self
+            .client
+            .get(&format!("{}/.well-known/nodeinfo", base))
+            .header(header::USER_AGENT, USER_AGENT)
+            .timeout(Self::PROBE_TIMEOUT)
+            .send()
+            .await
+            .ok()?;
+
+        let status = resp.status();
+
+        if status.is_success() {
+            Some(true)
+        } else if status.is_client_error() && status.as_u16() != 429 {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    async fn cache_instance(&self, base: String, is_fedi: bool) {
+        let mut cache = self.instance_cache.write().await;
+
+        if cache.len() >= Self::CACHE_SIZE {
+            cache.retain(|_base, (_is_fedi, checked_at)| checked_at.elapsed() < Self::CACHE_TTL);
+        }
+
+        if cache.len() >= Self::CACHE_SIZE {
+            cache.clear();
+        }
+
+        cache.insert(base, (is_fedi, Instant::now()));
+    }
+
+    async fn get_object<T>(&self, url: &str) -> Result<T, SiteError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        match reqwest::Url::parse(url) {
+            Ok(parsed) if is_public_url(&parsed) => (),
+            _ => return Err(SiteError::NotFound),
+        }
+
+        let object = self
+            .client
+            .get(url)
+            .header(header::ACCEPT, Self::ACCEPT)
+            .header(header::USER_AGENT, USER_AGENT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(object)
+    }
+
+    /// Get a name for the actor who made a note, in the form of
+    /// `user@instance`.
+    async fn get_artist(&self, note: &ActivityPubNote) -> Option<String> {
+        let actor_url = match &note.attributed_to {
+            Some(OneOrMany::One(link)) => link.href().to_string(),
+            Some(OneOrMany::Many(links)) => links.first()?.href().to_string(),
+            None => return None,
+        };
+
+        let actor: ActivityPubActor = match self.get_object(&actor_url).await {
+            Ok(actor) => actor,
+            Err(err) => {
+                tracing::warn!("unable to load actor {}: {:?}", actor_url, err);
+                return None;
+            }
+        };
+
+        Some(actor_name(&actor))
+    }
+}
+
+/// Format an actor as `user@instance`.
+fn actor_name(actor: &ActivityPubActor) -> String {
+    match reqwest::Url::parse(&actor.id)
+        .ok()
+        .and_then(|url| url.host_str().map(ToString::to_string))
+    {
+        Some(host) => format!("{}@{}", actor.preferred_username, host),
+        None => actor.preferred_username.clone(),
+    }
+}
+
+/// Convert the attachments on a note into posts.
+fn note_posts(
+    note: ActivityPubNote,
+    artist: Option<String>,
+    site_name: &'static str,
+) -> Vec<PostInfo> {
+    let source_link = note
+        .url
+        .and_then(|url| url.into_vec().into_iter().next())
+        .map(|link| link.href().to_string())
+        .unwrap_or(note.id);
+
+    let tags: Vec<String> = note
+        .tag
+        .map(OneOrMany::into_vec)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|tag| tag.kind.as_deref() == Some("Hashtag"))
+        .filter_map(|tag| tag.name)
+        .map(|name| name.trim_start_matches('#').to_string())
+        .collect();
+
+    // Like Mastodon, notes are only marked as sensitive.
+    let rating = if note.sensitive {
+        Rating::Questionable
+    } else {
+        Rating::Safe
+    };
+
+    let posted_at = note.published.as_deref().and_then(parse_date);
+    let title = note.summary.filter(|summary| !summary.is_empty());
+
+    note.attachment
+        .map(OneOrMany::into_vec)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|attachment| {
+            let url = attachment.url.into_vec().into_iter().next()?;
+            let url = url.href().to_string();
+
+            let file_type = match attachment.media_type.as_deref().and_then(mime_ext) {
+                Some(ext) => ext.to_string(),
+                None => get_file_ext(&url)?.to_string(),
+            };
+
+            let dimensions = match (attachment.width, attachment.height) {
+                (Some(width), Some(height)) => Some((width, height)),
+                _ => None,
+            };
+
+            Some(PostInfo {
+                file_type,
+                url,
+                source_link: Some(source_link.clone()),
+                title: attachment.name.or_else(|| title.clone()),
+                artist: artist.clone(),
+                rating: Some(rating),
+                tags: tags.clone(),
+                dimensions,
+                posted_at,
+                site_name,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+#[async_trait]
+impl Site for ActivityPub {
+    fn name(&self) -> &'static str {
+        "ActivityPub"
+    }
+
+    async fn url_supported(&self, url: &str) -> bool {
+        let captures = match self.matcher.captures(url) {
+            Some(captures) => captures,
+            None => return false,
+        };
+
+        // Direct links to files are handled elsewhere.
+        if get_file_ext(url).is_some() {
+            return false;
+        }
+
+        match reqwest::Url::parse(url) {
+            Ok(parsed) if is_public_url(&parsed) => (),
+            _ => return false,
+        }
+
+        let base = captures["host"].to_lowercase();
+
+        if let Some((is_fedi, checked_at)) = self.instance_cache.read().await.get(&base) {
+            if checked_at.elapsed() < Self::CACHE_TTL {
+                return *is_fedi;
+            }
+        }
+
+        // Errors may be temporary, so they are not remembered.
+        match self.probe_instance(&base).await {
+            Some(is_fedi) => {
+                self.cache_instance(base, is_fedi).await;
+                is_fedi
+            }
+            None => false,
+        }
+    }
+
+    async fn get_images(
+        &self,
+        _user_id: i32,
+        url: &str,
+    ) -> Result<Option<Vec<PostInfo>>, SiteError> {
+        let note: ActivityPubNote = self.get_object(url).await?;
+        let artist = self.get_artist(&note).await;
+
+        let posts = note_posts(note, artist, self.name());
+
+        if posts.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(posts))
+    }
+
+    fn shares_breaker(&self) -> bool {
+        false
+    }
+}
+
+pub struct Bluesky {
+    client: reqwest::Client,
+    matcher: regex::Regex,
+}
+
+#[derive(Debug, Deserialize)]
+struct BlueskyThreadResponse {
+    thread: BlueskyThread,
+}
+
+#[derive(Debug, Deserialize)]
+struct BlueskyThread {
+    post: BlueskyPost,
+}
+
+#[derive(Debug, Deserialize)]
+struct BlueskyPost {
+    author: BlueskyAuthor,
+    record: BlueskyRecord,
+    embed: Option<BlueskyEmbed>,
+    #[serde(default)]
+    labels: Vec<BlueskyLabel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BlueskyAuthor {
+    handle: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BlueskyRecord {
+    created_at: Option<String>,
+}
+
+/// Embedded images are either directly on the post, or alongside a quoted
+/// post as media.
+#[derive(Debug, Deserialize)]
+struct BlueskyEmbed {
+    #[serde(default)]
+    images: Vec<BlueskyImage>,
+    media: Option<Box<BlueskyEmbed>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BlueskyImage {
+    thumb: String,
+    fullsize: String,
+    alt: Option<String>,
+    aspect_ratio: Option<BlueskyAspectRatio>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BlueskyAspectRatio {
+    width: u32,
+    height: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct BlueskyLabel {
+    val: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct BlueskyDid {
+    did: String,
+}
+
+impl Bluesky {
+    const API: &'static str = "https://public.api.bsky.app/xrpc";
+
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            matcher: regex::Regex::new(
+                r#"https?://bsky\.app/profile/(?P<actor>[\w.:-]+)/post/(?P<rkey>\w+)"#,
+            )
+            .unwrap(),
+        }
+    }
+
+    async fn resolve_handle(&self, handle: &str) -> Result<String, SiteError> {
+        let resp: BlueskyDid = self
+            .client
+            .get(&format!("{}/com.atproto.identity.resolveHandle", Self::API))
+            .query(&[("handle", handle)])
+            .header(header::USER_AGENT, USER_AGENT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(resp.did)
+    }
+}
+
+/// Convert the images on a Bluesky post into posts.
+fn bluesky_posts(
+    post: BlueskyPost,
+    link: &str,
+    site_name: &'static str,
+) -> Result<Vec<PostInfo>, SiteError> {
+    let mut embed = match post.embed {
+        Some(embed) => embed,
+        None => return Ok(vec![]),
+    };
+
+    if embed.images.is_empty() {
+        if let Some(media) = embed.media {
+            embed = *media;
+        }
+    }
+
+    let labels: Vec<&str> = post.labels.iter().map(|label| label.val.as_str()).collect();
+    let rating = if labels.contains(&"porn") {
+        Rating::Explicit
+    } else if labels.contains(&"sexual") || labels.contains(&"nudity") {
+        Rating::Questionable
+    } else {
+        Rating::Safe
+    };
+
+    let posted_at = post.record.created_at.as_deref().and_then(parse_date);
+
+    embed
+        .images
+        .into_iter()
+        .map(|image| {
+            // Image URLs end with the format, such as `cid@jpeg`.
+            let file_type = image
+                .fullsize
+                .rsplit('@')
+                .next()
+                .filter(|ext| !ext.contains('/'))
+                .map(ToString::to_string)
+                .ok_or_else(|| {
+                    SiteError::Parse(format!("missing image format: {}", image.fullsize))
+                })?;
+
+            Ok(PostInfo {
+                file_type,
+                url: image.fullsize,
+                thumb: Some(image.thumb),
+                source_link: Some(link.to_string()),
+                title: image.alt.filter(|alt| !alt.is_empty()),
+                artist: Some(post.author.handle.clone()),
+                rating: Some(rating),
+                dimensions: image.aspect_ratio.map(|ratio| (ratio.width, ratio.height)),
+                posted_at,
+                site_name,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+#[async_trait]
+impl Site for Bluesky {
+    fn name(&self) -> &'static str {
+        "Bluesky"
+    }
+
+    async fn url_supported(&self, url: &str) -> bool {
+        self.matcher.is_match(url)
+    }
+
+    async fn get_images(
+        &self,
+        _user_id: i32,
+        url: &str,
+    ) -> Result<Option<Vec<PostInfo>>, SiteError> {
+        let captures = self.matcher.captures(url).unwrap();
+
+        let actor = &captures["actor"];
+        let did = if actor.starts_with("did:") {
+            actor.to_string()
+        } else {
+            self.resolve_handle(actor).await?
+        };
+
+        let uri = format!("at://{}/app.bsky.feed.post/{}", did, &captures["rkey"]);
+
+        let resp: BlueskyThreadResponse = self
+            .client
+            .get(&format!("{}/app.bsky.feed.getPostThread", Self::API))
+            .query(&[("uri", uri.as_str()), ("depth", "0"), ("parentHeight", "0")])
+            .header(header::USER_AGENT, USER_AGENT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let posts = bluesky_posts(resp.thread.post, url, self.name())?;
+
+        if posts.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(posts))
+    }
+}
+
+pub struct Tumblr {
+    client: reqwest::Client,
+    api_key: String,
+    matcher: regex::Regex,
+}
+
+#[derive(Debug, Deserialize)]
+struct TumblrResp {
+    response: TumblrPosts,
+}
+
+#[derive(Debug, Deserialize)]
+struct TumblrPosts {
+    posts: Vec<TumblrPost>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TumblrPost {
+    blog_name: String,
+    post_url: String,
+    timestamp: i64,
+    #[serde(default)]
+    tags: Vec<String>,
+    summary: Option<String>,
+    #[serde(default)]
+    photos: Vec<TumblrPhoto>,
+    /// HTML body of text posts, which may contain inline images.
+    body: Option<String>,
+    video_url: Option<String>,
+    thumbnail_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TumblrPhoto {
+    original_size: TumblrPhotoSize,
+    #[serde(default)]
+    alt_sizes: Vec<TumblrPhotoSize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TumblrPhotoSize {
+    url: String,
+    width: u32,
+    height: u32,
+}
+
+impl Tumblr {
+    pub fn new(api_key: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_key,
+            matcher: regex::Regex::new(r"https?://(?:(?P<blog>[\w-]+)\.tumblr\.com/post|(?:www\.)?tumblr\.com/(?:blog/view/)?(?P<path_blog>[\w-]+))/(?P<id>\d+)").unwrap(),
+        }
+    }
+}
+
+/// Convert the photos, inline images, or video of a Tumblr post into posts.
+fn tumblr_posts(post: TumblrPost, site_name: &'static str) -> Result<Vec<PostInfo>, SiteError> {
+    let base = PostInfo {
+        source_link: Some(post.post_url.clone()),
+        title: post.summary.clone().filter(|summary| !summary.is_empty()),
+        artist: Some(post.blog_name.clone()),
+        tags: post.tags.clone(),
+        posted_at: Some(from_timestamp(post.timestamp)),
+        site_name,
+        ..Default::default()
+    };
+
+    if let Some(video_url) = post.video_url {
+        return Ok(vec![PostInfo {
+            file_type: file_ext(&video_url)?,
+            url: video_url,
+            thumb: post.thumbnail_url,
+            ..base
+        }]);
+    }
+
+    if !post.photos.is_empty() {
+        return post
+            .photos
+            .into_iter()
+            .map(|photo| {
+                // Sizes go from largest to smallest, so find a small one that
+                // is still usable.
+                let thumb = photo
+                    .alt_sizes
+                    .iter()
+                    .rev()
+                    .find(|size| size.width >= 250)
+                    .map(|size| size.url.clone());
+
+                Ok(PostInfo {
+                    file_type: file_ext(&photo.original_size.url)?,
+                    thumb,
+                    dimensions: Some((photo.original_size.width, photo.original_size.height)),
+                    url: photo.original_size.url,
+                    ..base.clone()
+                })
+            })
+            .collect();
+    }
+
+    let body = match post.body {
+        Some(body) => scraper::Html::parse_fragment(&body),
+        None => return Ok(vec![]),
+    };
+    let img = scraper::Selector::parse("img").unwrap();
+
+    body.select(&img)
+        .filter_map(|img| {
+            let img = img.value();
+            let url = img.attr("src")?.to_string();
+
+            let dimensions = match (
+                img.attr("data-orig-width")
+                    .and_then(|width| width.parse().ok()),
+                img.attr("data-orig-height")
+                    .and_then(|height| height.parse().ok()),
+            ) {
+                (Some(width), Some(height)) => Some((width, height)),
+                _ => None,
+            };
+
+            Some((url, dimensions))
+        })
+        .map(|(url, dimensions)| {
+            Ok(PostInfo {
+                file_type: file_ext(&url)?,
+                url,
+                dimensions,
+                ..base.clone()
+            })
+        })
+        .collect()
+}
+
+#[async_trait]
+impl Site for Tumblr {
+    fn name(&self) -> &'static str {
+        "Tumblr"
+    }
+
+    async fn url_supported(&self, url: &str) -> bool {
+        self.matcher.is_match(url)
+    }
+
+    async fn get_images(
+        &self,
+        _user_id: i32,
+        url: &str,
+    ) -> Result<Option<Vec<PostInfo>>, SiteError> {
+        let captures = self.matcher.captures(url).unwrap();
+
+        let blog = captures
+            .name("blog")
+            .or_else(|| captures.name("path_blog"))
+            .unwrap()
+            .as_str();
+
+        let resp: TumblrResp = self
+            .client
+            .get(&format!(
+                "https://api.tumblr.com/v2/blog/{}.tumblr.com/posts",
+                blog
+            ))
+            .query(&[("id", &captures["id"]), ("api_key", self.api_key.as_str())])
+            .header(header::USER_AGENT, USER_AGENT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let post = resp
+            .response
+            .posts
+            .into_iter()
+            .next()
+            .ok_or(SiteError::NotFound)?;
+
+        let posts = tumblr_posts(post, self.name())?;
+
+        if posts.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(posts))
+    }
+}
+
+pub struct Reddit {
+    client: reqwest::Client,
+    matcher: regex::Regex,
+}
+
+#[derive(Debug, Deserialize)]
+struct RedditListing {
+    data: RedditListingData,
+}
+
+#[derive(Debug, Deserialize)]
+struct RedditListingData {
+    children: Vec<RedditChild>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RedditChild {
+    data: RedditPost,
+}
+
+#[derive(Debug, Deserialize)]
+struct RedditPost {
+    title: String,
+    author: String,
+    permalink: String,
+    over_18: bool,
+    created_utc: f64,
+    url: Option<String>,
+    post_hint: Option<String>,
+    preview: Option<RedditPreview>,
+    secure_media: Option<RedditMedia>,
+    gallery_data: Option<RedditGallery>,
+    media_metadata: Option<HashMap<String, RedditMediaMetadata>>,
+    #[serde(default)]
+    crosspost_parent_list: Vec<RedditPost>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RedditPreview {
+    images: Vec<RedditPreviewImage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RedditPreviewImage {
+    source: RedditImage,
+    #[serde(default)]
+    resolutions: Vec<RedditImage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RedditImage {
+    url: String,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct RedditMedia {
+    reddit_video: Option<RedditVideo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RedditVideo {
+    hls_url: String,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct RedditGallery {
+    items: Vec<RedditGalleryItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RedditGalleryItem {
+    media_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RedditMediaMetadata {
+    /// MIME type of the image, like image/png.
+    m: Option<String>,
+    s: Option<RedditMediaSource>,
+    #[serde(default)]
+    p: Vec<RedditMediaSource>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RedditMediaSource {
+    u: Option<String>,
+    gif: Option<String>,
+    x: u32,
+    y: u32,
+}
+
+impl Reddit {
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            matcher: regex::Regex::new(r"https?://(?:(?:www\.|old\.|new\.)?reddit\.com/(?:r/\w+/comments|comments|gallery)|redd\.it)/(?P<id>\w+)").unwrap(),
+        }
+    }
+}
+
+/// Pick a preview that is large enough to be used as a thumbnail.
+fn reddit_thumb<'a, I>(sizes: I) -> Option<String>
+where
+    I: IntoIterator<Item = (u32, &'a str)>,
+{
+    sizes
+        .into_iter()
+        .find(|(width, _)| *width >= 320)
+        .map(|(_, url)| url.to_string())
+}
+
+/// Convert a Reddit post into posts for each image, gallery item, or video.
+fn reddit_posts(mut post: RedditPost, site_name: &'static str) -> Result<Vec<PostInfo>, SiteError> {
+    // Crossposts only reference the media of the original post.
+    if !post.crosspost_parent_list.is_empty() {
+        let parent = post.crosspost_parent_list.remove(0);
+        post = RedditPost {
+            title: post.title,
+            permalink: post.permalink,
+            ..parent
+        };
+    }
+
+    let base = PostInfo {
+        source_link: Some(format!("https://www.reddit.com{}", post.permalink)),
+        title: Some(post.title.clone()),
+        artist: Some(format!("u/{}", post.author)),
+        rating: Some(if post.over_18 {
+            Rating::Explicit
+        } else {
+            Rating::Safe
+        }),
+        posted_at: Some(from_timestamp(post.created_utc as i64)),
+        site_name,
+        ..Default::default()
+    };
+
+    let preview = post
+        .preview
+        .and_then(|preview| preview.images.into_iter().next());
+    let preview_thumb = preview.as_ref().and_then(|preview| {
+        reddit_thumb(
+            preview
+                .resolutions
+                .iter()
+                .map(|size| (size.width, size.url.as_str())),
+        )
+    });
+
+    // Reddit videos have separate audio and video streams, so the playlist
+    // is given to the video processing to combine them.
+    if let Some(video) = post.secure_media.and_then(|media| media.reddit_video) {
+        return Ok(vec![PostInfo {
+            file_type: "m3u8".into(),
+            url: video.hls_url,
+            thumb: preview_thumb,
+            dimensions: Some((video.width, video.height)),
+            ..base
+        }]);
+    }
+
+    if let (Some(gallery), Some(mut metadata)) = (post.gallery_data, post.media_metadata) {
+        let posts = gallery
+            .items
+            .into_iter()
+            .filter_map(|item| metadata.remove(&item.media_id))
+            .filter_map(|media| {
+                let source = media.s?;
+                let url = source.gif.or(source.u)?;
+
+                let file_type = match media.m.as_deref().and_then(mime_ext) {
+                    Some(ext) => ext.to_string(),
+                    None => get_file_ext(&url)?.to_string(),
+                };
+
+                let thumb = reddit_thumb(
+                    media
+                        .p
+                        .iter()
+                        .filter_map(|size| size.u.as_deref().map(|url| (size.x, url))),
+                );
+
+                Some(PostInfo {
+                    file_type,
+                    url,
+                    thumb,
+                    dimensions: Some((source.x, source.y)),
+                    ..base.clone()
+                })
+            })
+            .collect();
+
+        return Ok(posts);
+    }
+
+    match (post.post_hint.as_deref(), post.url) {
+        (Some("image"), Some(url)) => Ok(vec![PostInfo {
+            file_type: file_ext(&url)?,
+            dimensions: preview
+                .as_ref()
+                .map(|preview| (preview.source.width, preview.source.height)),
+            url,
+            thumb: preview_thumb,
+            ..base
+        }]),
+        // Links to other sites are left for those sites to load.
+        _ => Ok(vec![]),
+    }
+}
+
+#[async_trait]
+impl Site for Reddit {
+    fn name(&self) -> &'static str {
+        "Reddit"
+    }
+
+    async fn url_supported(&self, url: &str) -> bool {
+        self.matcher.is_match(url)
+    }
+
+    async fn get_images(
+        &self,
+        _user_id: i32,
+        url: &str,
+    ) -> Result<Option<Vec<PostInfo>>, SiteError> {
+        let captures = self.matcher.captures(url).unwrap();
+
+        let listings: Vec<RedditListing> = self
+            .client
+            .get(&format!(
+                "https://www.reddit.com/comments/{}.json",
+                &captures["id"]
+            ))
+            .query(&[("raw_json", "1")])
+            .header(header::USER_AGENT, USER_AGENT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let post = listings
+            .into_iter()
+            .next()
+            .and_then(|listing| listing.data.children.into_iter().next())
+            .ok_or(SiteError::NotFound)?
+            .data;
+
+        let posts = reddit_posts(post, self.name())?;
+
+        if posts.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(posts))
+    }
+}
+
+pub struct Imgur {
+    client: reqwest::Client,
+    client_id: String,
+    matcher: regex::Regex,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImgurResp<T> {
+    data: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImgurAlbum {
+    title: Option<String>,
+    link: String,
+    nsfw: Option<bool>,
+    datetime: i64,
+    account_url: Option<String>,
+    #[serde(default)]
+    images: Vec<ImgurImage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImgurImage {
+    id: String,
+    title: Option<String>,
+    #[serde(rename = "type")]
+    mime: String,
+    width: u32,
+    height: u32,
+    link: String,
+    mp4: Option<String>,
+    nsfw: Option<bool>,
+    datetime: i64,
+    account_url: Option<String>,
+}
+
+impl Imgur {
+    pub fn new(client_id: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            client_id,
+            matcher: regex::Regex::new(r"https?://(?:(?:www\.|m\.)?imgur\.com/(?:(?P<kind>a|gallery)/)?(?:[\w-]*-)?(?P<id>\w{5,})|i\.imgur\.com/(?P<gifv>\w+)\.gifv)(?:[/?#]|$)").unwrap(),
+        }
+    }
+
+    async fn get<T>(&self, path: &str) -> Result<T, SiteError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let resp: ImgurResp<T> = self
+            .client
+            .get(&format!("https://api.imgur.com/3/{}", path))
+            .header(
+                header::AUTHORIZATION,
+                format!("Client-ID {}", self.client_id),
+            )
+            .header(header::USER_AGENT, USER_AGENT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(resp.data)
+    }
+}
+
+fn imgur_rating(nsfw: Option<bool>) -> Option<Rating> {
+    nsfw.map(|nsfw| if nsfw { Rating::Explicit } else { Rating::Safe })
+}
+
+/// Convert an image into a post. Animated images use the MP4 version.
+fn imgur_post(image: ImgurImage, source_link: &str, site_name: &'static str) -> PostInfo {
+    let (file_type, url) = match image.mp4 {
+        Some(mp4) => ("mp4".to_string(), mp4),
+        None => (
+            mime_ext(&image.mime).unwrap_or("jpg").to_string(),
+            image.link,
+        ),
+    };
+
+    PostInfo {
+        file_type,
+        url,
+        // Adding m to the ID gets a medium thumbnail.
+        thumb: Some(format!("https://i.imgur.com/{}m.jpg", image.id)),
+        source_link: Some(source_link.to_string()),
+        title: image.title,
+        artist: image.account_url,
+        rating: imgur_rating(image.nsfw),
+        dimensions: Some((image.width, image.height)),
+        posted_at: Some(from_timestamp(image.datetime)),
+        site_name,
+        ..Default::default()
+    }
+}
+
+fn imgur_album_posts(album: ImgurAlbum, site_name: &'static str) -> Vec<PostInfo> {
+    let link = album.link;
+    let title = album.title;
+    let artist = album.account_url;
+    let rating = imgur_rating(album.nsfw);
+
+    album
+        .images
+        .into_iter()
+        .map(|image| {
+            let mut post = imgur_post(image, &link, site_name);
+
+            post.title = post.title.or_else(|| title.clone());
+            post.artist = post.artist.or_else(|| artist.clone());
+            post.rating = post.rating.or(rating);
+
+            post
+        })
+        .collect()
+}
+
+#[async_trait]
+impl Site for Imgur {
+    fn name(&self) -> &'static str {
+        "Imgur"
+    }
+
+    async fn url_supported(&self, url: &str) -> bool {
+        self.matcher.is_match(url)
+    }
+
+    async fn get_images(
+        &self,
+        _user_id: i32,
+        url: &str,
+    ) -> Result<Option<Vec<PostInfo>>, SiteError> {
+        let captures = self.matcher.captures(url).unwrap();
+
+        let posts = match (captures.name("kind"), captures.name("gifv")) {
+            (_, Some(id)) => {
+                let image = self.get(&format!("image/{}", id.as_str())).await?;
+                vec![imgur_post(image, url, self.name())]
+            }
+            (Some(kind), _) if kind.as_str() == "a" => {
+                let album = self.get(&format!("album/{}", &captures["id"])).await?;
+                imgur_album_posts(album, self.name())
+            }
+            // Gallery links may be to an album or a single image, but the
+            // album endpoint works for both.
+            (Some(_), _) => {
+                let album = self.get(&format!("album/{}", &captures["id"])).await;
+
+                match album {
+                    Ok(album) => imgur_album_posts(album, self.name()),
+                    Err(SiteError::NotFound) => {
+                        let image = self.get(&format!("image/{}", &captures["id"])).await?;
+                        vec![imgur_post(image, url, self.name())]
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            (None, _) => {
+                let image = self.get(&format!("image/{}", &captures["id"])).await?;
+                vec![imgur_post(image, url, self.name())]
+            }
+        };
+
+        if posts.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(posts))
+    }
+}
+
+pub struct Newgrounds {
+    client: reqwest::Client,
+
+    art: regex::Regex,
+    portal: regex::Regex,
+
+    image: scraper::Selector,
+    thumb: scraper::Selector,
+    title: scraper::Selector,
+    rating: scraper::Selector,
+    tags: scraper::Selector,
+}
+
+#[derive(Debug, Deserialize)]
+struct NewgroundsVideo {
+    title: String,
+    author: String,
+    /// Video files keyed by resolution, such as 720p.
+    sources: HashMap<String, Vec<NewgroundsSource>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NewgroundsSource {
+    src: String,
+}
+
+impl Newgrounds {
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::new(),
+
+            art: regex::Regex::new(
+                r"https?://(?:www\.)?newgrounds\.com/art/view/(?P<artist>[\w-]+)/[\w-]+",
+            )
+            .unwrap(),
+            portal: regex::Regex::new(
+                r"https?://(?:www\.)?newgrounds\.com/portal/view/(?P<id>\d+)",
+            )
+            .unwrap(),
+
+            image: scraper::Selector::parse("img[src], img[data-smartload-src]").unwrap(),
+            thumb: scraper::Selector::parse(r#"meta[property="og:image"]"#).unwrap(),
+            title: scraper::Selector::parse(r#"meta[property="og:title"]"#).unwrap(),
+            rating: scraper::Selector::parse(r#"[class*="rated-"]"#).unwrap(),
+            tags: scraper::Selector::parse("dd.tags a").unwrap(),
+        }
+    }
+
+    /// Get the images from an art page. Posts with multiple images show
+    /// every image on the page, always hosted under art.ngfiles.com/images.
+    fn art_posts(&self, page: &str, url: &str, artist: &str) -> Result<Vec<PostInfo>, SiteError> {
+        let page = scraper::Html::parse_document(page);
+
+        let meta = |selector: &scraper::Selector| {
+            page.select(selector)
+                .next()
+                .and_then(|meta| meta.value().attr("content"))
+                .map(ToString::to_string)
+        };
+
+        let rating = page
+            .select(&self.rating)
+            .flat_map(|elem| elem.value().classes())
+            .filter_map(|class| match class {
+                "rated-e" | "rated-t" => Some(Rating::Safe),
+                "rated-m" => Some(Rating::Questionable),
+                "rated-a" => Some(Rating::Explicit),
+                _ => None,
+            })
+            .next();
+
+        let tags: Vec<String> = page
+            .select(&self.tags)
+            .map(|tag| tag.text().collect::<String>().trim().to_string())
+            .collect();
+
+        let thumb = meta(&self.thumb);
+        let title = meta(&self.title);
+
+        let mut images: Vec<&str> = vec![];
+        for img in page.select(&self.image) {
+            let img = img.value();
+            let src = match img.attr("data-smartload-src").or_else(|| img.attr("src")) {
+                Some(src) if src.contains("art.ngfiles.com/images/") => src,
+                _ => continue,
+            };
+
+            if !images.contains(&src) {
+                images.push(src);
+            }
+        }
+
+        images
+            .into_iter()
+            .map(|src| {
+                Ok(PostInfo {
+                    file_type: file_ext(src)?,
+                    url: src.to_string(),
+                    thumb: thumb.clone(),
+                    source_link: Some(url.to_string()),
+                    title: title.clone(),
+                    artist: Some(artist.to_string()),
+                    rating,
+                    tags: tags.clone(),
+                    site_name: self.name(),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    async fn get_page(&self, url: &str) -> Result<String, SiteError> {
+        let page = self
+            .client
+            .get(url)
+            .header(header::USER_AGENT, USER_AGENT)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        Ok(page)
+    }
+}
+
+/// Pick the highest resolution video from a portal submission.
+fn newgrounds_video(
+    video: NewgroundsVideo,
+    url: &str,
+    thumb: Option<String>,
+    site_name: &'static str,
+) -> Result<Option<PostInfo>, SiteError> {
+    let best = video
+        .sources
+        .into_iter()
+        .filter_map(|(resolution, sources)| {
+            let height: u32 = resolution.trim_end_matches('p').parse().ok()?;
+            let source = sources.into_iter().next()?;
+
+            Some((height, source))
+        })
+        .max_by_key(|(height, _)| *height);
+
+    let source = match best {
+        Some((_height, source)) => source,
+        None => return Ok(None),
+    };
+
+    Ok(Some(PostInfo {
+        file_type: file_ext(&source.src)?,
+        url: source.src,
+        thumb,
+        source_link: Some(url.to_string()),
+        title: Some(video.title),
+        artist: Some(video.author),
+        site_name,
+        ..Default::default()
+    }))
+}
+
+#[async_trait]
+impl Site for Newgrounds {
+    fn name(&self) -> &'static str {
+        "Newgrounds"
+    }
+
+    async fn url_supported(&self, url: &str) -> bool {
+        self.art.is_match(url) || self.portal.is_match(url)
+    }
+
+    async fn get_images(
+        &self,
+        _user_id: i32,
+        url: &str,
+    ) -> Result<Option<Vec<PostInfo>>, SiteError> {
+        if let Some(captures) = self.art.captures(url) {
+            let page = self.get_page(url).await?;
+            let posts = self.art_posts(&page, url, &captures["artist"])?;
+
+            if posts.is_empty() {
+                return Ok(None);
+            }
+
+            return Ok(Some(posts));
+        }
+
+        let captures = self.portal.captures(url).unwrap();
+        let id = &captures["id"];
+
+        let thumb = {
+            let page = scraper::Html::parse_document(&self.get_page(url).await?);
+            page.select(&self.thumb)
+                .next()
+                .and_then(|meta| meta.value().attr("content"))
+                .map(ToString::to_string)
+        };
+
+        // Video details are only returned for requests that look like they
+        // came from the site's own player.
+        let video: NewgroundsVideo = self
+            .client
+            .get(&format!("https://www.newgrounds.com/portal/video/{}", id))
+            .header("X-Requested-With", "XMLHttpRequest")
+            .header(header::USER_AGENT, USER_AGENT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let post = newgrounds_video(
+            video,
+            &format!("https://www.newgrounds.com/portal/view/{}", id),
+            thumb,
+            self.name(),
+        )?;
+
+        Ok(post.map(|post| vec![post]))
+    }
+}
+
+pub struct SoFurry {
+    client: reqwest::Client,
+    matcher: regex::Regex,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SoFurrySubmission {
+    id: String,
+    title: String,
+    author: String,
+    /// One for artwork and four for photos, other types are stories,
+    /// music, and journals.
+    content_type: String,
+    /// Zero for clean, one for mature, and two for adult.
+    content_level: String,
+    thumbnail: String,
+    full: String,
+    /// Comma separated list of tags.
+    #[serde(default)]
+    tags: String,
+    /// Unix timestamp, as a string.
+    post_time: Option<String>,
+}
+
+impl SoFurry {
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            matcher: regex::Regex::new(r"https?://(?:www\.)?sofurry\.com/(?:view|s)/(?P<id>\d+)")
+                .unwrap(),
+        }
+    }
+}
+
+fn sofurry_post(submission: SoFurrySubmission, site_name: &'static str) -> Option<PostInfo> {
+    if submission.content_type != "1" && submission.content_type != "4" {
+        return None;
+    }
+
+    let rating = match submission.content_level.as_ref() {
+        "0" => Some(Rating::Safe),
+        "1" => Some(Rating::Questionable),
+        "2" => Some(Rating::Explicit),
+        _ => None,
+    };
+
+    // Full images are served by an endpoint without an extension, and
+    // Telegram handles any image type the same way.
+    let file_type = get_file_ext(&submission.full).unwrap_or("jpg").to_string();
+
+    Some(PostInfo {
+        file_type,
+        url: submission.full,
+        thumb: Some(submission.thumbnail),
+        source_link: Some(format!("https://www.sofurry.com/view/{}", submission.id)),
+        title: Some(submission.title),
+        artist: Some(submission.author),
+        rating,
+        tags: submission
+            .tags
+            .split(',')
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect(),
+        posted_at: submission
+            .post_time
+            .and_then(|time| time.parse().ok())
+            .map(from_timestamp),
+        site_name,
+        ..Default::default()
+    })
+}
+
+#[async_trait]
+impl Site for SoFurry {
+    fn name(&self) -> &'static str {
+        "SoFurry"
+    }
+
+    async fn url_supported(&self, url: &str) -> bool {
+        self.matcher.is_match(url)
+    }
+
+    async fn get_images(
+        &self,
+        _user_id: i32,
+        url: &str,
+    ) -> Result<Option<Vec<PostInfo>>, SiteError> {
+        let captures = self.matcher.captures(url).unwrap();
+
+        let submission: SoFurrySubmission = self
+            .client
+            .get("https://api2.sofurry.com/std/getSubmissionDetails")
+            .query(&[("id", &captures["id"])])
+            .header(header::USER_AGENT, USER_AGENT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(sofurry_post(submission, self.name()).map(|post| vec![post]))
+    }
+}
+
+pub struct FurryNetwork {
+    client: reqwest::Client,
+    matcher: regex::Regex,
+}
+
+#[derive(Debug, Deserialize)]
+struct FurryNetworkArtwork {
+    id: i32,
+    title: Option<String>,
+    /// Zero for general, one for mature, and two for explicit.
+    rating: u8,
+    created: Option<String>,
+    character: FurryNetworkCharacter,
+    images: FurryNetworkImages,
+    #[serde(default)]
+    tags: Vec<FurryNetworkTag>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FurryNetworkCharacter {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct FurryNetworkImages {
+    original: String,
+    thumbnail: Option<String>,
+    medium: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FurryNetworkTag {
+    value: String,
+}
+
+impl FurryNetwork {
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            matcher: regex::Regex::new(
+                r"https?://(?:beta\.)?furrynetwork\.com/artwork/(?P<id>\d+)",
+            )
+            .unwrap(),
+        }
+    }
+}
+
+fn furrynetwork_post(
+    artwork: FurryNetworkArtwork,
+    site_name: &'static str,
+) -> Result<PostInfo, SiteError> {
+    let rating = match artwork.rating {
+        0 => Some(Rating::Safe),
+        1 => Some(Rating::Questionable),
+        2 => Some(Rating::Explicit),
+        _ => None,
+    };
+
+    Ok(PostInfo {
+        file_type: file_ext(&artwork.images.original)?,
+        url: artwork.images.original,
+        thumb: artwork.images.medium.or(artwork.images.thumbnail),
+        source_link: Some(format!("https://furrynetwork.com/artwork/{}/", artwork.id)),
+        title: artwork.title,
+        artist: Some(artwork.character.name),
+        rating,
+        tags: artwork.tags.into_iter().map(|tag| tag.value).collect(),
+        posted_at: artwork.created.as_deref().and_then(parse_date),
+        site_name,
+        ..Default::default()
+    })
+}
+
+#[async_trait]
+impl Site for FurryNetwork {
+    fn name(&self) -> &'static str {
+        "Furry Network"
+    }
+
+    async fn url_supported(&self, url: &str) -> bool {
+        self.matcher.is_match(url)
+    }
+
+    async fn get_images(
+        &self,
+        _user_id: i32,
+        url: &str,
+    ) -> Result<Option<Vec<PostInfo>>, SiteError> {
+        let captures = self.matcher.captures(url).unwrap();
+
+        let artwork: FurryNetworkArtwork = self
+            .client
+            .get(&format!(
+                "https://furrynetwork.com/api/artwork/{}",
+                &captures["id"]
+            ))
+            .header(header::USER_AGENT, USER_AGENT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(Some(vec![furrynetwork_post(artwork, self.name())?]))
+    }
+}
+
+pub struct DeviantArt {
+    client: reqwest::Client,
+    client_id: String,
+    client_secret: String,
+
+    token: RwLock<Option<(String, Instant)>>,
+    app_url: scraper::Selector,
+    matcher: regex::Regex,
+}
+
+/// Numbers in DeviantArt's oEmbed responses are sometimes strings.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum DeviantArtNumber {
+    Number(u32),
+    Text(String),
+}
+
+impl DeviantArtNumber {
+    fn value(&self) -> Option<u32> {
+        match self {
+            DeviantArtNumber::Number(num) => Some(*num),
+            DeviantArtNumber::Text(text) => text.parse().ok(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviantArtOEmbed {
+    #[serde(rename = "type")]
+    kind: String,
+    title: String,
+    url: Option<String>,
+    author_name: String,
+    thumbnail_url: Option<String>,
+    width: Option<DeviantArtNumber>,
+    height: Option<DeviantArtNumber>,
+    /// Either adult or nonadult.
+    safety: Option<String>,
+    pubdate: Option<String>,
+    /// Comma separated list of tags.
+    tags: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviantArtToken {
+    access_token: String,
+    expires_in: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviantArtUser {
+    username: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviantArtImage {
+    src: String,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviantArtDeviation {
+    url: String,
+    title: String,
+    is_mature: bool,
+    /// Unix timestamp, as a string.
+    published_time: Option<String>,
+    author: DeviantArtUser,
+    content: Option<DeviantArtImage>,
+    #[serde(default)]
+    thumbs: Vec<DeviantArtImage>,
+}
+
+impl DeviantArt {
+    const API_TOKEN: &'static str = "https://www.deviantart.com/oauth2/token";
+
+    pub fn new(client_id: String, client_secret: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            client_id,
+            client_secret,
+
+            token: RwLock::new(None),
+            app_url: scraper::Selector::parse(r#"meta[property="da:appurl"]"#).unwrap(),
+            matcher: regex::Regex::new(r"https?://(?:(?:www\.)?deviantart\.com/[\w-]+/art/[\w-]+|[\w-]+\.deviantart\.com/art/[\w-]+|fav\.me/\w+)").unwrap(),
+        }
+    }
+
+    /// Get an access token using the app's credentials, reusing it until it
+    /// expires.
+    async fn get_token(&self) -> Result<String, SiteError> {
+        if let Some((token, expires)) = &*self.token.read().await {
+            if Instant::now() < *expires {
+                return Ok(token.clone());
+            }
+        }
+
+        let resp: DeviantArtToken = self
+            .client
+            .post(Self::API_TOKEN)
+            .form(&[
+                ("grant_type", "client_credentials"),
+                ("client_id", &self.client_id),
+                ("client_secret", &self.client_secret),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        // Refresh a little early so a token doesn't expire mid-request.
+        let expires = Instant::now() + Duration::from_secs(resp.expires_in.saturating_sub(60));
+        *self.token.write().await = Some((resp.access_token.clone(), expires));
+
+        Ok(resp.access_token)
+    }
+
+    /// Load a deviation through the API. oEmbed doesn't include the image
+    /// for mature deviations, so they need to be loaded this way.
+    async fn load_deviation(&self, url: &str) -> Result<PostInfo, SiteError> {
+        let page = self
+            .client
+            .get(url)
+            .header(header::USER_AGENT, USER_AGENT)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        let id = deviation_id(&scraper::Html::parse_document(&page), &self.app_url)
+            .ok_or_else(|| SiteError::Parse("deviantart page was missing id".into()))?;
+
+        let token = self.get_token().await?;
+
+        let deviation: DeviantArtDeviation = self
+            .client
+            .get(&format!(
+                "https://www.deviantart.com/api/v1/oauth2/deviation/{}",
+                id
+            ))
+            .query(&[("access_token", token.as_str()), ("mature_content", "true")])
+            .header(header::USER_AGENT, USER_AGENT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        deviation_post(deviation, self.name())
+    }
+}
+
+/// Get the deviation's UUID from the app link on its page.
+fn deviation_id(page: &scraper::Html, app_url: &scraper::Selector) -> Option<String> {
+    let content = page.select(app_url).next()?.value().attr("content")?;
+    let id = content.rsplit('/').next()?;
+
+    if id.is_empty() {
+        None
+    } else {
+        Some(id.to_string())
+    }
+}
+
+/// Convert an oEmbed response into a post. Returns None if the response
+/// didn't include an image, such as for mature deviations.
+fn oembed_post(
+    oembed: DeviantArtOEmbed,
+    link: &str,
+    site_name: &'static str,
+) -> Result<Option<PostInfo>, SiteError> {
+    let url = match oembed.url {
+        Some(url) if oembed.kind == "photo" => url,
+        _ => return Ok(None),
+    };
+
+    let rating = oembed.safety.as_deref().map(|safety| match safety {
+        "adult" => Rating::Explicit,
+        _ => Rating::Safe,
+    });
+
+    let dimensions = match (
+        oembed.width.as_ref().and_then(DeviantArtNumber::value),
+        oembed.height.as_ref().and_then(DeviantArtNumber::value),
+    ) {
+        (Some(width), Some(height)) => Some((width, height)),
+        _ => None,
+    };
+
+    let tags = oembed
+        .tags
+        .map(|tags| {
+            tags.split(',')
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(Some(PostInfo {
+        file_type: file_ext(&url)?,
+        url,
+        thumb: oembed.thumbnail_url,
+        source_link: Some(link.to_string()),
+        title: Some(oembed.title),
+        artist: Some(oembed.author_name),
+        rating,
+        tags,
+        dimensions,
+        posted_at: oembed.pubdate.as_deref().and_then(parse_date),
+        site_name,
+        ..Default::default()
+    }))
+}
+
+/// Convert a deviation from the API into a post.
+fn deviation_post(
+    deviation: DeviantArtDeviation,
+    site_name: &'static str,
+) -> Result<PostInfo, SiteError> {
+    let content = deviation
+        .content
+        .ok_or_else(|| SiteError::Parse("deviation had no image".into()))?;
+
+    let posted_at = deviation
+        .published_time
+        .and_then(|time| time.parse().ok())
+        .map(from_timestamp);
+
+    Ok(PostInfo {
+        file_type: file_ext(&content.src)?,
+        thumb: deviation.thumbs.into_iter().last().map(|thumb| thumb.src),
+        url: content.src,
+        source_link: Some(deviation.url),
+        title: Some(deviation.title),
+        artist: Some(deviation.author.username),
+        rating: Some(if deviation.is_mature {
+            Rating::Explicit
+        } else {
+            Rating::Safe
+        }),
+        dimensions: Some((content.width, content.height)),
+        posted_at,
+        site_name,
+        ..Default::default()
+    })
+}
+
+#[async_trait]
+impl Site for DeviantArt {
+    fn name(&self) -> &'static str {
+        "DeviantArt"
+    }
+
+    async fn url_supported(&self, url: &str) -> bool {
+        self.matcher.is_match(url)
+    }
+
+    async fn get_images(
+        &self,
+        _user_id: i32,
+        url: &str,
+    ) -> Result<Option<Vec<PostInfo>>, SiteError> {
+        let oembed: DeviantArtOEmbed = self
+            .client
+            .get("https://backend.deviantart.com/oembed")
+            .query(&[("url", url)])
+            .header(header::USER_AGENT, USER_AGENT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if let Some(post) = oembed_post(oembed, url, self.name())? {
+            return Ok(Some(vec![post]));
+        }
+
+        // fav.me links redirect to the deviation, which is followed when
+        // loading the page.
+        let post = self.load_deviation(url).await?;
+
+        Ok(Some(vec![post]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    #[test]
+    fn test_get_file_ext() {
+        let tests = vec![
+            ("https://example.com/image.png", Some("png")),
+            ("https://example.com/image.jpg?size=large", Some("jpg")),
+            ("1234.artist_name.gif", Some("gif")),
+            ("https://example.com/image", None),
+            ("https://example.com/", None),
+        ];
+
+        for (name, ext) in tests {
+            assert_eq!(super::get_file_ext(name), ext, "wrong ext for {}", name);
+        }
+    }
+
+    #[test]
+    fn test_ugoira_id() {
+        let tests = vec![
+            (
+                "https://i.pximg.net/img-zip-ugoira/img/2020/01/01/00/00/00/12345_ugoira1920x1080.zip",
+                Some(12345),
+            ),
+            (
+                "https://i.pximg.net/img-original/img/2020/01/01/00/00/00/12345_p0.png",
+                None,
+            ),
+            ("https://example.com/12345_ugoira1920x1080.zip", None),
+        ];
+
+        for (url, id) in tests {
+            assert_eq!(super::Pixiv::ugoira_id(url), id, "wrong id for {}", url);
+        }
+    }
+
+    #[test]
+    fn test_oauth_signature() {
+        // Example from Twitter's documentation on creating a signature.
+        let params = vec![
+            (
+                "status",
+                "Hello Ladies + Gentlemen, a signed OAuth request!",
+            ),
+            ("include_entities", "true"),
+            ("oauth_consumer_key", "xvz1evFS4wEEPTGEFPHBog"),
+            ("oauth_nonce", "kYjzVBB8Y0ZFabxSWbWovY3uYSQ2pTgmZeNu2VS4cg"),
+            ("oauth_signature_method", "HMAC-SHA1"),
+            ("oauth_timestamp", "1318622958"),
+            (
+                "oauth_token",
+                "370773112-GmHxMAgYyLbNEtIKZeRNFsMKPR9EyMZeS9weJAEb",
+            ),
+            ("oauth_version", "1.0"),
+        ];
+
+        let signature = super::oauth_signature(
+            "POST",
+            "https://api.twitter.com/1.1/statuses/update.json",
+            &params,
+            "kAcSOqF21Fu85e7zjz7ZN2U4ZRhfV3WpwPAoE3Z7kBw",
+            "LswwdoUaIvS8ltyTt5jkRh4J50vUPVVHtR2YPi5kE",
+        );
+
+        assert_eq!(signature, "hCtSmYh+iHYCEqBWrE7C7hYmtUk=");
+        assert_eq!(super::oauth_encode("a b+c~"), "a%20b%2Bc~");
+    }
+
+    #[test]
+    fn test_deviantart_oembed() {
+        let oembed =
+            serde_json::from_str(include_str!("../tests/fixtures/deviantart/oembed.json")).unwrap();
+
+        let link = "https://www.deviantart.com/example-artist/art/Autumn-Fox-834512344";
+        let post = super::oembed_post(oembed, link, "DeviantArt")
+            .unwrap()
+            .expect("missing post");
+
+        assert_eq!(post.file_type, "png");
+        assert_eq!(post.title.as_deref(), Some("Autumn Fox"));
+        assert_eq!(post.artist.as_deref(), Some("example-artist"));
+        assert_eq!(post.rating, Some(super::Rating::Safe));
+        assert_eq!(post.tags, vec!["fox", "autumn", "digitalpainting"]);
+        assert_eq!(post.dimensions, Some((1280, 960)));
+        assert_eq!(post.source_link.as_deref(), Some(link));
+        assert!(post.thumb.unwrap().ends_with("-150.png"));
+        assert!(post.posted_at.is_some());
+    }
+
+    #[test]
+    fn test_deviantart_oembed_mature() {
+        let oembed = serde_json::from_str(include_str!(
+            "../tests/fixtures/deviantart/oembed_mature.json"
+        ))
+        .unwrap();
+
+        let post = super::oembed_post(oembed, "https://fav.me/ddsu2k9", "DeviantArt").unwrap();
+        assert!(post.is_none(), "mature oembed should need the api");
+    }
+
+    #[test]
+    fn test_deviantart_deviation() {
+        let page = scraper::Html::parse_document(include_str!(
+            "../tests/fixtures/deviantart/deviation.html"
+        ));
+        let selector = scraper::Selector::parse(r#"meta[property="da:appurl"]"#).unwrap();
+
+        assert_eq!(
+            super::deviation_id(&page, &selector).as_deref(),
+            Some("1F4C2E4A-8A2B-D1C5-3B7E-9A1D0E6F2C11")
+        );
+
+        let deviation =
+            serde_json::from_str(include_str!("../tests/fixtures/deviantart/deviation.json"))
+                .unwrap();
+        let post = super::deviation_post(deviation, "DeviantArt").unwrap();
+
+        assert_eq!(post.file_type, "jpg");
+        assert_eq!(post.title.as_deref(), Some("Night Swim"));
+        assert_eq!(post.artist.as_deref(), Some("example-artist"));
+        assert_eq!(post.rating, Some(super::Rating::Explicit));
+        assert_eq!(post.dimensions, Some((1200, 1600)));
+        assert!(post.thumb.unwrap().ends_with("-300w.jpg"));
+        assert_eq!(post.posted_at.unwrap().timestamp(), 1_584_284_400);
+    }
+
+    #[test]
+    fn test_activitypub_note() {
+        let note =
+            serde_json::from_str(include_str!("../tests/fixtures/activitypub/note.json")).unwrap();
+
+        let posts = super::note_posts(note, Some("artist@misskey.example".into()), "ActivityPub");
+        assert_eq!(posts.len(), 2);
+
+        let first = &posts[0];
+        assert_eq!(first.file_type, "png");
+        assert_eq!(
+            first.source_link.as_deref(),
+            Some("https://misskey.example/notes/9k2m4x7a1b")
+        );
+        assert_eq!(first.title.as_deref(), Some("A fox sitting in snow"));
+        assert_eq!(first.artist.as_deref(), Some("artist@misskey.example"));
+        assert_eq!(first.rating, Some(super::Rating::Questionable));
+        assert_eq!(first.tags, vec!["fox"]);
+        assert!(first.posted_at.is_some());
+
+        let second = &posts[1];
+        assert_eq!(second.file_type, "jpg");
+        assert_eq!(
+            second.url,
+            "https://files.misskey.example/webpublic-7b2d4f10"
+        );
+        assert_eq!(second.dimensions, Some((1600, 900)));
+        assert_eq!(second.title, None);
+    }
+
+    #[test]
+    fn test_activitypub_actor_name() {
+        let actor: super::ActivityPubActor = serde_json::from_value(serde_json::json!({
+            "id": "https://pleroma.example/users/fox",
+            "preferredUsername": "fox",
+        }))
+        .unwrap();
+
+        assert_eq!(super::actor_name(&actor), "fox@pleroma.example");
+    }
+
+    #[test]
+    fn test_is_public_url() {
+        let is_public = |url: &str| super::is_public_url(&reqwest::Url::parse(url).unwrap());
+
+        assert!(is_public("https://pleroma.example/notes/1"));
+        assert!(is_public("http://misskey.example./notes/1"));
+
+        assert!(!is_public("http://169.254.169.254/latest/meta-data"));
+        assert!(!is_public("http://127.0.0.1:8080/notes/1"));
+        assert!(!is_public("http://0x7f.1/notes/1"));
+        assert!(!is_public("http://[::1]/notes/1"));
+        assert!(!is_public("http://localhost/notes/1"));
+        assert!(!is_public("http://metadata/computeMetadata"));
+        assert!(!is_public("http://printer.local/status"));
+        assert!(!is_public(
+            "http://metadata.google.internal/computeMetadata"
+        ));
+        assert!(!is_public("ftp://pleroma.example/notes/1"));
+    }
+
+    #[test]
+    fn test_bluesky_post() {
+        let resp: super::BlueskyThreadResponse =
+            serde_json::from_str(include_str!("../tests/fixtures/bluesky/thread.json")).unwrap();
+
+        let link = "https://bsky.app/profile/example-artist.bsky.social/post/3kq7x2yv4bz2a";
+        let posts = super::bluesky_posts(resp.thread.post, link, "Bluesky").unwrap();
+        assert_eq!(posts.len(), 1);
+
+        let post = &posts[0];
+        assert_eq!(post.file_type, "jpeg");
+        assert!(post.url.contains("feed_fullsize"));
+        assert!(post.thumb.as_ref().unwrap().contains("feed_thumbnail"));
+        assert_eq!(post.source_link.as_deref(), Some(link));
+        assert_eq!(post.title.as_deref(), Some("Sketch of a wolf"));
+        assert_eq!(post.artist.as_deref(), Some("example-artist.bsky.social"));
+        assert_eq!(post.rating, Some(super::Rating::Questionable));
+        assert_eq!(post.dimensions, Some((2000, 1500)));
+    }
+
+    #[test]
+    fn test_booru_urls() {
+        use super::{Booru, BooruKind};
+
+        let booru = |kind, config| {
+            let (host, file_host) = Booru::parse_config(config).unwrap();
+            let name = Box::leak(Booru::default_name(&host).into_boxed_str());
+            Booru::new(kind, host, &file_host, name)
+        };
+
+        let danbooru = booru(BooruKind::Danbooru, "danbooru.donmai.us|cdn.donmai.us");
+        assert_eq!(danbooru.name, "Danbooru");
+        assert!(danbooru
+            .show
+            .is_match("https://danbooru.donmai.us/posts/4567890"));
+        assert!(danbooru
+            .data
+            .is_match("https://cdn.donmai.us/original/d3/4f/d34fb9ca7bd6a7e8b2bc7ab40cd2f0be.png"));
+        assert!(danbooru.data.is_match(
+            "https://cdn.donmai.us/sample/d3/4f/sample-d34fb9ca7bd6a7e8b2bc7ab40cd2f0be.jpg"
+        ));
+
+        // Other hosts on the same domain don't claim each other's files.
+        let safebooru = booru(BooruKind::Danbooru, "safebooru.donmai.us");
+        assert!(!safebooru
+            .data
+            .is_match("https://cdn.donmai.us/original/d3/4f/d34fb9ca7bd6a7e8b2bc7ab40cd2f0be.png"));
+
+        let gelbooru = booru(BooruKind::Gelbooru, "gelbooru.com");
+        assert!(gelbooru
+            .show
+            .is_match("https://gelbooru.com/index.php?page=post&s=view&id=7654321"));
+        assert!(gelbooru.data.is_match(
+            "https://img3.gelbooru.com/images/d3/4f/d34fb9ca7bd6a7e8b2bc7ab40cd2f0be.jpg"
+        ));
+        assert!(!gelbooru
+            .show
+            .is_match("https://danbooru.donmai.us/posts/4567890"));
+
+        let philomena = booru(BooruKind::Philomena, "derpibooru.org|derpicdn.net");
+        assert_eq!(philomena.name, "Derpibooru");
+        assert!(philomena
+            .show
+            .is_match("https://derpibooru.org/images/2345678"));
+        assert!(philomena
+            .data
+            .is_match("https://derpicdn.net/img/view/2020/5/4/2345678.png"));
+        assert!(!philomena
+            .data
+            .is_match("https://derpibooru.org/images/2345678"));
+    }
+
+    #[test]
+    fn test_booru_config() {
+        use super::Booru;
+
+        assert_eq!(
+            Booru::parse_config(" Derpibooru.org | derpicdn.net "),
+            Some(("derpibooru.org".to_string(), "derpicdn.net".to_string()))
+        );
+        assert_eq!(
+            Booru::parse_config("gelbooru.com"),
+            Some(("gelbooru.com".to_string(), "gelbooru.com".to_string()))
+        );
+
+        for config in &[
+            "",
+            "https://gelbooru.com",
+            "gelbooru.com/",
+            "gelbooru.com:8080",
+            "gelbooru.com|",
+            "a.com|b.com|c.com",
+            "127.0.0.1",
+        ] {
+            assert_eq!(Booru::parse_config(config), None, "{} was valid", config);
+        }
+
+        assert_eq!(Booru::default_name("www.e-shuushuu.net"), "E-shuushuu");
+    }
+
+    #[test]
+    fn test_danbooru_post() {
+        let post =
+            serde_json::from_str(include_str!("../tests/fixtures/booru/danbooru.json")).unwrap();
+        let post = super::danbooru_post(post, "danbooru.donmai.us", "Danbooru").unwrap();
+
+        assert_eq!(post.file_type, "png");
+        assert_eq!(
+            post.source_link.as_deref(),
+            Some("https://danbooru.donmai.us/posts/4567890")
+        );
+        assert_eq!(post.artist.as_deref(), Some("example_artist"));
+        assert_eq!(post.rating, Some(super::Rating::Safe));
+        assert!(post.tags.contains(&"fox".to_string()));
+        assert_eq!(post.dimensions, Some((2048, 1536)));
+        assert!(post.posted_at.is_some());
+    }
+
+    #[test]
+    fn test_gelbooru_post() {
+        let resp =
+            serde_json::from_str(include_str!("../tests/fixtures/booru/gelbooru.json")).unwrap();
+        let post = match resp {
+            super::GelbooruResp::Wrapped { mut post } => post.remove(0),
+            super::GelbooruResp::Posts(_) => panic!("expected wrapped response"),
+        };
+        let post = super::gelbooru_post(post, "gelbooru.com", "Gelbooru").unwrap();
+
+        assert_eq!(post.file_type, "jpg");
+        assert_eq!(
+            post.source_link.as_deref(),
+            Some("https://gelbooru.com/index.php?page=post&s=view&id=7654321")
+        );
+        assert_eq!(post.rating, Some(super::Rating::Questionable));
+        assert_eq!(post.tags, vec!["1girl", "fox_ears", "solo"]);
+        assert_eq!(post.dimensions, Some((1200, 1700)));
+        assert!(post.posted_at.is_some());
+    }
+
+    #[test]
+    fn test_philomena_post() {
+        let resp: super::PhilomenaResp =
+            serde_json::from_str(include_str!("../tests/fixtures/booru/philomena.json")).unwrap();
+        let post = super::philomena_post(resp.image, "derpibooru.org", "Derpibooru");
+
+        assert_eq!(post.file_type, "png");
+        assert_eq!(
+            post.source_link.as_deref(),
+            Some("https://derpibooru.org/images/2345678")
+        );
+        assert_eq!(post.artist.as_deref(), Some("example artist"));
+        assert_eq!(post.rating, Some(super::Rating::Questionable));
+        assert_eq!(post.dimensions, Some((1920, 1080)));
+    }
+
+    #[test]
+    fn test_tumblr_post() {
+        let resp: super::TumblrResp =
+            serde_json::from_str(include_str!("../tests/fixtures/tumblr/photo.json")).unwrap();
+        let post = resp.response.posts.into_iter().next().unwrap();
+        let posts = super::tumblr_posts(post, "Tumblr").unwrap();
+
+        assert_eq!(posts.len(), 2);
+        assert_eq!(posts[0].file_type, "png");
+        assert_eq!(posts[0].artist.as_deref(), Some("example-artist"));
+        assert_eq!(posts[0].title.as_deref(), Some("Fox sketches"));
+        assert_eq!(posts[0].dimensions, Some((1280, 960)));
+        assert!(posts[0].thumb.as_ref().unwrap().ends_with("_250.png"));
+        assert_eq!(posts[0].tags, vec!["fox", "sketch"]);
+        assert_eq!(posts[1].file_type, "jpg");
+        assert_eq!(posts[1].thumb, None);
+    }
+
+    #[test]
+    fn test_reddit_gallery() {
+        let listings: Vec<super::RedditListing> =
+            serde_json::from_str(include_str!("../tests/fixtures/reddit/gallery.json")).unwrap();
+        let post = listings
+            .into_iter()
+            .next()
+            .unwrap()
+            .data
+            .children
+            .remove(0)
+            .data;
+        let posts = super::reddit_posts(post, "Reddit").unwrap();
+
+        assert_eq!(posts.len(), 2);
+        // Gallery order comes from the gallery items, not the metadata.
+        assert_eq!(posts[0].file_type, "png");
+        assert_eq!(posts[0].dimensions, Some((1600, 1200)));
+        assert!(posts[0].thumb.as_ref().unwrap().contains("width=320"));
+        assert_eq!(posts[1].file_type, "jpg");
+        assert!(posts[1].thumb.as_ref().unwrap().contains("width=480"));
+        assert_eq!(
+            posts[0].source_link.as_deref(),
+            Some("https://www.reddit.com/r/furry/comments/k3x9q2/two_foxes_i_drew_this_week/")
+        );
+        assert_eq!(posts[0].artist.as_deref(), Some("u/example_artist"));
+        assert_eq!(posts[0].rating, Some(super::Rating::Safe));
+    }
+
+    #[test]
+    fn test_reddit_video() {
+        let post =
+            serde_json::from_str(include_str!("../tests/fixtures/reddit/video.json")).unwrap();
+        let posts = super::reddit_posts(post, "Reddit").unwrap();
+
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].file_type, "m3u8");
+        assert!(posts[0].url.contains("HLSPlaylist.m3u8"));
+        assert_eq!(posts[0].rating, Some(super::Rating::Explicit));
+        assert_eq!(posts[0].dimensions, Some((1280, 720)));
+    }
+
+    #[test]
+    fn test_imgur_album() {
+        let resp: super::ImgurResp<super::ImgurAlbum> =
+            serde_json::from_str(include_str!("../tests/fixtures/imgur/album.json")).unwrap();
+        let posts = super::imgur_album_posts(resp.data, "Imgur");
+
+        assert_eq!(posts.len(), 2);
+        assert_eq!(posts[0].file_type, "png");
+        assert_eq!(posts[0].url, "https://i.imgur.com/Ab12Cd3.png");
+        assert_eq!(
+            posts[0].thumb.as_deref(),
+            Some("https://i.imgur.com/Ab12Cd3m.jpg")
+        );
+        assert_eq!(posts[0].title.as_deref(), Some("Fox reference sheet"));
+        assert_eq!(posts[0].artist.as_deref(), Some("example-artist"));
+        assert_eq!(posts[0].rating, Some(super::Rating::Safe));
+        assert_eq!(
+            posts[0].source_link.as_deref(),
+            Some("https://imgur.com/a/Xy7Zq2a")
+        );
+        assert_eq!(posts[1].file_type, "mp4");
+        assert_eq!(posts[1].title.as_deref(), Some("Tail wag"));
+    }
+
+    #[test]
+    fn test_imgur_urls() {
+        let imgur = super::Imgur::new("".into());
+
+        let tests = vec![
+            ("https://imgur.com/a/Xy7Zq2a", Some("a"), "Xy7Zq2a"),
+            (
+                "https://imgur.com/gallery/cute-fox-AbC123x",
+                Some("gallery"),
+                "AbC123x",
+            ),
+            ("https://imgur.com/Ab12Cd3", None, "Ab12Cd3"),
+        ];
+
+        for (url, kind, id) in tests {
+            let captures = imgur.matcher.captures(url).expect("url should match");
+            assert_eq!(captures.name("kind").map(|m| m.as_str()), kind);
+            assert_eq!(&captures["id"], id);
+        }
+
+        let captures = imgur
+            .matcher
+            .captures("https://i.imgur.com/Ef45Gh6.gifv")
+            .unwrap();
+        assert_eq!(&captures["gifv"], "Ef45Gh6");
+        assert!(!imgur.matcher.is_match("https://i.imgur.com/Ab12Cd3.png"));
+    }
+
+    #[test]
+    fn test_newgrounds_art() {
+        let newgrounds = super::Newgrounds::new();
+        let url = "https://www.newgrounds.com/art/view/example-artist/fox-in-the-rain";
+
+        let captures = newgrounds.art.captures(url).unwrap();
+        assert_eq!(&captures["artist"], "example-artist");
+
+        let posts = newgrounds
+            .art_posts(
+                include_str!("../tests/fixtures/newgrounds/art.html"),
+                url,
+                &captures["artist"],
+            )
+            .unwrap();
+
+        assert_eq!(posts.len(), 2);
+        assert_eq!(posts[0].file_type, "png");
+        assert!(posts[0].url.contains("art.ngfiles.com/images/"));
+        assert!(posts[0].thumb.as_ref().unwrap().contains("thumbnails"));
+        assert_eq!(posts[0].title.as_deref(), Some("Fox in the Rain"));
+        assert_eq!(posts[0].artist.as_deref(), Some("example-artist"));
+        assert_eq!(posts[0].rating, Some(super::Rating::Questionable));
+        assert_eq!(posts[0].tags, vec!["fox", "rain"]);
+        assert_eq!(posts[1].file_type, "jpg");
+    }
+
+    #[test]
+    fn test_newgrounds_video() {
+        let video =
+            serde_json::from_str(include_str!("../tests/fixtures/newgrounds/video.json")).unwrap();
+        let url = "https://www.newgrounds.com/portal/view/765432";
+
+        let post = super::newgrounds_video(video, url, None, "Newgrounds")
+            .unwrap()
+            .expect("missing video");
+
+        assert_eq!(post.file_type, "mp4");
+        assert!(post.url.contains(".720p."));
+        assert_eq!(post.title.as_deref(), Some("Fox Loop"));
+        assert_eq!(post.artist.as_deref(), Some("example-animator"));
+    }
+
+    #[test]
+    fn test_sofurry_post() {
+        let submission =
+            serde_json::from_str(include_str!("../tests/fixtures/sofurry/submission.json"))
+                .unwrap();
+        let post = super::sofurry_post(submission, "SoFurry").expect("missing post");
+
+        assert_eq!(post.file_type, "jpg");
+        assert_eq!(
+            post.source_link.as_deref(),
+            Some("https://www.sofurry.com/view/1654321")
+        );
+        assert_eq!(post.title.as_deref(), Some("Forest Guardian"));
+        assert_eq!(post.artist.as_deref(), Some("ExampleArtist"));
+        assert_eq!(post.rating, Some(super::Rating::Safe));
+        assert_eq!(post.tags, vec!["fox", "forest", "fantasy"]);
+        assert_eq!(post.posted_at.unwrap().timestamp(), 1_577_836_800);
+    }
+
+    #[test]
+    fn test_furrynetwork_post() {
+        let artwork =
+            serde_json::from_str(include_str!("../tests/fixtures/furrynetwork/artwork.json"))
+                .unwrap();
+        let post = super::furrynetwork_post(artwork, "Furry Network").unwrap();
+
+        assert_eq!(post.file_type, "png");
+        assert_eq!(
+            post.source_link.as_deref(),
+            Some("https://furrynetwork.com/artwork/1234567/")
+        );
+        assert_eq!(post.artist.as_deref(), Some("exampleartist"));
+        assert_eq!(post.rating, Some(super::Rating::Explicit));
+        assert!(post.thumb.unwrap().contains("medium"));
+        assert_eq!(post.tags, vec!["fox", "digital"]);
+    }
+
+    #[test]
+    fn test_file_types_sendable() {
+        let file_types = super::Direct::EXTENSIONS
+            .iter()
+            .copied()
+            .chain(
+                vec![
+                    "image/jpeg",
+                    "image/png",
+                    "image/gif",
+                    "image/webp",
+                    "video/mp4",
+                    "video/webm",
+                ]
+                .into_iter()
+                .filter_map(super::mime_ext),
+            )
+            // Reddit videos and Pixiv animations.
+            .chain(vec!["m3u8", "zip"]);
+
+        for file_type in file_types {
+            assert!(
+                crate::utils::media_kind(file_type).is_some(),
+                "{} could not be sent",
+                file_type
+            );
+        }
+    }
+
+    #[test]
+    fn test_breaker() {
+        let mut breaker = super::Breaker::default();
+        let cooldown = Duration::from_secs(60);
+
+        assert!(breaker.allows(cooldown), "new breaker was open");
+
+        assert!(!breaker.record_failure(2), "opened before threshold");
+        assert!(breaker.allows(cooldown), "open before threshold");
+
+        assert!(breaker.record_failure(2), "did not open at threshold");
+        assert!(!breaker.allows(cooldown), "allowed while open");
+
+        breaker.opened_at = breaker.opened_at.map(|opened_at| opened_at - cooldown);
+        assert!(breaker.allows(cooldown), "blocked after cooldown");
+        assert!(!breaker.allows(cooldown), "allowed a second probe");
+
+        assert!(!breaker.record_failure(2), "reported opening twice");
+        assert!(!breaker.allows(cooldown), "allowed after failed probe");
+
+        breaker.opened_at = breaker.opened_at.map(|opened_at| opened_at - cooldown);
+        assert!(breaker.allows(cooldown), "blocked after cooldown");
+        breaker.record_ignored();
+        assert!(
+            breaker.allows(cooldown),
+            "probe not released after ignored error"
+        );
+        breaker.record_ignored();
+        assert_eq!(breaker.failures, 3, "ignored error changed failure count");
+
+        breaker.record_success();
+        assert!(breaker.allows(cooldown), "still open after success");
+    }
+
+    #[test]
+    fn test_site_failure() {
+        use super::SiteError;
+
+        for err in vec![
+            SiteError::Timeout,
+            SiteError::Upstream("bad gateway".into()),
+            SiteError::RateLimited,
+            SiteError::Blocked,
+        ] {
+            assert!(err.is_site_failure(), "{:?} was not a failure", err);
+        }
+
+        for err in vec![
+            SiteError::NotFound,
+            SiteError::AuthFailed("post requires an account".into()),
+            SiteError::AccountExpired,
+            SiteError::Rejected("bad request".into()),
+            SiteError::Parse("missing field".into()),
+            SiteError::Internal("database".into()),
+            SiteError::Blocked.for_account(),
+            SiteError::RateLimited.for_account(),
+        ] {
+            assert!(!err.is_site_failure(), "{:?} was a failure", err);
+            assert!(!err.is_retryable(), "{:?} was retryable", err);
+        }
+
+        assert!(matches!(
+            SiteError::Blocked.for_account().inner(),
+            SiteError::Blocked
+        ));
+        assert!(matches!(
+            SiteError::NotFound.for_account(),
+            SiteError::NotFound
+        ));
+    }
+}
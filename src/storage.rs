@@ -0,0 +1,74 @@
+use failure::ResultExt;
+use rusoto_s3::S3;
+
+/// Public file storage on S3, used for processed videos and images that
+/// can't be loaded directly by Telegram.
+#[derive(Clone)]
+pub struct Storage {
+    client: rusoto_s3::S3Client,
+    bucket: String,
+    url: String,
+}
+
+impl Storage {
+    pub fn new(config: &crate::Config) -> Self {
+        let region = rusoto_core::Region::Custom {
+            name: config.s3_region.clone(),
+            endpoint: config.s3_endpoint.clone(),
+        };
+
+        let client = rusoto_core::request::HttpClient::new().expect("Unable to create S3 client");
+        let provider = rusoto_credential::StaticProvider::new_minimal(
+            config.s3_token.clone(),
+            config.s3_secret.clone(),
+        );
+
+        Self {
+            client: rusoto_s3::S3Client::new_with(client, provider, region),
+            bucket: config.s3_bucket.clone(),
+            url: config.s3_url.clone(),
+        }
+    }
+
+    /// Get the public URL for a key.
+    pub fn url(&self, key: &str) -> String {
+        format!("{}/{}/{}", self.url, self.bucket, key)
+    }
+
+    /// Check if a key has already been uploaded.
+    pub async fn exists(&self, key: &str) -> bool {
+        let head = rusoto_s3::HeadObjectRequest {
+            bucket: self.bucket.clone(),
+            key: key.to_string(),
+            ..Default::default()
+        };
+
+        self.client.head_object(head).await.is_ok()
+    }
+
+    /// Upload a publicly readable file, returning its URL.
+    pub async fn put(
+        &self,
+        key: &str,
+        content_type: &str,
+        body: rusoto_core::ByteStream,
+        content_length: i64,
+    ) -> failure::Fallible<String> {
+        let put = rusoto_s3::PutObjectRequest {
+            acl: Some("public-read".into()),
+            bucket: self.bucket.clone(),
+            content_type: Some(content_type.to_string()),
+            key: key.to_string(),
+            body: Some(body),
+            content_length: Some(content_length),
+            ..Default::default()
+        };
+
+        self.client
+            .put_object(put)
+            .await
+            .context("unable to upload file")?;
+
+        Ok(self.url(key))
+    }
+}
@@ -1,10 +1,15 @@
 use failure::ResultExt;
 use sentry::integrations::failure::capture_fail;
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tracing_futures::Instrument;
 
-use crate::models::{FileCache, Sites, UserConfig, UserConfigKey};
+use crate::blocklist::Blocklist;
+use crate::models::{
+    FileCache, FrameCache, GroupConfig, GroupConfigKey, Sites, UserConfig, UserConfigKey,
+};
+use crate::sites::{Rating, SiteError};
 use crate::BoxedSite;
 
 type Bundle<'a> = &'a fluent::FluentBundle<fluent::FluentResource>;
@@ -16,58 +21,715 @@ pub struct SiteCallback<'a> {
     pub results: Vec<crate::PostInfo>,
 }
 
+/// Maximum amount of time to spend resolving a single link.
+const LINK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What happened when loading images from a link.
+#[derive(Debug)]
+pub enum LinkOutcome {
+    /// Images were found and passed to the callback.
+    Found,
+    /// The link was supported by a site but had no images.
+    NoMedia,
+    /// No site supported the link.
+    Unsupported,
+    /// The site was unable to load the link.
+    Failed(SiteError),
+}
+
+impl LinkOutcome {
+    /// Name of the message explaining why no images were loaded, if this
+    /// outcome should be reported to the user.
+    pub fn reason_name(&self) -> Option<&'static str> {
+        let name = match self {
+            LinkOutcome::Found | LinkOutcome::Unsupported => return None,
+            LinkOutcome::NoMedia => "link-reason-no-media",
+            LinkOutcome::Failed(err) => match err.inner() {
+                SiteError::NotFound => "link-reason-not-found",
+                SiteError::AuthFailed(_) => "link-reason-auth-failed",
+                SiteError::AccountExpired => "link-reason-account-expired",
+                SiteError::RateLimited => "link-reason-rate-limited",
+                SiteError::Blocked => "link-reason-blocked",
+                SiteError::Timeout => "link-reason-timeout",
+                SiteError::Unavailable => "link-reason-unavailable",
+                SiteError::Parse(_)
+                | SiteError::Rejected(_)
+                | SiteError::Upstream(_)
+                | SiteError::Internal(_)
+                | SiteError::LinkedAccount(_) => "link-reason-error",
+            },
+        };
+
+        Some(name)
+    }
+}
+
+/// The outcome of loading a single link.
+#[derive(Debug)]
+pub struct LinkResult<'a> {
+    pub link: &'a str,
+    /// Name of the site that handled the link, if known.
+    pub site: Option<&'static str>,
+    pub outcome: LinkOutcome,
+}
+
+/// Find images for every link, resolving all links concurrently.
+///
+/// The callback is called in the same order as the provided links. A failure
+/// on one link does not prevent loading the others, every link has its
+/// outcome returned.
 #[tracing::instrument(skip(user, sites, callback))]
 pub async fn find_images<'a, C>(
     user: &tgbotapi::User,
     links: Vec<&'a str>,
-    sites: &mut [BoxedSite],
+    sites: &[BoxedSite],
     callback: &mut C,
-) -> failure::Fallible<Vec<&'a str>>
+) -> Vec<LinkResult<'a>>
 where
     C: FnMut(SiteCallback),
 {
-    let mut missing = vec![];
+    let futs = links
+        .iter()
+        .map(|link| tokio::time::timeout(LINK_TIMEOUT, find_link_images(user.id, link, sites)));
+    let outcomes = futures::future::join_all(futs).await;
 
-    'link: for link in links {
-        for site in sites.iter_mut() {
-            let start = Instant::now();
+    let mut link_results = Vec::with_capacity(links.len());
 
-            if site.url_supported(link).await {
-                tracing::debug!("link {} supported by {}", link, site.name());
+    for (link, outcome) in links.into_iter().zip(outcomes) {
+        let (site, duration, images) = match outcome {
+            Ok(Some(found)) => found,
+            Ok(None) => {
+                link_results.push(LinkResult {
+                    link,
+                    site: None,
+                    outcome: LinkOutcome::Unsupported,
+                });
+                continue;
+            }
+            Err(_elapsed) => {
+                tracing::warn!("timed out resolving link {}", link);
+                link_results.push(LinkResult {
+                    link,
+                    site: None,
+                    outcome: LinkOutcome::Failed(SiteError::Timeout),
+                });
+                continue;
+            }
+        };
 
-                let images = site
-                    .get_images(user.id, link)
-                    .await
-                    .context("unable to extract site images")?;
-
-                match images {
-                    Some(results) => {
-                        tracing::debug!("found images: {:?}", results);
-                        callback(SiteCallback {
-                            site: &site,
-                            link,
-                            duration: start.elapsed().as_millis() as i64,
-                            results,
-                        });
-                        continue 'link;
-                    }
-                    _ => {
-                        tracing::debug!("no images found");
-                        missing.push(link);
-                        continue 'link;
-                    }
+        let outcome = match images {
+            Ok(Some(results)) if !results.is_empty() => {
+                tracing::debug!("found images: {:?}", results);
+                callback(SiteCallback {
+                    site,
+                    link,
+                    duration,
+                    results,
+                });
+                LinkOutcome::Found
+            }
+            Ok(_) => {
+                tracing::debug!("no images found");
+                LinkOutcome::NoMedia
+            }
+            Err(err) => {
+                tracing::warn!("unable to extract site images from {}: {:?}", link, err);
+
+                // Only report errors that may be caused by a bug.
+                if let SiteError::Parse(_)
+                | SiteError::Rejected(_)
+                | SiteError::Upstream(_)
+                | SiteError::Internal(_) = err
+                {
+                    let tags = Some(vec![("site", site.name().to_string())]);
+                    with_user_scope(Some(user), tags, || capture_fail(&err));
                 }
+
+                LinkOutcome::Failed(err)
+            }
+        };
+
+        link_results.push(LinkResult {
+            link,
+            site: Some(site.name()),
+            outcome,
+        });
+    }
+
+    link_results
+}
+
+type LinkImages<'a> = (
+    &'a BoxedSite,
+    i64,
+    Result<Option<Vec<crate::PostInfo>>, SiteError>,
+);
+
+/// Find the first site that supports a link and load its images.
+///
+/// Returns None if no site supported the link.
+async fn find_link_images<'a>(
+    user_id: i32,
+    link: &str,
+    sites: &'a [BoxedSite],
+) -> Option<LinkImages<'a>> {
+    for site in sites {
+        let start = Instant::now();
+
+        if site.url_supported(link).await {
+            tracing::debug!("link {} supported by {}", link, site.name());
+
+            let images = site.get_images(user_id, link).await;
+
+            return Some((site, start.elapsed().as_millis() as i64, images));
+        }
+    }
+
+    None
+}
+
+/// Build a localized list of links that could not be loaded, one per line.
+///
+/// Returns None if every link was loaded or unsupported.
+pub fn link_failures_message(bundle: Bundle, link_results: &[LinkResult]) -> Option<String> {
+    let lines: Vec<String> = link_results
+        .iter()
+        .filter_map(|result| {
+            let reason_name = result.outcome.reason_name()?;
+
+            let reason = get_message(bundle, reason_name, None).unwrap();
+
+            let mut args = fluent::FluentArgs::new();
+            args.insert("link", result.link.into());
+            args.insert("reason", reason.into());
+
+            let text = match result.site {
+                Some(site) => {
+                    args.insert("site", site.into());
+                    get_message(bundle, "link-failure-site", Some(args))
+                }
+                None => get_message(bundle, "link-failure", Some(args)),
+            };
+
+            Some(text.unwrap())
+        })
+        .collect();
+
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join("\n"))
+    }
+}
+
+/// How closely an image must match a result for it to be used, from most
+/// to least strict.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub enum Strictness {
+    Strict,
+    Normal,
+    Loose,
+}
+
+#[derive(Debug)]
+pub struct ParseStrictnessError;
+
+impl std::str::FromStr for Strictness {
+    type Err = ParseStrictnessError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(Strictness::Strict),
+            "normal" => Ok(Strictness::Normal),
+            "loose" => Ok(Strictness::Loose),
+            _ => Err(ParseStrictnessError),
+        }
+    }
+}
+
+impl Default for Strictness {
+    fn default() -> Self {
+        Strictness::Normal
+    }
+}
+
+impl Strictness {
+    /// Every strictness, from most to least strict.
+    pub const ALL: &'static [Strictness] =
+        &[Strictness::Strict, Strictness::Normal, Strictness::Loose];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Strictness::Strict => "strict",
+            Strictness::Normal => "normal",
+            Strictness::Loose => "loose",
+        }
+    }
+
+    pub fn policy(self) -> MatchPolicy {
+        match self {
+            Strictness::Strict => MatchPolicy {
+                good: 2,
+                automatic: 1,
+                alternate: 3,
+            },
+            Strictness::Normal => MatchPolicy {
+                good: 4,
+                automatic: 3,
+                alternate: 6,
+            },
+            Strictness::Loose => MatchPolicy {
+                good: 7,
+                automatic: 5,
+                alternate: 10,
+            },
+        }
+    }
+}
+
+/// The largest hash distances allowed for each way matches are used.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MatchPolicy {
+    /// Matches that are shown without a warning and with a link preview.
+    pub good: u64,
+    /// Matches that are used when automatically sourcing images in groups
+    /// and channels.
+    pub automatic: u64,
+    /// The average distance of a set of alternate versions.
+    pub alternate: u64,
+}
+
+impl Default for MatchPolicy {
+    fn default() -> Self {
+        Strictness::default().policy()
+    }
+}
+
+impl MatchPolicy {
+    /// Load the policy for a user and chat, using the strictest of their
+    /// settings.
+    pub async fn load(
+        conn: &quaint::pooled::Quaint,
+        user_id: Option<i32>,
+        chat_id: Option<i64>,
+    ) -> failure::Fallible<Self> {
+        let conn = conn
+            .check_out()
+            .await
+            .context("unable to check out database")?;
+
+        let user_strictness: Option<Strictness> = match user_id {
+            Some(user_id) => UserConfig::get(&conn, UserConfigKey::MatchStrictness, user_id)
+                .await
+                .context("unable to get user match strictness")?,
+            None => None,
+        };
+
+        let group_strictness: Option<Strictness> = match chat_id {
+            Some(chat_id) => GroupConfig::get(&conn, chat_id, GroupConfigKey::MatchStrictness)
+                .await
+                .context("unable to get group match strictness")?,
+            None => None,
+        };
+
+        let strictness = user_strictness
+            .into_iter()
+            .chain(group_strictness)
+            .min()
+            .unwrap_or_default();
+
+        Ok(strictness.policy())
+    }
+
+    pub fn is_good(&self, file: &fuzzysearch::File) -> bool {
+        file.distance.unwrap() <= self.good
+    }
+
+    pub fn is_automatic(&self, file: &fuzzysearch::File) -> bool {
+        file.distance.unwrap() <= self.automatic
+    }
+
+    /// If a set of matches are close enough to be shown as alternates.
+    pub fn is_alternate(&self, files: &[fuzzysearch::File]) -> bool {
+        let total: u64 = files.iter().map(|file| file.distance.unwrap()).sum();
+
+        total <= self.alternate * files.len() as u64
+    }
+}
+
+/// What a user may be shown, combining their settings with the group's.
+pub struct ContentFilter {
+    /// The most explicit rating allowed.
+    pub rating: Rating,
+    pub blocklist: Blocklist,
+}
+
+/// A post that was removed by a [ContentFilter].
+pub struct HiddenPost {
+    pub link: String,
+    /// Name of the message explaining why the post was hidden.
+    pub reason: &'static str,
+}
+
+impl ContentFilter {
+    /// Load the filter for a user. In a group, the group's settings also
+    /// apply.
+    ///
+    /// Everything is allowed unless a user or group has chosen otherwise.
+    pub async fn load(
+        conn: &quaint::pooled::Quaint,
+        user_id: i32,
+        chat_id: Option<i64>,
+    ) -> failure::Fallible<Self> {
+        let conn = conn
+            .check_out()
+            .await
+            .context("unable to check out database")?;
+
+        let user_rating: Option<Rating> = UserConfig::get(&conn, UserConfigKey::MaxRating, user_id)
+            .await
+            .context("unable to get user max rating")?;
+        let user_blocklist: Option<Vec<String>> =
+            UserConfig::get(&conn, UserConfigKey::Blocklist, user_id)
+                .await
+                .context("unable to get user blocklist")?;
+
+        let (group_rating, group_blocklist): (Option<Rating>, Option<Vec<String>>) = match chat_id {
+            Some(chat_id) => (
+                GroupConfig::get(&conn, chat_id, GroupConfigKey::MaxRating)
+                    .await
+                    .context("unable to get group max rating")?,
+                GroupConfig::get(&conn, chat_id, GroupConfigKey::Blocklist)
+                    .await
+                    .context("unable to get group blocklist")?,
+            ),
+            None => (None, None),
+        };
+
+        let mut blocklist = Blocklist::parse(user_blocklist.iter().flatten().map(String::as_str));
+        blocklist.extend(Blocklist::parse(
+            group_blocklist.iter().flatten().map(String::as_str),
+        ));
+
+        Ok(Self {
+            rating: std::cmp::min(
+                user_rating.unwrap_or(Rating::Explicit),
+                group_rating.unwrap_or(Rating::Explicit),
+            ),
+            blocklist,
+        })
+    }
+
+    /// If this filter allows everything, so posts don't need to be checked.
+    pub fn is_empty(&self) -> bool {
+        self.rating == Rating::Explicit && self.blocklist.is_empty()
+    }
+
+    /// Why a post should be hidden, if it should be. Posts without a known
+    /// rating are hidden unless every rating is allowed.
+    pub fn hidden_reason(&self, post: &crate::PostInfo) -> Option<&'static str> {
+        match post.rating {
+            Some(rating) if rating > self.rating => return Some("filter-reason-rating"),
+            None if self.rating < Rating::Explicit => return Some("filter-reason-unknown-rating"),
+            _ => (),
+        }
+
+        let tags: Vec<&str> = post
+            .tags
+            .iter()
+            .chain(post.artist.iter())
+            .map(String::as_str)
+            .collect();
+
+        if self.blocklist.blocks(&tags, post.rating) {
+            return Some("filter-reason-blocklist");
+        }
+
+        None
+    }
+
+    /// Split posts into those that may be shown and those that must be hidden.
+    pub fn filter_posts(
+        &self,
+        posts: Vec<crate::PostInfo>,
+    ) -> (Vec<crate::PostInfo>, Vec<HiddenPost>) {
+        let mut allowed = Vec::with_capacity(posts.len());
+        let mut hidden = vec![];
+
+        for post in posts {
+            match self.hidden_reason(&post) {
+                Some(reason) => hidden.push(HiddenPost {
+                    link: post.source_link.unwrap_or(post.url),
+                    reason,
+                }),
+                None => allowed.push(post),
             }
         }
+
+        (allowed, hidden)
+    }
+
+    /// If a reverse search match should be hidden, given the outcome and
+    /// posts of loading its link.
+    ///
+    /// Matches that couldn't be checked are hidden, including posts without
+    /// tags when there is a blocklist.
+    fn hides_match(&self, outcome: &LinkOutcome, posts: &[crate::PostInfo]) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        if !matches!(outcome, LinkOutcome::Found) {
+            return true;
+        }
+
+        posts.iter().any(|post| {
+            self.hidden_reason(post).is_some()
+                || (!self.blocklist.is_empty() && post.tags.is_empty())
+        })
     }
 
-    Ok(missing)
+    /// Find which reverse search matches should be hidden, returning their
+    /// URLs.
+    ///
+    /// Matches only include artists, so they are loaded through the sites to
+    /// get their tags and rating. Any that can't be loaded are hidden.
+    pub async fn hidden_matches<'a, I>(
+        &self,
+        user: &tgbotapi::User,
+        matches: I,
+        sites: &[BoxedSite],
+    ) -> Vec<String>
+    where
+        I: IntoIterator<Item = &'a fuzzysearch::File>,
+    {
+        if self.is_empty() {
+            return vec![];
+        }
+
+        let mut hidden = vec![];
+        let mut links = vec![];
+
+        for m in matches {
+            let artists = m.artists.clone().unwrap_or_default();
+
+            if self.blocklist.blocks(&artists, None) {
+                hidden.push(m.url());
+            } else {
+                links.push(m.url());
+            }
+        }
+
+        let links = links.iter().map(String::as_str).collect();
+
+        let mut loaded: HashMap<String, Vec<crate::PostInfo>> = HashMap::new();
+        let link_results = find_images(user, links, sites, &mut |info| {
+            loaded.insert(info.link.to_string(), info.results);
+        })
+        .await;
+
+        for result in link_results {
+            let posts = loaded.remove(result.link).unwrap_or_default();
+
+            if self.hides_match(&result.outcome, &posts) {
+                hidden.push(result.link.to_string());
+            }
+        }
+
+        hidden
+    }
+}
+
+/// Build a localized list of hidden posts, one per line.
+///
+/// Returns None if no posts were hidden.
+pub fn hidden_message(bundle: Bundle, hidden: &[HiddenPost]) -> Option<String> {
+    if hidden.is_empty() {
+        return None;
+    }
+
+    let lines: Vec<String> = hidden
+        .iter()
+        .map(|post| {
+            let reason = get_message(bundle, post.reason, None).unwrap();
+
+            let mut args = fluent::FluentArgs::new();
+            args.insert("link", fluent::FluentValue::from(post.link.as_str()));
+            args.insert("reason", reason.into());
+
+            get_message(bundle, "filter-hidden", Some(args)).unwrap()
+        })
+        .collect();
+
+    Some(lines.join("\n"))
 }
 
 pub fn find_best_photo(sizes: &[tgbotapi::PhotoSize]) -> Option<&tgbotapi::PhotoSize> {
     sizes.iter().max_by_key(|size| size.height * size.width)
 }
 
+/// Largest file the Bot API allows bots to download.
+const MAX_DOWNLOAD_SIZE: i32 = 20 * 1024 * 1024;
+
+/// Image types that can be hashed when sent as a document, with the file
+/// extensions used when Telegram didn't provide a MIME type.
+const IMAGE_TYPES: &[(&str, &[&str])] = &[
+    ("image/png", &["png"]),
+    ("image/jpeg", &["jpg", "jpeg"]),
+    ("image/webp", &["webp"]),
+    ("image/gif", &["gif"]),
+];
+
+/// How a file needs to be processed before it can be hashed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileKind {
+    /// An image that can be hashed directly.
+    Image,
+    /// An animation, video, or sticker that needs frames extracted first.
+    Frames,
+}
+
+/// A file from Telegram that can be downloaded and reverse searched.
+#[derive(Clone, Copy, Debug)]
+pub struct SearchableFile<'a> {
+    pub file_id: &'a str,
+    pub file_unique_id: &'a str,
+    pub kind: FileKind,
+}
+
+impl<'a> From<&'a tgbotapi::PhotoSize> for SearchableFile<'a> {
+    fn from(photo: &'a tgbotapi::PhotoSize) -> Self {
+        Self {
+            file_id: &photo.file_id,
+            file_unique_id: &photo.file_unique_id,
+            kind: FileKind::Image,
+        }
+    }
+}
+
+impl<'a> From<&'a tgbotapi::Document> for SearchableFile<'a> {
+    fn from(document: &'a tgbotapi::Document) -> Self {
+        Self {
+            file_id: &document.file_id,
+            file_unique_id: &document.file_unique_id,
+            kind: FileKind::Image,
+        }
+    }
+}
+
+/// Find the file to search in a message. Photos and uncompressed images sent
+/// as documents are preferred, then animations, videos, and static stickers.
+pub fn find_searchable_file(message: &tgbotapi::Message) -> Option<SearchableFile> {
+    if let Some(photo) = message.photo.as_deref().and_then(find_best_photo) {
+        return Some(photo.into());
+    }
+
+    if let Some(document) = message.document.as_ref().filter(|document| {
+        is_image_document(
+            document.mime_type.as_deref(),
+            document.file_name.as_deref(),
+            document.file_size,
+        )
+    }) {
+        return Some(document.into());
+    }
+
+    let (file_id, file_unique_id, file_size) = if let Some(animation) = &message.animation {
+        (
+            &animation.file_id,
+            &animation.file_unique_id,
+            animation.file_size,
+        )
+    } else if let Some(video) = &message.video {
+        (&video.file_id, &video.file_unique_id, video.file_size)
+    } else {
+        // Animated stickers are Lottie files, which ffmpeg can't decode.
+        let sticker = message
+            .sticker
+            .as_ref()
+            .filter(|sticker| !sticker.is_animated)?;
+
+        (&sticker.file_id, &sticker.file_unique_id, sticker.file_size)
+    };
+
+    if file_size.unwrap_or_default() > MAX_DOWNLOAD_SIZE {
+        return None;
+    }
+
+    Some(SearchableFile {
+        file_id,
+        file_unique_id,
+        kind: FileKind::Frames,
+    })
+}
+
+/// Check if a document is an image that can be downloaded and hashed.
+fn is_image_document(
+    mime_type: Option<&str>,
+    file_name: Option<&str>,
+    file_size: Option<i32>,
+) -> bool {
+    if file_size.unwrap_or_default() > MAX_DOWNLOAD_SIZE {
+        return false;
+    }
+
+    if let Some(mime_type) = mime_type {
+        return IMAGE_TYPES.iter().any(|(known, _)| *known == mime_type);
+    }
+
+    // A name without a dot has no extension.
+    let extension = match file_name.and_then(|name| name.rfind('.').map(|idx| &name[idx + 1..])) {
+        Some(extension) => extension.to_ascii_lowercase(),
+        None => return false,
+    };
+
+    IMAGE_TYPES
+        .iter()
+        .any(|(_, extensions)| extensions.contains(&extension.as_str()))
+}
+
+/// How a file from a site can be sent to Telegram.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MediaKind {
+    Photo,
+    Animation,
+    /// A video Telegram can play directly.
+    Video,
+    /// A video that must be converted to MP4 before it can be sent.
+    ConvertedVideo,
+}
+
+/// Determine how a file type should be sent, if it can be sent at all.
+pub fn media_kind(file_type: &str) -> Option<MediaKind> {
+    let kind = match file_type {
+        "png" | "jpg" | "jpeg" | "webp" => MediaKind::Photo,
+        "gif" => MediaKind::Animation,
+        "mp4" => MediaKind::Video,
+        "webm" | "zip" | "m3u8" => MediaKind::ConvertedVideo,
+        _ => return None,
+    };
+
+    Some(kind)
+}
+
+/// Build a caption for a post, crediting the artist when known.
+pub fn post_caption(bundle: Bundle, post: &crate::PostInfo) -> Option<String> {
+    let source_link = post.source_link.as_ref()?;
+
+    let artist = match &post.artist {
+        Some(artist) => artist,
+        None => return Some(source_link.to_owned()),
+    };
+
+    let mut args = fluent::FluentArgs::new();
+    args.insert("link", fluent::FluentValue::from(source_link.as_str()));
+    args.insert("artist", fluent::FluentValue::from(artist.as_str()));
+
+    get_message(bundle, "mirror-caption", Some(args)).ok()
+}
+
 pub fn get_message(
     bundle: Bundle,
     name: &str,
@@ -112,7 +774,11 @@ where
 
 type AlternateItems<'a> = Vec<(&'a Vec<String>, &'a Vec<fuzzysearch::File>)>;
 
-pub fn build_alternate_response(bundle: Bundle, mut items: AlternateItems) -> (String, Vec<i64>) {
+pub fn build_alternate_response(
+    bundle: Bundle,
+    policy: MatchPolicy,
+    mut items: AlternateItems,
+) -> (String, Vec<i64>) {
     let mut used_hashes = vec![];
 
     items.sort_by(|a, b| {
@@ -127,10 +793,8 @@ pub fn build_alternate_response(bundle: Bundle, mut items: AlternateItems) -> (S
     s.push_str("\n\n");
 
     for item in items {
-        let total_dist: u64 = item.1.iter().map(|item| item.distance.unwrap()).sum();
-        tracing::trace!("total distance: {}", total_dist);
-        if total_dist > (6 * item.1.len() as u64) {
-            tracing::trace!("too high, aborting");
+        if !policy.is_alternate(item.1) {
+            tracing::trace!("distance too high, skipping");
             continue;
         }
         let artist_name = item
@@ -164,6 +828,51 @@ pub fn build_alternate_response(bundle: Bundle, mut items: AlternateItems) -> (S
     (s, used_hashes)
 }
 
+/// Build a summary of the best match for each recent photo, newest first.
+pub fn build_recent_source_response(
+    bundle: Bundle,
+    policy: MatchPolicy,
+    matches: &[Option<fuzzysearch::File>],
+    hidden: &[String],
+) -> String {
+    let mut args = fluent::FluentArgs::new();
+    args.insert("count", matches.len().into());
+
+    let mut s = String::new();
+    s.push_str(&get_message(&bundle, "source-recent-title", Some(args)).unwrap());
+    s.push_str("\n\n");
+
+    for (idx, result) in matches.iter().enumerate() {
+        let mut args = fluent::FluentArgs::new();
+        args.insert("index", (idx + 1).into());
+
+        let name = match result {
+            Some(result) => {
+                let link = result.url();
+                args.insert("distance", result.distance.unwrap().into());
+
+                if hidden.contains(&link) {
+                    "source-recent-hidden"
+                } else {
+                    args.insert("link", link.into());
+
+                    if policy.is_good(result) {
+                        "source-recent-result"
+                    } else {
+                        "source-recent-unsure"
+                    }
+                }
+            }
+            None => "source-recent-none",
+        };
+
+        s.push_str(&get_message(&bundle, name, Some(args)).unwrap());
+        s.push_str("\n");
+    }
+
+    s
+}
+
 pub fn parse_known_bots(message: &tgbotapi::Message) -> Option<Vec<String>> {
     let from = if let Some(ref forward_from) = message.forward_from {
         Some(forward_from)
@@ -209,7 +918,6 @@ pub fn continuous_action(
 ) -> ContinuousAction {
     use futures::future::FutureExt;
     use futures_util::stream::StreamExt;
-    use std::time::Duration;
 
     let (tx, rx) = tokio::sync::oneshot::channel();
 
@@ -267,22 +975,94 @@ pub async fn match_image(
     bot: &tgbotapi::Telegram,
     conn: &quaint::pooled::Quaint,
     fapi: &fuzzysearch::FuzzySearch,
-    file: &tgbotapi::PhotoSize,
+    file: SearchableFile<'_>,
 ) -> failure::Fallible<Vec<fuzzysearch::File>> {
     let conn = conn
         .check_out()
         .await
         .context("unable to check out database")?;
 
-    if let Some(hash) = FileCache::get(&conn, &file.file_unique_id)
+    if file.kind == FileKind::Frames {
+        return match_frames(bot, &conn, fapi, file).await;
+    }
+
+    if let Some(hash) = FileCache::get(&conn, file.file_unique_id)
         .await
         .context("unable to query file cache")?
     {
-        return lookup_single_hash(&fapi, hash).await;
+        return lookup_hashes(&fapi, &[hash]).await;
+    }
+
+    let data = download_file(bot, file).await?;
+
+    let hash = tokio::task::spawn_blocking(move || fuzzysearch::hash_bytes(&data))
+        .await
+        .context("unable to spawn blocking")?
+        .context("unable to hash bytes")?;
+
+    FileCache::set(&conn, file.file_unique_id, hash)
+        .await
+        .context("unable to set file cache")?;
+
+    lookup_hashes(&fapi, &[hash]).await
+}
+
+/// Search for an animation, video, or sticker by hashing frames extracted
+/// from it, caching every frame's hash. Files without any frames to search
+/// are cached too, and never have any matches.
+async fn match_frames(
+    bot: &tgbotapi::Telegram,
+    conn: &quaint::pooled::PooledConnection,
+    fapi: &fuzzysearch::FuzzySearch,
+    file: SearchableFile<'_>,
+) -> failure::Fallible<Vec<fuzzysearch::File>> {
+    if let Some(hashes) = FrameCache::get(conn, file.file_unique_id)
+        .await
+        .context("unable to query frame cache")?
+    {
+        return lookup_frame_hashes(&fapi, &hashes).await;
+    }
+
+    let data = download_file(bot, file).await?;
+
+    let hashes = tokio::task::spawn_blocking(move || -> failure::Fallible<Vec<i64>> {
+        let frames = crate::video::extract_frames(&data)?;
+
+        frames
+            .iter()
+            .map(|frame| Ok(fuzzysearch::hash_bytes(frame)?))
+            .collect()
+    })
+    .await
+    .context("unable to spawn blocking")?
+    .context("unable to hash frames")?;
+
+    FrameCache::set(conn, file.file_unique_id, &hashes)
+        .await
+        .context("unable to set frame cache")?;
+
+    lookup_frame_hashes(&fapi, &hashes).await
+}
+
+/// Look up the hashes of extracted frames, skipping the search entirely when
+/// no frames could be extracted.
+async fn lookup_frame_hashes(
+    fapi: &fuzzysearch::FuzzySearch,
+    hashes: &[i64],
+) -> failure::Fallible<Vec<fuzzysearch::File>> {
+    if hashes.is_empty() {
+        return Ok(vec![]);
     }
 
+    lookup_hashes(&fapi, &hashes).await
+}
+
+async fn download_file(
+    bot: &tgbotapi::Telegram,
+    file: SearchableFile<'_>,
+) -> failure::Fallible<Vec<u8>> {
     let get_file = tgbotapi::requests::GetFile {
-        file_id: file.file_id.clone(),
+        file_id: file.file_id.to_string(),
     };
 
     let file_info = bot
@@ -294,44 +1074,77 @@ pub async fn match_image(
         .await
         .context("unable to download file from telegram")?;
 
-    let hash = tokio::task::spawn_blocking(move || fuzzysearch::hash_bytes(&data))
-        .await
-        .context("unable to spawn blocking")?
-        .context("unable to hash bytes")?;
-
-    FileCache::set(&conn, &file.file_unique_id, hash)
-        .await
-        .context("unable to set file cache")?;
-
-    lookup_single_hash(&fapi, hash).await
+    Ok(data)
 }
 
-async fn lookup_single_hash(
+/// Look up matches for hashes, using each match's distance to the closest
+/// hash and keeping only the closest match for each URL.
+async fn lookup_hashes(
     fapi: &fuzzysearch::FuzzySearch,
-    hash: i64,
+    hashes: &[i64],
 ) -> failure::Fallible<Vec<fuzzysearch::File>> {
-    let mut matches = fapi
-        .lookup_hashes(vec![hash])
+    let matches = fapi
+        .lookup_hashes(hashes.to_vec())
         .await
         .context("unable to lookup hash")?;
 
-    for mut m in &mut matches {
-        m.distance =
-            hamming::distance_fast(&m.hash.unwrap().to_be_bytes(), &hash.to_be_bytes()).ok();
+    let mut closest: Vec<fuzzysearch::File> = Vec::with_capacity(matches.len());
+    for mut m in matches {
+        let found = m.hash.unwrap().to_be_bytes();
+        m.distance = hashes
+            .iter()
+            .filter_map(|hash| hamming::distance_fast(&found, &hash.to_be_bytes()).ok())
+            .min();
+
+        match closest
+            .iter_mut()
+            .find(|existing| existing.url() == m.url())
+        {
+            Some(existing) if existing.distance <= m.distance => (),
+            Some(existing) => *existing = m,
+            None => closest.push(m),
+        }
     }
 
-    matches.sort_by(|a, b| {
+    closest.sort_by(|a, b| {
         a.distance
             .unwrap()
             .partial_cmp(&b.distance.unwrap())
             .unwrap()
     });
 
-    Ok(matches)
+    Ok(closest)
+}
+
+/// Get a user's order for every registered site, without any stored sites
+/// that aren't registered.
+pub async fn site_order(
+    conn: &quaint::pooled::Quaint,
+    sites: &[crate::BoxedSite],
+    user_id: i32,
+) -> failure::Fallible<Vec<Sites>> {
+    let conn = conn
+        .check_out()
+        .await
+        .context("unable to check out database")?;
+
+    let stored: Option<Vec<String>> = UserConfig::get(&conn, UserConfigKey::SiteSortOrder, user_id)
+        .await
+        .context("unable to get user site sort order")?;
+
+    let mut order = Sites::order(stored, sites.iter().map(|site| site.name()));
+    order.retain(|site| {
+        sites
+            .iter()
+            .any(|available| available.name() == site.as_str())
+    });
+
+    Ok(order)
 }
 
 pub async fn sort_results(
     conn: &quaint::pooled::Quaint,
+    sites: &[crate::BoxedSite],
     user_id: i32,
     results: &mut Vec<fuzzysearch::File>,
 ) -> failure::Fallible<()> {
@@ -340,17 +1153,14 @@ pub async fn sort_results(
         return Ok(());
     }
 
-    let conn = conn
-        .check_out()
-        .await
-        .context("unable to check out database")?;
+    let order = site_order(conn, sites, user_id).await?;
 
-    let row: Option<Vec<String>> = UserConfig::get(&conn, UserConfigKey::SiteSortOrder, user_id)
-        .await
-        .context("unable to get user site sort order")?;
-    let sites = match row {
-        Some(row) => row.iter().map(|item| item.parse().unwrap()).collect(),
-        None => Sites::default_order(),
+    // Sites that aren't registered go after every known site.
+    let position = |name: &str| {
+        order
+            .iter()
+            .position(|site| site.as_str() == name)
+            .unwrap_or(order.len())
     };
 
     results.sort_unstable_by(|a, b| {
@@ -361,16 +1171,7 @@ pub async fn sort_results(
             return a_dist.cmp(&b_dist);
         }
 
-        let a_idx = sites
-            .iter()
-            .position(|s| s.as_str() == a.site_name())
-            .unwrap();
-        let b_idx = sites
-            .iter()
-            .position(|s| s.as_str() == b.site_name())
-            .unwrap();
-
-        a_idx.cmp(&b_idx)
+        position(a.site_name()).cmp(&position(b.site_name()))
     });
 
     Ok(())
@@ -391,3 +1192,135 @@ pub async fn use_source_name(
 
     Ok(row)
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_media_kind() {
+        use super::{media_kind, MediaKind};
+
+        // Every file type sites may return for a post.
+        let site_file_types = &[
+            "png", "jpg", "jpeg", "webp", "gif", "mp4", "webm", "zip", "m3u8",
+        ];
+
+        for file_type in site_file_types {
+            assert!(
+                media_kind(file_type).is_some(),
+                "{} could not be sent",
+                file_type
+            );
+        }
+
+        assert_eq!(media_kind("webp"), Some(MediaKind::Photo));
+        assert_eq!(media_kind("mp4"), Some(MediaKind::Video));
+        assert_eq!(media_kind("zip"), Some(MediaKind::ConvertedVideo));
+        assert_eq!(media_kind("pdf"), None);
+    }
+
+    fn filter(rating: crate::sites::Rating, blocklist: &[&str]) -> super::ContentFilter {
+        super::ContentFilter {
+            rating,
+            blocklist: crate::blocklist::Blocklist::parse(blocklist.iter().copied()),
+        }
+    }
+
+    #[test]
+    fn test_hidden_reason_unknown_rating() {
+        use crate::sites::Rating;
+
+        let unrated = crate::PostInfo::default();
+        let safe = crate::PostInfo {
+            rating: Some(Rating::Safe),
+            ..Default::default()
+        };
+
+        assert_eq!(filter(Rating::Explicit, &[]).hidden_reason(&unrated), None);
+        assert_eq!(
+            filter(Rating::Questionable, &[]).hidden_reason(&unrated),
+            Some("filter-reason-unknown-rating")
+        );
+        assert_eq!(filter(Rating::Safe, &[]).hidden_reason(&safe), None);
+    }
+
+    #[test]
+    fn test_hides_match() {
+        use super::LinkOutcome;
+        use crate::sites::{Rating, SiteError};
+
+        let tagged = crate::PostInfo {
+            rating: Some(Rating::Safe),
+            tags: vec!["fox".into()],
+            ..Default::default()
+        };
+        let untagged = crate::PostInfo {
+            rating: Some(Rating::Safe),
+            ..Default::default()
+        };
+
+        let unchecked = vec![
+            LinkOutcome::NoMedia,
+            LinkOutcome::Unsupported,
+            LinkOutcome::Failed(SiteError::Timeout),
+        ];
+
+        for outcome in &unchecked {
+            assert!(!filter(Rating::Explicit, &[]).hides_match(outcome, &[]));
+            assert!(filter(Rating::Safe, &[]).hides_match(outcome, &[]));
+            assert!(filter(Rating::Explicit, &["gore"]).hides_match(outcome, &[]));
+        }
+
+        let found = LinkOutcome::Found;
+        assert!(!filter(Rating::Safe, &[]).hides_match(&found, &[tagged.clone()]));
+        assert!(!filter(Rating::Safe, &["gore"]).hides_match(&found, &[tagged]));
+        assert!(filter(Rating::Safe, &["gore"]).hides_match(&found, &[untagged.clone()]));
+        assert!(!filter(Rating::Safe, &[]).hides_match(&found, &[untagged]));
+    }
+
+    #[test]
+    fn test_strictness() {
+        use super::{MatchPolicy, Strictness};
+
+        for strictness in Strictness::ALL {
+            assert_eq!(
+                strictness.as_str().parse::<Strictness>().unwrap(),
+                *strictness
+            );
+        }
+        assert!("unknown".parse::<Strictness>().is_err());
+
+        // Each preset should allow more than the stricter one before it.
+        for pair in Strictness::ALL.windows(2) {
+            let (stricter, looser) = (pair[0].policy(), pair[1].policy());
+
+            assert!(stricter.good < looser.good);
+            assert!(stricter.automatic < looser.automatic);
+            assert!(stricter.alternate < looser.alternate);
+        }
+
+        assert_eq!(MatchPolicy::default(), Strictness::Normal.policy());
+    }
+
+    #[test]
+    fn test_is_image_document() {
+        use super::is_image_document;
+
+        assert!(is_image_document(Some("image/png"), None, Some(1024)));
+        assert!(is_image_document(Some("image/webp"), Some("file"), None));
+        assert!(is_image_document(None, Some("IMAGE.JPG"), None));
+        assert!(!is_image_document(
+            Some("video/mp4"),
+            Some("video.png"),
+            None
+        ));
+        assert!(!is_image_document(None, Some("document.pdf"), None));
+        assert!(!is_image_document(None, Some("png"), None));
+        assert!(!is_image_document(None, Some("JPG"), None));
+        assert!(!is_image_document(None, None, None));
+        assert!(!is_image_document(
+            Some("image/png"),
+            None,
+            Some(50 * 1024 * 1024)
+        ));
+    }
+}
@@ -9,6 +9,14 @@ impl std::fmt::Display for FfmpegError {
 
 impl std::error::Error for FfmpegError {}
 
+/// If a URL is for an HLS playlist, which ffmpeg can load directly.
+pub fn is_playlist(url: &str) -> bool {
+    url.split('?')
+        .next()
+        .map(|path| path.ends_with(".m3u8"))
+        .unwrap_or(false)
+}
+
 pub fn process_video(file: &std::path::Path) -> failure::Fallible<String> {
     let id = crate::generate_id();
 
@@ -36,3 +44,141 @@ pub fn process_video(file: &std::path::Path) -> failure::Fallible<String> {
 
     Ok(path)
 }
+
+/// A working directory for processing a file, removed when dropped so it
+/// is cleaned up even when processing fails partway through.
+struct WorkDir(std::path::PathBuf);
+
+impl WorkDir {
+    fn create(id: &str) -> std::io::Result<Self> {
+        let dir = std::path::PathBuf::from(format!("videos/{}", id));
+        std::fs::create_dir_all(&dir)?;
+
+        Ok(Self(dir))
+    }
+}
+
+impl std::ops::Deref for WorkDir {
+    type Target = std::path::Path;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_dir_all(&self.0) {
+            tracing::warn!("unable to remove {}: {:?}", self.0.display(), err);
+        }
+    }
+}
+
+/// Convert a Pixiv ugoira, a zip of images each shown for their own delay,
+/// into a video.
+pub fn process_ugoira(
+    file: &std::path::Path,
+    frames: &[crate::sites::UgoiraFrame],
+) -> failure::Fallible<String> {
+    let id = crate::generate_id();
+
+    let dir = WorkDir::create(&id)?;
+
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(file)?)?;
+    for idx in 0..archive.len() {
+        let mut entry = archive.by_index(idx)?;
+        let mut output = std::fs::File::create(dir.join(entry.sanitized_name()))?;
+        std::io::copy(&mut entry, &mut output)?;
+    }
+
+    // The concat demuxer applies each duration to the file before it, and
+    // ignores the duration of the final file unless it is repeated.
+    let mut list = String::new();
+    for frame in frames {
+        list.push_str(&format!(
+            "file '{}'\nduration {}\n",
+            frame.file,
+            f64::from(frame.delay) / 1000.0
+        ));
+    }
+    if let Some(frame) = frames.last() {
+        list.push_str(&format!("file '{}'\n", frame.file));
+    }
+
+    let list_path = dir.join("frames.txt");
+    std::fs::write(&list_path, list)?;
+
+    let path = format!("videos/{}.mp4", id);
+
+    let output = std::process::Command::new("ffmpeg")
+        .arg("-f")
+        .arg("concat")
+        .arg("-i")
+        .arg(list_path.as_os_str())
+        .arg("-c:v")
+        .arg("libx264")
+        .arg("-pix_fmt")
+        .arg("yuv420p")
+        // libx264 requires even dimensions.
+        .arg("-vf")
+        .arg("pad=ceil(iw/2)*2:ceil(ih/2)*2")
+        .arg("-crf")
+        .arg("26")
+        .arg("-fs")
+        .arg("50M")
+        .arg(&path)
+        .output()
+        .map_err(|_| FfmpegError)?;
+
+    tracing::trace!("finished ffmpeg run {:?}", output);
+
+    if !output.status.success() {
+        let _ = std::fs::remove_file(&path);
+        return Err(FfmpegError.into());
+    }
+
+    Ok(path)
+}
+
+/// Most frames to extract from a file for reverse searching.
+const SEARCH_FRAMES: usize = 3;
+
+/// Extract representative frames from an animation, video, or sticker,
+/// returning each frame as a PNG.
+///
+/// ffmpeg's thumbnail filter picks the most representative frame from each
+/// batch of 100 frames, so short animations produce a single frame. Files
+/// ffmpeg can't decode produce no frames, as retrying won't change that.
+pub fn extract_frames(data: &[u8]) -> failure::Fallible<Vec<Vec<u8>>> {
+    let id = crate::generate_id();
+
+    let dir = WorkDir::create(&id)?;
+
+    let input = dir.join("input");
+    std::fs::write(&input, data)?;
+
+    let output = std::process::Command::new("ffmpeg")
+        .arg("-i")
+        .arg(input.as_os_str())
+        .arg("-vf")
+        .arg("thumbnail")
+        .arg("-frames:v")
+        .arg(SEARCH_FRAMES.to_string())
+        .arg("-vsync")
+        .arg("vfr")
+        .arg(dir.join("%d.png").as_os_str())
+        .output()
+        .map_err(|_| FfmpegError)?;
+
+    tracing::trace!("finished ffmpeg run {:?}", output);
+
+    if !output.status.success() {
+        tracing::debug!("ffmpeg was unable to extract frames");
+        return Ok(vec![]);
+    }
+
+    Ok((1..=SEARCH_FRAMES)
+        .map(|idx| dir.join(format!("{}.png", idx)))
+        .filter_map(|path| std::fs::read(path).ok())
+        .collect())
+}